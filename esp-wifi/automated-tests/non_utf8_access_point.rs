@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use embedded_svc::wifi::{AccessPointConfiguration, Configuration, Wifi};
+
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::initialize;
+use esp_wifi::wifi::WifiApDevice;
+use esp_wifi::EspWifiInitFor;
+use esp_wifi_sys::include::{
+    esp_wifi_get_config, esp_wifi_set_config, wifi_config_t, wifi_interface_t_WIFI_IF_AP,
+};
+use hal::clock::ClockControl;
+use hal::Rng;
+use hal::{peripherals::Peripherals, prelude::*};
+
+/// `0xc3` starts a two byte sequence which `(` doesn't continue
+const SSID: &[u8] = b"esp-wifi-\xc3(";
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::Wifi,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let wifi = peripherals.WIFI;
+    let (_device, mut controller) =
+        esp_wifi::wifi::new_with_mode(&init, wifi, WifiApDevice).unwrap();
+
+    let ap_config = Configuration::AccessPoint(AccessPointConfiguration {
+        ssid: "esp-wifi".try_into().unwrap(),
+        ..Default::default()
+    });
+    let res = controller.set_configuration(&ap_config);
+    println!("wifi_set_configuration returned {:?}", res);
+
+    // The configuration API only takes UTF-8, so the SSID is patched in through the driver
+    unsafe {
+        let mut cfg: wifi_config_t = core::mem::zeroed();
+        assert_eq!(esp_wifi_get_config(wifi_interface_t_WIFI_IF_AP, &mut cfg), 0);
+        cfg.ap.ssid = [0; 32];
+        cfg.ap.ssid[..SSID.len()].copy_from_slice(SSID);
+        cfg.ap.ssid_len = SSID.len() as u8;
+        assert_eq!(esp_wifi_set_config(wifi_interface_t_WIFI_IF_AP, &mut cfg), 0);
+    }
+
+    controller.start().unwrap();
+    println!("is wifi started: {:?}", controller.is_started());
+
+    println!("Start busy loop on main. Beaconing the SSID {:?}", SSID);
+
+    loop {}
+}
@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use embedded_svc::wifi::{AccessPointInfo, AuthMethod, ClientConfiguration, Configuration, Wifi};
+
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::initialize;
+use esp_wifi::wifi::{WifiError, WifiStaDevice};
+use esp_wifi::{current_millis, EspWifiInitFor};
+use hal::clock::ClockControl;
+use hal::Rng;
+use hal::{peripherals::Peripherals, prelude::*};
+
+/// What `non_utf8_access_point` beacons after the invalid sequence was replaced
+const EXPECTED_SSID: &str = "esp-wifi-\u{FFFD}(";
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    println!("Running test");
+
+    #[cfg(not(feature = "esp32"))]
+    println!("[RUN esp32 non_utf8_access_point]");
+
+    #[cfg(feature = "esp32")]
+    println!("[RUN esp32c3 non_utf8_access_point]");
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::Wifi,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let wifi = peripherals.WIFI;
+    let (_device, mut controller) =
+        esp_wifi::wifi::new_with_mode(&init, wifi, WifiStaDevice).unwrap();
+
+    // The driver would silently cut the SSID at the null byte
+    let res = controller.set_configuration(&Configuration::Client(ClientConfiguration {
+        ssid: "esp-\0wifi".try_into().unwrap(),
+        auth_method: AuthMethod::None,
+        ..Default::default()
+    }));
+    println!("wifi_set_configuration with a null byte returned {:?}", res);
+    if res.is_ok() {
+        println!("[FAILED]");
+        loop {}
+    }
+
+    let res = controller.set_configuration(&Configuration::Client(Default::default()));
+    println!("wifi_set_configuration returned {:?}", res);
+
+    controller.start().unwrap();
+    println!("is wifi started: {:?}", controller.is_started());
+
+    let mut tries = 15;
+
+    loop {
+        println!("Start Wifi Scan");
+        let res: Result<(heapless::Vec<AccessPointInfo, 10>, usize), WifiError> =
+            controller.scan_n();
+        if let Ok((res, _count)) = res {
+            for ap in res {
+                println!("{:?}", ap);
+                if ap.ssid == EXPECTED_SSID {
+                    println!("[PASSED]");
+                    loop {}
+                }
+            }
+        }
+        tries -= 1;
+        if tries == 0 {
+            println!("[FAILED]");
+            loop {}
+        }
+
+        let wait_end = current_millis() + 1 * 1000;
+        while current_millis() < wait_end {
+            // wait
+        }
+    }
+}
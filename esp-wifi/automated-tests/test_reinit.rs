@@ -0,0 +1,125 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use embedded_svc::wifi::{AuthMethod, ClientConfiguration, Configuration, Wifi};
+
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::wifi::WifiStaDevice;
+use esp_wifi::{current_millis, deinit, heap_free, initialize, reinitialize, EspWifiConfig};
+use esp_wifi::{EspWifiInitFor, EspWifiInitialization};
+use hal::clock::ClockControl;
+use hal::Rng;
+use hal::{peripherals::Peripherals, prelude::*};
+
+const SSID: &str = "esp-wifi";
+const STATIC_IP: [u8; 4] = [192, 168, 2, 2];
+const CYCLES: usize = 10;
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    println!("Running test");
+
+    // Like `test_connect` this needs the other board to provide the access point
+    #[cfg(not(feature = "esp32"))]
+    println!("[RUN esp32 open_access_point]");
+
+    #[cfg(feature = "esp32")]
+    println!("[RUN esp32c3 open_access_point]");
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::Wifi,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let mut wifi = peripherals.WIFI;
+
+    // The driver is brought down right away, so the baseline doesn't include anything it
+    // allocated and a leak in the first cycle is caught as well
+    let (device, controller) =
+        esp_wifi::wifi::new_with_mode(&init, &mut wifi, WifiStaDevice).unwrap();
+    deinit(init, controller, device).unwrap();
+    let baseline = heap_free();
+    println!("Heap free before the first cycle: {}", baseline);
+
+    for cycle in 0..CYCLES {
+        println!("Cycle {}", cycle);
+
+        let init = reinitialize(EspWifiConfig::default()).unwrap();
+        let rx_bytes = connect_and_transfer(init, &mut wifi, baseline);
+        println!("Received {} bytes", rx_bytes);
+    }
+
+    println!("[PASSED]");
+
+    loop {}
+}
+
+/// Runs one init → connect → transfer → deinit cycle and checks the heap afterwards.
+fn connect_and_transfer(
+    init: EspWifiInitialization,
+    wifi: &mut hal::peripherals::WIFI,
+    baseline: usize,
+) -> u64 {
+    let (device, mut controller) =
+        esp_wifi::wifi::new_with_mode(&init, &mut *wifi, WifiStaDevice).unwrap();
+
+    controller
+        .set_configuration(&Configuration::Client(ClientConfiguration {
+            ssid: SSID.try_into().unwrap(),
+            auth_method: AuthMethod::None,
+            ..Default::default()
+        }))
+        .unwrap();
+    controller.start().unwrap();
+    controller.connect().unwrap();
+
+    wait_for(|| controller.is_connected().unwrap_or(false), 15_000);
+
+    // Announcing our address makes the access point side answer with some traffic
+    controller.send_gratuitous_arp(STATIC_IP).unwrap();
+    wait_for(|| controller.connection_stats().rx_bytes > 0, 5_000);
+    let rx_bytes = controller.connection_stats().rx_bytes;
+
+    deinit(init, controller, device).unwrap();
+
+    let free = heap_free();
+    println!("Heap free after deinit: {}", free);
+    if free != baseline {
+        println!("Heap free should be {}", baseline);
+        println!("[FAILED]");
+        loop {}
+    }
+
+    rx_bytes
+}
+
+fn wait_for(mut condition: impl FnMut() -> bool, timeout_ms: u64) {
+    let wait_end = current_millis() + timeout_ms;
+    while !condition() {
+        if current_millis() > wait_end {
+            println!("[FAILED]");
+            loop {}
+        }
+    }
+}
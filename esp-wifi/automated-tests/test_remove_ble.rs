@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::{add_ble, heap_free, initialize, remove_ble, EspWifiInitFor};
+use hal::clock::ClockControl;
+use hal::Rng;
+use hal::{peripherals::Peripherals, prelude::*};
+
+const CYCLES: usize = 10;
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    println!("Running test");
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let mut init = initialize(
+        EspWifiInitFor::Wifi,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    // Taken before BLE was ever brought up, so the first cycle can't leak unnoticed
+    let baseline = heap_free();
+    println!("Heap free before the first cycle: {}", baseline);
+
+    for cycle in 0..CYCLES {
+        println!("Cycle {}", cycle);
+
+        add_ble(&mut init).unwrap();
+        println!("Heap free with BLE: {}", heap_free());
+
+        remove_ble(&mut init).unwrap();
+        let free = heap_free();
+        println!("Heap free after remove_ble: {}", free);
+
+        if free != baseline {
+            println!("Heap free should be {}", baseline);
+            println!("[FAILED]");
+            loop {}
+        }
+    }
+
+    println!("[PASSED]");
+
+    loop {}
+}
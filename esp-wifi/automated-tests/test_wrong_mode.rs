@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use esp_backtrace as _;
+use embedded_svc::wifi::Wifi;
+use esp_println::println;
+use esp_wifi::initialize;
+use esp_wifi::wifi::{WifiApDevice, WifiError, WifiMode, WifiStaDevice};
+use esp_wifi::EspWifiInitFor;
+use hal::clock::ClockControl;
+use hal::Rng;
+use hal::{peripherals::Peripherals, prelude::*};
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    println!("Running test");
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::Wifi,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let mut wifi = peripherals.WIFI;
+
+    {
+        let (_device, controller) =
+            esp_wifi::wifi::new_with_mode(&init, &mut wifi, WifiStaDevice).unwrap();
+
+        let res = controller.ap_sta_list();
+        println!("ap_sta_list in station mode returned {:?}", res);
+        check_wrong_mode(res.map(|_| ()), WifiMode::Ap, WifiMode::Sta);
+    }
+
+    {
+        let (_device, mut controller) =
+            esp_wifi::wifi::new_with_mode(&init, &mut wifi, WifiApDevice).unwrap();
+
+        let res = controller.abort_connect();
+        println!("abort_connect in access point mode returned {:?}", res);
+        check_wrong_mode(res, WifiMode::Sta, WifiMode::Ap);
+
+        let res = Wifi::connect(&mut controller);
+        println!("connect in access point mode returned {:?}", res);
+        check_wrong_mode(res, WifiMode::Sta, WifiMode::Ap);
+
+        let res = Wifi::disconnect(&mut controller);
+        println!("disconnect in access point mode returned {:?}", res);
+        check_wrong_mode(res, WifiMode::Sta, WifiMode::Ap);
+
+        // the mode is checked before anything is awaited, so the futures complete right away
+        #[cfg(feature = "async")]
+        {
+            let res = embassy_futures::block_on(controller.connect());
+            println!("async connect in access point mode returned {:?}", res);
+            check_wrong_mode(res, WifiMode::Sta, WifiMode::Ap);
+
+            let res = embassy_futures::block_on(controller.disconnect());
+            println!("async disconnect in access point mode returned {:?}", res);
+            check_wrong_mode(res, WifiMode::Sta, WifiMode::Ap);
+        }
+    }
+
+    println!("[PASSED]");
+
+    loop {}
+}
+
+fn check_wrong_mode(res: Result<(), WifiError>, expected: WifiMode, actual: WifiMode) {
+    match res {
+        Err(WifiError::WrongMode {
+            expected: e,
+            actual: a,
+        }) if e == expected && a == actual => {}
+        _ => {
+            println!("[FAILED]");
+            loop {}
+        }
+    }
+}
@@ -40,6 +40,40 @@ fn main() -> Result<(), String> {
         "#
         );
     }
+    #[cfg(any(
+        all(feature = "timer-timg0", feature = "timer-timg1"),
+        all(feature = "timer-timg0", feature = "timer-systimer"),
+        all(feature = "timer-timg1", feature = "timer-systimer"),
+    ))]
+    {
+        panic!(
+            r#"
+
+        Only one of the `timer-timg0`, `timer-timg1` and `timer-systimer` features can be enabled.
+
+        "#
+        );
+    }
+    #[cfg(all(feature = "timer-systimer", feature = "esp32"))]
+    {
+        panic!(
+            r#"
+
+        SYSTIMER is not available on this target.
+
+        "#
+        );
+    }
+    #[cfg(all(feature = "timer-timg1", feature = "esp32c2"))]
+    {
+        panic!(
+            r#"
+
+        TIMG1 is not available on this target.
+
+        "#
+        );
+    }
     match std::env::var("OPT_LEVEL") {
         Ok(level) => {
             if level != "2" && level != "3" {
@@ -74,6 +108,29 @@ fn main() -> Result<(), String> {
     #[cfg(feature = "esp32s3")]
     println!("cargo:rustc-cfg=esp32s3");
 
+    #[cfg(feature = "timer-timg0")]
+    println!("cargo:rustc-cfg=timebase_timg0");
+
+    #[cfg(feature = "timer-timg1")]
+    println!("cargo:rustc-cfg=timebase_timg1");
+
+    #[cfg(feature = "timer-systimer")]
+    println!("cargo:rustc-cfg=timebase_systimer");
+
+    // Without an explicit choice, use the timer esp-wifi has always used on the target
+    #[cfg(not(any(
+        feature = "timer-timg0",
+        feature = "timer-timg1",
+        feature = "timer-systimer"
+    )))]
+    {
+        #[cfg(any(feature = "esp32", feature = "esp32s2", feature = "esp32s3"))]
+        println!("cargo:rustc-cfg=timebase_timg1");
+
+        #[cfg(not(any(feature = "esp32", feature = "esp32s2", feature = "esp32s3")))]
+        println!("cargo:rustc-cfg=timebase_systimer");
+    }
+
     #[cfg(feature = "coex")]
     {
         #[cfg(all(feature = "wifi", feature = "ble"))]
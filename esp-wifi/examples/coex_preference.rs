@@ -0,0 +1,183 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use bleps::{
+    ad_structure::{
+        create_advertising_data, AdStructure, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE,
+    },
+    att::Uuid,
+    Ble, HciConnector,
+};
+
+use esp_wifi::{
+    ble::controller::BleConnector,
+    coex::{self, CoexPreference},
+    current_millis,
+    wifi::WifiStaDevice,
+    wifi_interface::WifiStack,
+    EspWifiInitFor,
+};
+
+use embedded_io::*;
+use embedded_svc::ipv4::Interface;
+use embedded_svc::wifi::{ClientConfiguration, Configuration, Wifi};
+
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::initialize;
+use esp_wifi::wifi::utils::create_network_interface;
+use hal::{clock::ClockControl, Rng};
+use hal::{peripherals::Peripherals, prelude::*};
+use smoltcp::{iface::SocketStorage, wire::IpAddress, wire::Ipv4Address};
+
+const SSID: &str = env!("SSID");
+const PASSWORD: &str = env!("PASSWORD");
+const HOST_IP: &str = env!("HOST_IP");
+
+const TEST_DURATION: usize = 10;
+const RX_BUFFER_SIZE: usize = 16384;
+const TX_BUFFER_SIZE: usize = 1024;
+const IO_BUFFER_SIZE: usize = 1024;
+const DOWNLOAD_PORT: u16 = 4321;
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    let server_address: Ipv4Address = HOST_IP.parse().expect("Invalid HOST_IP address");
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::WifiBle,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let wifi = peripherals.WIFI;
+    let bluetooth = peripherals.BT;
+
+    let mut socket_set_entries: [SocketStorage; 3] = Default::default();
+    let (iface, device, mut controller, sockets) =
+        create_network_interface(&init, wifi, WifiStaDevice, &mut socket_set_entries).unwrap();
+    let wifi_stack = WifiStack::new(iface, device, sockets, current_millis);
+
+    let client_config = Configuration::Client(ClientConfiguration {
+        ssid: SSID.try_into().unwrap(),
+        password: PASSWORD.try_into().unwrap(),
+        ..Default::default()
+    });
+    controller.set_configuration(&client_config).unwrap();
+    controller.start().unwrap();
+    println!("wifi_connect {:?}", controller.connect());
+
+    println!("Wait to get connected");
+    while !controller.is_connected().unwrap() {}
+
+    println!("Wait to get an ip address");
+    loop {
+        wifi_stack.work();
+
+        if wifi_stack.is_iface_up() {
+            println!("got ip {:?}", wifi_stack.get_ip_info());
+            break;
+        }
+    }
+
+    let connector = BleConnector::new(&init, bluetooth);
+    let hci = HciConnector::new(connector, esp_wifi::current_millis);
+    let mut ble = Ble::new(&hci);
+
+    println!("{:?}", ble.init());
+    println!("{:?}", ble.cmd_set_le_advertising_parameters());
+    println!(
+        "{:?}",
+        ble.cmd_set_le_advertising_data(
+            create_advertising_data(&[
+                AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+                AdStructure::ServiceUuids16(&[Uuid::Uuid16(0x1809)]),
+                AdStructure::CompleteLocalName(examples_util::SOC_NAME),
+            ])
+            .unwrap()
+        )
+    );
+    println!("{:?}", ble.cmd_set_le_advertise_enable(true));
+    println!("started advertising, watch the advertising interval with a BLE scanner");
+
+    let mut rx_buffer = [0u8; RX_BUFFER_SIZE];
+    let mut tx_buffer = [0u8; TX_BUFFER_SIZE];
+    let mut socket = wifi_stack.get_socket(&mut rx_buffer, &mut tx_buffer);
+
+    let preferences = [
+        CoexPreference::Balance,
+        CoexPreference::Wifi,
+        CoexPreference::Bt,
+    ];
+
+    loop {
+        for preference in preferences {
+            println!(
+                "coex preference {:?}: {:?}",
+                preference,
+                coex::set_preference(preference)
+            );
+            println!(
+                "download with {:?}: {} kB/s",
+                preference,
+                test_download(server_address, &mut socket)
+            );
+
+            let wait_end = current_millis() + 3 * 1000;
+            while current_millis() < wait_end {
+                socket.work();
+            }
+        }
+    }
+}
+
+fn test_download<'a>(
+    server_address: Ipv4Address,
+    socket: &mut esp_wifi::wifi_interface::Socket<'a, 'a, WifiStaDevice>,
+) -> usize {
+    socket.work();
+
+    socket
+        .open(IpAddress::Ipv4(server_address), DOWNLOAD_PORT)
+        .unwrap();
+
+    let mut buf = [0; IO_BUFFER_SIZE];
+
+    let mut total = 0;
+    let wait_end = current_millis() + (TEST_DURATION as u64 * 1000);
+    loop {
+        socket.work();
+        if let Ok(len) = socket.read(&mut buf) {
+            total += len;
+        } else {
+            break;
+        }
+
+        if current_millis() > wait_end {
+            break;
+        }
+    }
+
+    socket.disconnect();
+
+    (total + 512) / 1024 / TEST_DURATION
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+use embassy_executor::Spawner;
+use embedded_io_async::{Read, Write};
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::{ble::controller::asynch::BleConnector, initialize, EspWifiInitFor};
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+use hal::{clock::ClockControl, embassy, peripherals::*, prelude::*, timer::TimerGroup, Rng};
+
+const HCI_COMMAND: u8 = 0x01;
+const HCI_EVENT: u8 = 0x04;
+
+const HCI_RESET: [u8; 4] = [HCI_COMMAND, 0x03, 0x0c, 0x00];
+// passive scanning, 100 ms interval and window
+const LE_SET_SCAN_PARAMETERS: [u8; 11] = [
+    HCI_COMMAND,
+    0x0b,
+    0x20,
+    0x07,
+    0x00,
+    0xa0,
+    0x00,
+    0xa0,
+    0x00,
+    0x00,
+    0x00,
+];
+const LE_SET_SCAN_ENABLE: [u8; 6] = [HCI_COMMAND, 0x0c, 0x20, 0x02, 0x01, 0x00];
+
+const LE_META_EVENT: u8 = 0x3e;
+const LE_ADVERTISING_REPORT: u8 = 0x02;
+
+#[main]
+async fn main(_spawner: Spawner) -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::Ble,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let timer_group0 = TimerGroup::new(peripherals.TIMG0, &clocks);
+    embassy::init(&clocks, timer_group0);
+
+    let mut bluetooth = peripherals.BT;
+    let mut connector = BleConnector::new(&init, &mut bluetooth);
+
+    // The connector is an H4 transport: a host stack writes whole packets and reads them back
+    // one at a time, a header first and the payload after it
+    for command in [
+        &HCI_RESET[..],
+        &LE_SET_SCAN_PARAMETERS[..],
+        &LE_SET_SCAN_ENABLE[..],
+    ] {
+        connector.write_all(command).await.unwrap();
+
+        let mut buf = [0u8; 255];
+        let (code, payload) = read_event(&mut connector, &mut buf).await;
+        println!(
+            "command {:02x?} answered by event {:02x}: {:02x?}",
+            command, code, payload
+        );
+    }
+
+    println!("scanning");
+    loop {
+        let mut buf = [0u8; 255];
+        let (code, payload) = read_event(&mut connector, &mut buf).await;
+
+        if code == LE_META_EVENT && payload.first() == Some(&LE_ADVERTISING_REPORT) {
+            // the first report: subevent, number of reports, event type, address type, address
+            if payload.len() >= 10 {
+                let mut address = [0u8; 6];
+                address.copy_from_slice(&payload[4..10]);
+                address.reverse();
+                println!("advertisement from {:02x?}", address);
+            }
+        }
+    }
+}
+
+/// Reads the next HCI event, skipping any other packets.
+async fn read_event<'a>(connector: &mut BleConnector<'_>, buf: &'a mut [u8]) -> (u8, &'a [u8]) {
+    loop {
+        let mut header = [0u8; 3];
+        connector.read_exact(&mut header).await.unwrap();
+        let [kind, code, len] = header;
+
+        if kind != HCI_EVENT {
+            println!("unexpected packet type {:02x}", kind);
+            // A read returns the rest of the packet and nothing of the next one
+            connector.read(buf).await.unwrap();
+            continue;
+        }
+
+        let payload = &mut buf[..len as usize];
+        connector.read_exact(payload).await.unwrap();
+        return (code, payload);
+    }
+}
@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+#[path = "../../examples-util/util.rs"]
+mod examples_util;
+use examples_util::hal;
+
+use bleps::{
+    ad_structure::{
+        create_advertising_data, AdStructure, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE,
+    },
+    att::Uuid,
+    Ble, HciConnector,
+};
+
+use esp_backtrace as _;
+use esp_println::println;
+use esp_wifi::ble::controller::BleConnector;
+use esp_wifi::esp_now::{EspNow, PeerInfo, BROADCAST_ADDRESS};
+use esp_wifi::{current_millis, initialize, EspWifiInitFor};
+use hal::clock::ClockControl;
+use hal::Rng;
+use hal::{peripherals::Peripherals, prelude::*};
+
+/// Frames sent per round and way of sending
+const FRAMES: usize = 200;
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "log")]
+    esp_println::logger::init_logger(log::LevelFilter::Info);
+
+    let peripherals = Peripherals::take();
+
+    let system = peripherals.SYSTEM.split();
+    let clocks = ClockControl::max(system.clock_control).freeze();
+
+    #[cfg(target_arch = "xtensa")]
+    let timer = hal::timer::TimerGroup::new(peripherals.TIMG1, &clocks).timer0;
+    #[cfg(target_arch = "riscv32")]
+    let timer = hal::systimer::SystemTimer::new(peripherals.SYSTIMER).alarm0;
+    let init = initialize(
+        EspWifiInitFor::WifiBle,
+        timer,
+        Rng::new(peripherals.RNG),
+        system.radio_clock_control,
+        &clocks,
+    )
+    .unwrap();
+
+    let wifi = peripherals.WIFI;
+    let bluetooth = peripherals.BT;
+    let mut esp_now = EspNow::new(&init, wifi).unwrap();
+
+    let connector = BleConnector::new(&init, bluetooth);
+    let hci = HciConnector::new(connector, current_millis);
+    let mut ble = Ble::new(&hci);
+
+    println!("{:?}", ble.init());
+    println!("{:?}", ble.cmd_set_le_advertising_parameters());
+    println!(
+        "{:?}",
+        ble.cmd_set_le_advertising_data(
+            create_advertising_data(&[
+                AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+                AdStructure::ServiceUuids16(&[Uuid::Uuid16(0x1809)]),
+                AdStructure::CompleteLocalName(examples_util::SOC_NAME),
+            ])
+            .unwrap()
+        )
+    );
+    println!("{:?}", ble.cmd_set_le_advertise_enable(true));
+    println!("started advertising, connect to it with a BLE central to keep Bluetooth busy");
+
+    println!("Waiting for a broadcast of a peer running the `esp_now` example");
+    let peer = loop {
+        if let Some(r) = esp_now.receive() {
+            if r.info.dst_address == BROADCAST_ADDRESS {
+                break r.info.src_address;
+            }
+        }
+    };
+    println!("Found peer {:?}", peer);
+
+    esp_now
+        .add_peer(PeerInfo {
+            peer_address: peer,
+            lmk: None,
+            channel: None,
+            encrypt: false,
+        })
+        .unwrap();
+
+    loop {
+        let mut delivered = 0;
+        for _ in 0..FRAMES {
+            if esp_now.send(&peer, b"0123456789").unwrap().wait().is_ok() {
+                delivered += 1;
+            }
+        }
+        println!("send: {}/{} frames delivered", delivered, FRAMES);
+
+        let mut delivered = 0;
+        for _ in 0..FRAMES {
+            if esp_now
+                .send_with_wifi_priority(&peer, b"0123456789")
+                .is_ok()
+            {
+                delivered += 1;
+            }
+        }
+        println!(
+            "send_with_wifi_priority: {}/{} frames delivered",
+            delivered, FRAMES
+        );
+
+        // Drop the peer's own traffic, it's not part of the measurement
+        while esp_now.receive().is_some() {}
+    }
+}
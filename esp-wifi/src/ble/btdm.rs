@@ -6,6 +6,7 @@ use portable_atomic::{AtomicBool, Ordering};
 use crate::ble::btdm::ble_os_adapter_chip_specific::{osi_funcs_s, G_OSI_FUNCS};
 use crate::ble::HciOutCollector;
 use crate::ble::HCI_OUT_COLLECTOR;
+use crate::compat::blocking_checks;
 use crate::hal::macros::ram;
 use crate::{
     binary::include::*,
@@ -55,6 +56,10 @@ extern "C" {
 
     fn btdm_controller_enable(mode: esp_bt_mode_t);
 
+    fn btdm_controller_disable();
+
+    fn btdm_controller_deinit();
+
     fn API_vhci_host_check_send_available() -> bool;
     fn API_vhci_host_send_packet(data: *const u8, len: u16);
     fn API_vhci_host_register_callback(vhci_host_callbac: *const vhci_host_callback_s) -> i32;
@@ -83,7 +88,7 @@ extern "C" fn notify_host_recv(data: *mut u8, len: u16) -> i32 {
             data: buf,
         };
 
-        critical_section::with(|cs| {
+        blocking_checks::with(|cs| {
             let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
             if queue.enqueue(packet).is_err() {
                 warn!("Dropping BLE packet");
@@ -115,6 +120,7 @@ unsafe extern "C" fn interrupt_enable() {
     INTERRUPT_DISABLE_CNT -= 1;
     let flags = G_INTER_FLAGS[INTERRUPT_DISABLE_CNT];
     trace!("interrupt_enable {}", flags);
+    crate::compat::blocking_checks::critical_section_left();
     critical_section::release(core::mem::transmute(flags));
 }
 
@@ -124,6 +130,7 @@ unsafe extern "C" fn interrupt_disable() {
     let flags = core::mem::transmute(critical_section::acquire());
     G_INTER_FLAGS[INTERRUPT_DISABLE_CNT] = flags;
     INTERRUPT_DISABLE_CNT += 1;
+    crate::compat::blocking_checks::critical_section_entered();
     trace!("interrupt_disable {}", flags);
 }
 
@@ -145,7 +152,14 @@ unsafe extern "C" fn semphr_delete(sem: *const ()) {
 }
 
 unsafe extern "C" fn semphr_take(sem: *const (), block_time_ms: u32) -> i32 {
-    crate::common_adapter::semphr_take(sem as *mut crate::binary::c_types::c_void, block_time_ms)
+    // The controller passes milliseconds while `semphr_take` waits for system timer ticks
+    let block_time_tick = if block_time_ms == OSI_FUNCS_TIME_BLOCKING {
+        OSI_FUNCS_TIME_BLOCKING
+    } else {
+        crate::timer::millis_to_ticks(block_time_ms as u64).min(u32::MAX as u64 - 1) as u32
+    };
+
+    crate::common_adapter::semphr_take(sem as *mut crate::binary::c_types::c_void, block_time_tick)
 }
 
 unsafe extern "C" fn semphr_give(sem: *const ()) -> i32 {
@@ -182,7 +196,7 @@ unsafe extern "C" fn queue_delete(queue: *const ()) {
 #[ram]
 unsafe extern "C" fn queue_send(queue: *const (), item: *const (), _block_time_ms: u32) -> i32 {
     if queue == &BT_INTERNAL_QUEUE as *const _ as *const () {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             // assume the size is 8 - shouldn't rely on that
             let message = item as *const u8;
             let mut data = [0u8; 8];
@@ -191,7 +205,7 @@ unsafe extern "C" fn queue_send(queue: *const (), item: *const (), _block_time_m
             }
             trace!("queue posting {:?}", data);
 
-            critical_section::with(|cs| {
+            blocking_checks::with(|cs| {
                 let mut queue = BT_INTERNAL_QUEUE.borrow_ref_mut(cs);
                 unwrap!(queue.enqueue(data));
             });
@@ -223,6 +237,10 @@ unsafe extern "C" fn queue_recv(queue: *const (), item: *const (), block_time_ms
         block_time_ms
     );
 
+    if block_time_ms != 0 {
+        crate::compat::blocking_checks::assert_may_block("queue_recv");
+    }
+
     let forever = block_time_ms == OSI_FUNCS_TIME_BLOCKING;
     let start = crate::timer::get_systimer_count();
     let block_ticks = crate::timer::millis_to_ticks(block_time_ms as u64);
@@ -230,10 +248,10 @@ unsafe extern "C" fn queue_recv(queue: *const (), item: *const (), block_time_ms
     // handle the BT_QUEUE
     if queue == &BT_INTERNAL_QUEUE as *const _ as *const () {
         loop {
-            let res = critical_section::with(|_| {
+            let res = blocking_checks::with(|_| {
                 memory_fence();
 
-                critical_section::with(|cs| {
+                blocking_checks::with(|cs| {
                     let mut queue = BT_INTERNAL_QUEUE.borrow_ref_mut(cs);
                     if let Some(message) = queue.dequeue() {
                         let item = item as *mut u8;
@@ -296,7 +314,8 @@ unsafe extern "C" fn task_create(
         core_id
     );
 
-    *(handle as *mut usize) = 0; // we will run it in task 0
+    // the task function identifies the task in `task_delete`
+    *(handle as *mut usize) = func as usize;
 
     if spawn_task(
         func,
@@ -313,8 +332,14 @@ unsafe extern "C" fn task_create(
     }
 }
 
-unsafe extern "C" fn task_delete(_task: *const ()) {
-    todo!();
+unsafe extern "C" fn task_delete(task: *const ()) {
+    trace!("task_delete {:?}", task);
+
+    if task.is_null() {
+        crate::preempt::restart_current_task();
+    }
+
+    crate::compat::task_runner::delete_task(task as usize);
 }
 
 #[ram]
@@ -511,13 +536,38 @@ pub(crate) fn ble_init() {
     }
 }
 
+#[cfg(coex)]
+pub(crate) fn ble_deinit() -> Result<(), i32> {
+    unsafe {
+        btdm_controller_disable();
+
+        coex_disable();
+
+        btdm_controller_deinit();
+
+        crate::common_adapter::chip_specific::phy_disable();
+        ble_os_adapter_chip_specific::bt_periph_module_disable();
+
+        // Drop HCI packets the host didn't read, they belong to the old controller
+        blocking_checks::with(|cs| {
+            while BT_RECEIVE_QUEUE.borrow_ref_mut(cs).dequeue().is_some() {}
+            while BT_INTERNAL_QUEUE.borrow_ref_mut(cs).dequeue().is_some() {}
+        });
+        BLE_HCI_READ_DATA_LEN = 0;
+        BLE_HCI_READ_DATA_INDEX = 0;
+        PACKET_SENT.store(true, Ordering::Relaxed);
+    }
+
+    Ok(())
+}
+
 static mut BLE_HCI_READ_DATA: [u8; 256] = [0u8; 256];
 static mut BLE_HCI_READ_DATA_INDEX: usize = 0;
 static mut BLE_HCI_READ_DATA_LEN: usize = 0;
 
 #[cfg(feature = "async")]
 pub fn have_hci_read_data() -> bool {
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
         !queue.is_empty()
             || unsafe {
@@ -527,7 +577,7 @@ pub fn have_hci_read_data() -> bool {
 }
 
 pub(crate) fn read_next(data: &mut [u8]) -> usize {
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
 
         match queue.dequeue() {
@@ -540,21 +590,48 @@ pub(crate) fn read_next(data: &mut [u8]) -> usize {
     })
 }
 
-pub fn read_hci(data: &mut [u8]) -> usize {
+/// Moves the next received packet into the read buffer once the current one is read.
+unsafe fn fill_hci_read_data() {
+    if BLE_HCI_READ_DATA_LEN == 0 {
+        blocking_checks::with(|cs| {
+            let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
+
+            if let Some(packet) = queue.dequeue() {
+                BLE_HCI_READ_DATA[..packet.len as usize]
+                    .copy_from_slice(&packet.data[..packet.len as usize]);
+                BLE_HCI_READ_DATA_LEN = packet.len as usize;
+                BLE_HCI_READ_DATA_INDEX = 0;
+            }
+        });
+    }
+}
+
+/// Reads the rest of the current packet, at most `data.len()` bytes.
+///
+/// Never returns bytes of two packets, what doesn't fit into `data` is returned by the next
+/// call.
+#[cfg(feature = "async")]
+pub(crate) fn read_hci_packet(data: &mut [u8]) -> usize {
     unsafe {
-        if BLE_HCI_READ_DATA_LEN == 0 {
-            critical_section::with(|cs| {
-                let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
-
-                if let Some(packet) = queue.dequeue() {
-                    BLE_HCI_READ_DATA[..packet.len as usize]
-                        .copy_from_slice(&packet.data[..packet.len as usize]);
-                    BLE_HCI_READ_DATA_LEN = packet.len as usize;
-                    BLE_HCI_READ_DATA_INDEX = 0;
-                }
-            });
+        fill_hci_read_data();
+
+        let len = usize::min(BLE_HCI_READ_DATA_LEN - BLE_HCI_READ_DATA_INDEX, data.len());
+        data[..len].copy_from_slice(&BLE_HCI_READ_DATA[BLE_HCI_READ_DATA_INDEX..][..len]);
+        BLE_HCI_READ_DATA_INDEX += len;
+
+        if BLE_HCI_READ_DATA_INDEX >= BLE_HCI_READ_DATA_LEN {
+            BLE_HCI_READ_DATA_LEN = 0;
+            BLE_HCI_READ_DATA_INDEX = 0;
         }
 
+        len
+    }
+}
+
+pub fn read_hci(data: &mut [u8]) -> usize {
+    unsafe {
+        fill_hci_read_data();
+
         if BLE_HCI_READ_DATA_LEN > 0 {
             data[0] = BLE_HCI_READ_DATA[BLE_HCI_READ_DATA_INDEX];
             BLE_HCI_READ_DATA_INDEX += 1;
@@ -608,7 +685,7 @@ fn dump_packet_info(buffer: &[u8]) {
     #[cfg(not(feature = "dump-packets"))]
     return;
 
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         info!("@HCIFRAME {:?}", buffer);
     });
 }
@@ -30,6 +30,7 @@ impl<'d> BleConnector<'d> {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BleConnectorError {
     Unknown,
 }
@@ -78,6 +79,12 @@ impl Write for BleConnector<'_> {
 }
 
 /// Async Interface
+///
+/// The async [`BleConnector`](asynch::BleConnector) is an HCI transport in the format of the
+/// UART transport (H4): every packet starts with its packet type. Reads never return bytes of
+/// two packets, a packet which doesn't fit into the buffer is returned over several reads. This
+/// makes it usable for host stacks reading one packet at a time, e.g. with `read_exact` of the
+/// header followed by the payload.
 #[cfg(feature = "async")]
 pub mod asynch {
     use core::task::Poll;
@@ -86,7 +93,7 @@ pub mod asynch {
     use crate::EspWifiInitialization;
 
     use super::BleConnectorError;
-    use super::{read_hci, send_hci};
+    use crate::ble::{read_hci_packet, send_hci};
     use crate::hal::peripheral::{Peripheral, PeripheralRef};
     use embassy_sync::waitqueue::AtomicWaker;
     use embedded_io::ErrorType;
@@ -115,35 +122,43 @@ pub mod asynch {
                 _device: device.into_ref(),
             }
         }
+
+        /// Waits until the controller sent data to the host, without reading it.
+        pub async fn wait_for_data(&mut self) {
+            HciReadyEventFuture.await
+        }
     }
 
     impl ErrorType for BleConnector<'_> {
         type Error = BleConnectorError;
     }
 
+    impl embedded_io::ReadReady for BleConnector<'_> {
+        fn read_ready(&mut self) -> Result<bool, BleConnectorError> {
+            Ok(have_hci_read_data())
+        }
+    }
+
     impl embedded_io_async::Read for BleConnector<'_> {
+        /// Reads the rest of the current HCI packet or waits for the next one.
         async fn read(&mut self, buf: &mut [u8]) -> Result<usize, BleConnectorError> {
-            if !have_hci_read_data() {
-                HciReadyEventFuture.await;
+            if buf.is_empty() {
+                return Ok(0);
             }
 
-            let mut total = 0;
-            for b in buf {
-                let mut buffer = [0u8];
-                let len = read_hci(&mut buffer);
-
-                if len == 1 {
-                    *b = buffer[0];
-                    total += 1;
-                } else {
-                    return Ok(total);
+            loop {
+                let len = read_hci_packet(buf);
+                if len > 0 {
+                    return Ok(len);
                 }
-            }
 
-            Ok(total)
+                HciReadyEventFuture.await;
+            }
         }
     }
 
+    /// Writes are collected until a packet is complete, which is then passed to the controller.
+    /// A write must not contain the end of one packet and the start of the next.
     impl embedded_io_async::Write for BleConnector<'_> {
         async fn write(&mut self, buf: &[u8]) -> Result<usize, BleConnectorError> {
             send_hci(buf);
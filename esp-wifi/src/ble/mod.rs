@@ -8,18 +8,39 @@ pub(crate) mod npl;
 
 use core::mem::MaybeUninit;
 
+use portable_atomic::{AtomicBool, Ordering};
+
 #[cfg(any(esp32, esp32c3, esp32s3))]
 use self::btdm as ble;
 
 #[cfg(any(esp32c2, esp32c6, esp32h2))]
 use self::npl as ble;
 
-pub(crate) use ble::ble_init;
 pub(crate) use ble::read_hci;
+#[cfg(feature = "async")]
+pub(crate) use ble::read_hci_packet;
 pub(crate) use ble::read_next;
 pub(crate) use ble::send_hci;
 
 pub mod controller;
+pub mod tx_power;
+
+/// Set once the BLE controller is initialized and enabled.
+pub(crate) static BLE_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn ble_init() {
+    ble::ble_init();
+    BLE_INITIALIZED.store(true, Ordering::Relaxed);
+}
+
+/// Stops and deinitializes the BLE controller, returning the driver's error code on failure.
+#[cfg(coex)]
+pub(crate) fn ble_deinit() -> Result<(), i32> {
+    ble::ble_deinit()?;
+    BLE_INITIALIZED.store(false, Ordering::Relaxed);
+
+    Ok(())
+}
 
 pub(crate) unsafe extern "C" fn malloc(size: u32) -> *mut crate::binary::c_types::c_void {
     crate::compat::malloc::malloc(size as usize).cast()
@@ -9,6 +9,7 @@ use super::*;
 use crate::binary::c_types::{c_char, c_void};
 use crate::binary::include::*;
 use crate::compat;
+use crate::compat::blocking_checks;
 use crate::compat::common::str_from_c;
 use crate::compat::queue::SimpleQueue;
 use crate::compat::task_runner::spawn_task;
@@ -237,10 +238,18 @@ extern "C" {
 
     pub(crate) fn ble_controller_enable(mode: u8) -> i32;
 
+    pub(crate) fn ble_controller_disable() -> i32;
+
+    pub(crate) fn ble_controller_deinit() -> i32;
+
     pub(crate) fn esp_register_ext_funcs(funcs: *const ext_funcs_t) -> i32;
 
     pub(crate) fn esp_register_npl_funcs(funcs: *const npl_funcs_t) -> i32;
 
+    pub(crate) fn esp_unregister_ext_funcs();
+
+    pub(crate) fn esp_unregister_npl_funcs();
+
     pub(crate) fn ble_get_npl_element_info(
         cfg: *const esp_bt_controller_config_t,
         npl_info: *const ble_npl_count_info_t,
@@ -392,7 +401,8 @@ unsafe extern "C" fn task_create(
         core_id,
     );
 
-    *(task_handle as *mut usize) = 0; // we will run it in task 0
+    // the task function identifies the task in `task_delete`
+    *(task_handle as *mut usize) = task_func as usize;
 
     if spawn_task(
         task_func as *mut c_void,
@@ -409,8 +419,14 @@ unsafe extern "C" fn task_create(
     }
 }
 
-unsafe extern "C" fn task_delete(_: *const c_void) {
-    todo!();
+unsafe extern "C" fn task_delete(task: *const c_void) {
+    trace!("task_delete {:?}", task);
+
+    if task.is_null() {
+        crate::preempt::restart_current_task();
+    }
+
+    crate::compat::task_runner::delete_task(task as usize);
 }
 
 unsafe extern "C" fn osi_assert(ln: u32, fn_name: *const c_void, param1: u32, param2: u32) {
@@ -610,12 +626,14 @@ unsafe extern "C" fn ble_npl_get_time_forever() -> u32 {
 
 unsafe extern "C" fn ble_npl_hw_exit_critical(mask: u32) {
     trace!("ble_npl_hw_exit_critical {}", mask);
+    crate::compat::blocking_checks::critical_section_left();
     critical_section::release(core::mem::transmute(mask as u8));
 }
 
 unsafe extern "C" fn ble_npl_hw_enter_critical() -> u32 {
     trace!("ble_npl_hw_enter_critical");
     let as_u8: u8 = core::mem::transmute(critical_section::acquire());
+    crate::compat::blocking_checks::critical_section_entered();
     as_u8 as u32
 }
 
@@ -687,6 +705,12 @@ unsafe extern "C" fn ble_npl_callout_deinit(callout: *const ble_npl_callout) {
     trace!("ble_npl_callout_deinit");
 
     ble_npl_callout_stop(callout);
+
+    let idx = ((*callout).dummy - 1) as usize;
+    if let Some(mut co) = CALLOUTS[idx].take() {
+        ble_npl_event_deinit(addr_of_mut!(co.events));
+    }
+    (*callout.cast_mut()).dummy = 0;
 }
 
 unsafe extern "C" fn ble_npl_callout_stop(callout: *const ble_npl_callout) {
@@ -796,8 +820,14 @@ unsafe extern "C" fn ble_npl_event_reset(event: *const ble_npl_event) {
     }
 }
 
-unsafe extern "C" fn ble_npl_event_deinit(_event: *const ble_npl_event) {
-    todo!()
+unsafe extern "C" fn ble_npl_event_deinit(event: *const ble_npl_event) {
+    trace!("ble_npl_event_deinit {:?}", event);
+
+    let event = event.cast_mut();
+    if (*event).dummy != 0 {
+        EVENTS[((*event).dummy - 1) as usize] = None;
+        (*event).dummy = 0;
+    }
 }
 
 unsafe extern "C" fn ble_npl_event_init(
@@ -828,7 +858,7 @@ unsafe extern "C" fn ble_npl_eventq_is_empty(queue: *const ble_npl_eventq) -> bo
         panic!("Try to use uninitialized queue");
     }
 
-    critical_section::with(|_| EVENT_QUEUE.is_empty())
+    blocking_checks::with(|_| EVENT_QUEUE.is_empty())
 }
 
 unsafe extern "C" fn ble_npl_event_run(event: *const ble_npl_event) {
@@ -861,7 +891,7 @@ unsafe extern "C" fn ble_npl_eventq_remove(
         panic!("Try to use uninitialized event");
     }
 
-    critical_section::with(|_| {
+    blocking_checks::with(|_| {
         unwrap!(EVENTS[((*event).dummy - 1) as usize].as_mut()).queued = false;
     });
 }
@@ -877,7 +907,7 @@ unsafe extern "C" fn ble_npl_eventq_put(queue: *const ble_npl_eventq, event: *co
         panic!("Try to use uninitialized event");
     }
 
-    critical_section::with(|_| {
+    blocking_checks::with(|_| {
         unwrap!(EVENTS[((*event).dummy - 1) as usize].as_mut()).queued = true;
         unwrap!(EVENT_QUEUE.enqueue((*event).dummy as usize));
     });
@@ -891,7 +921,7 @@ unsafe extern "C" fn ble_npl_eventq_get(
 
     if time == TIME_FOREVER {
         loop {
-            let dequeued = critical_section::with(|_| EVENT_QUEUE.dequeue());
+            let dequeued = blocking_checks::with(|_| EVENT_QUEUE.dequeue());
 
             if let Some(event_idx) = dequeued {
                 let evt = unwrap!(EVENTS[event_idx - 1].as_mut());
@@ -909,8 +939,13 @@ unsafe extern "C" fn ble_npl_eventq_get(
     }
 }
 
-unsafe extern "C" fn ble_npl_eventq_deinit(_queue: *const ble_npl_eventq) {
-    todo!()
+unsafe extern "C" fn ble_npl_eventq_deinit(queue: *const ble_npl_eventq) {
+    trace!("ble_npl_eventq_deinit {:?}", queue);
+
+    blocking_checks::with(|_| {
+        while EVENT_QUEUE.dequeue().is_some() {}
+        (*queue.cast_mut()).dummy = 0;
+    });
 }
 
 unsafe extern "C" fn ble_npl_callout_init(
@@ -972,7 +1007,7 @@ unsafe extern "C" fn callout_timer_callback_wrapper(arg: *mut c_void) {
 unsafe extern "C" fn ble_npl_eventq_init(queue: *const ble_npl_eventq) {
     trace!("ble_npl_eventq_init {:?}", queue);
 
-    critical_section::with(|_cs| {
+    blocking_checks::with(|_cs| {
         let queue = queue as *mut ble_npl_eventq;
 
         if (*queue).dummy == 0 {
@@ -1154,6 +1189,63 @@ pub(crate) fn ble_init() {
     }
 }
 
+#[cfg(coex)]
+pub(crate) fn ble_deinit() -> Result<(), i32> {
+    unsafe {
+        let res = ble_controller_disable();
+        if res != 0 {
+            warn!("ble_controller_disable returned {}", res);
+            return Err(res);
+        }
+
+        #[cfg(coex)]
+        crate::binary::include::coex_disable();
+
+        let res = ble_controller_deinit();
+        if res != 0 {
+            warn!("ble_controller_deinit returned {}", res);
+            return Err(res);
+        }
+
+        #[cfg(not(esp32c2))]
+        {
+            extern "C" {
+                fn esp_ble_msys_deinit();
+            }
+
+            esp_ble_msys_deinit();
+        }
+
+        #[cfg(esp32c2)]
+        {
+            crate::compat::malloc::free(OS_MSYS_INIT_1_DATA.cast());
+            crate::compat::malloc::free(OS_MSYS_INIT_2_DATA.cast());
+            OS_MSYS_INIT_1_DATA = core::ptr::null_mut();
+            OS_MSYS_INIT_2_DATA = core::ptr::null_mut();
+        }
+
+        esp_unregister_npl_funcs();
+        esp_unregister_ext_funcs();
+
+        crate::common_adapter::chip_specific::phy_disable();
+        ble_os_adapter_chip_specific::bt_periph_module_disable();
+
+        // Release what the controller didn't deinitialize itself, the next initialization
+        // starts with empty tables
+        for co in CALLOUTS.iter_mut().flatten() {
+            compat::timer_compat::compat_timer_disarm(addr_of_mut!(co.timer_handle));
+        }
+        CALLOUTS = [None; 18];
+        EVENTS = [None; 95];
+        blocking_checks::with(|cs| {
+            while EVENT_QUEUE.dequeue().is_some() {}
+            while BT_RECEIVE_QUEUE.borrow_ref_mut(cs).dequeue().is_some() {}
+        });
+    }
+
+    Ok(())
+}
+
 #[cfg(esp32c2)]
 fn os_msys_buf_alloc() -> bool {
     unsafe {
@@ -1224,7 +1316,7 @@ unsafe extern "C" fn ble_hs_hci_rx_evt(cmd: *const u8, arg: *const c_void) {
     let payload = core::slice::from_raw_parts(cmd.offset(2), len);
     debug!("$ pld = {:?}", payload);
 
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
         let mut data = [0u8; 256];
 
@@ -1259,7 +1351,7 @@ unsafe extern "C" fn ble_hs_rx_data(om: *const OsMbuf, arg: *const c_void) {
     let len = (*om).om_len;
     let data_slice = core::slice::from_raw_parts(data_ptr, len as usize);
 
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
         let mut data = [0u8; 256];
 
@@ -1291,7 +1383,7 @@ static mut BLE_HCI_READ_DATA_LEN: usize = 0;
 
 #[cfg(feature = "async")]
 pub fn have_hci_read_data() -> bool {
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
         !queue.is_empty()
             || unsafe {
@@ -1301,7 +1393,7 @@ pub fn have_hci_read_data() -> bool {
 }
 
 pub(crate) fn read_next(data: &mut [u8]) -> usize {
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
 
         match queue.dequeue() {
@@ -1314,21 +1406,48 @@ pub(crate) fn read_next(data: &mut [u8]) -> usize {
     })
 }
 
-pub fn read_hci(data: &mut [u8]) -> usize {
+/// Moves the next received packet into the read buffer once the current one is read.
+unsafe fn fill_hci_read_data() {
+    if BLE_HCI_READ_DATA_LEN == 0 {
+        blocking_checks::with(|cs| {
+            let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
+
+            if let Some(packet) = queue.dequeue() {
+                BLE_HCI_READ_DATA[..packet.len as usize]
+                    .copy_from_slice(&packet.data[..packet.len as usize]);
+                BLE_HCI_READ_DATA_LEN = packet.len as usize;
+                BLE_HCI_READ_DATA_INDEX = 0;
+            }
+        });
+    }
+}
+
+/// Reads the rest of the current packet, at most `data.len()` bytes.
+///
+/// Never returns bytes of two packets, what doesn't fit into `data` is returned by the next
+/// call.
+#[cfg(feature = "async")]
+pub(crate) fn read_hci_packet(data: &mut [u8]) -> usize {
     unsafe {
-        if BLE_HCI_READ_DATA_LEN == 0 {
-            critical_section::with(|cs| {
-                let mut queue = BT_RECEIVE_QUEUE.borrow_ref_mut(cs);
-
-                if let Some(packet) = queue.dequeue() {
-                    BLE_HCI_READ_DATA[..packet.len as usize]
-                        .copy_from_slice(&packet.data[..packet.len as usize]);
-                    BLE_HCI_READ_DATA_LEN = packet.len as usize;
-                    BLE_HCI_READ_DATA_INDEX = 0;
-                }
-            });
+        fill_hci_read_data();
+
+        let len = usize::min(BLE_HCI_READ_DATA_LEN - BLE_HCI_READ_DATA_INDEX, data.len());
+        data[..len].copy_from_slice(&BLE_HCI_READ_DATA[BLE_HCI_READ_DATA_INDEX..][..len]);
+        BLE_HCI_READ_DATA_INDEX += len;
+
+        if BLE_HCI_READ_DATA_INDEX >= BLE_HCI_READ_DATA_LEN {
+            BLE_HCI_READ_DATA_LEN = 0;
+            BLE_HCI_READ_DATA_INDEX = 0;
         }
 
+        len
+    }
+}
+
+pub fn read_hci(data: &mut [u8]) -> usize {
+    unsafe {
+        fill_hci_read_data();
+
         if BLE_HCI_READ_DATA_LEN > 0 {
             data[0] = BLE_HCI_READ_DATA[BLE_HCI_READ_DATA_INDEX];
             BLE_HCI_READ_DATA_INDEX += 1;
@@ -1358,7 +1477,7 @@ pub fn send_hci(data: &[u8]) {
 
                 dump_packet_info(&packet);
 
-                critical_section::with(|_cs| {
+                blocking_checks::with(|_cs| {
                     if packet[0] == DATA_TYPE_COMMAND {
                         let cmd = r_ble_hci_trans_buf_alloc(BLE_HCI_TRANS_BUF_CMD);
                         core::ptr::copy_nonoverlapping(
@@ -1410,7 +1529,7 @@ fn dump_packet_info(buffer: &[u8]) {
     #[cfg(not(feature = "dump-packets"))]
     return;
 
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         info!("@HCIFRAME {:?}", buffer);
     });
 }
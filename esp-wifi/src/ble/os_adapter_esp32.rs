@@ -339,6 +339,11 @@ pub(crate) fn bt_periph_module_enable() {
     // nothing
 }
 
+#[cfg(coex)]
+pub(crate) fn bt_periph_module_disable() {
+    // nothing
+}
+
 pub(crate) fn disable_sleep_mode() {
     extern "C" {
         fn btdm_controller_set_sleep_mode(mode: u8);
@@ -77,6 +77,13 @@ pub(crate) fn bt_periph_module_enable() {
     }
 }
 
+#[cfg(coex)]
+pub(crate) fn bt_periph_module_disable() {
+    unsafe {
+        unwrap!(RADIO_CLOCKS.as_mut()).disable(RadioPeripherals::Bt);
+    }
+}
+
 pub(crate) fn disable_sleep_mode() {
     // nothing
 }
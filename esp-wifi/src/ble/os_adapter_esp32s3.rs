@@ -218,6 +218,11 @@ pub(crate) fn bt_periph_module_enable() {
     // nothing
 }
 
+#[cfg(coex)]
+pub(crate) fn bt_periph_module_disable() {
+    // nothing
+}
+
 pub(crate) fn disable_sleep_mode() {
     // nothing
 }
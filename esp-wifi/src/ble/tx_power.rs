@@ -0,0 +1,298 @@
+//! Transmit power of the BLE controller.
+//!
+//! The controller knows a fixed set of power levels which differs between chip families,
+//! [`TxPowerLevel`] only has the levels of the chip esp-wifi is built for. The power can be set
+//! separately for advertising, scanning and each connection, see [`TxPowerRole`] for what the
+//! chips support.
+//!
+//! ```ignore
+//! // deliberately weak advertisements for proximity based pairing
+//! tx_power::set(TxPowerRole::Advertising, TxPowerLevel::N12)?;
+//! ```
+
+use portable_atomic::Ordering;
+
+/// What the transmit power is set for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxPowerRole {
+    /// Everything which has no power of its own
+    Default,
+    /// Advertising
+    Advertising,
+    /// Scanning
+    ///
+    /// On ESP32-C2, ESP32-C6 and ESP32-H2 the controller has no separate power for scanning,
+    /// this sets and gets the default power.
+    Scan,
+    /// An established connection
+    ///
+    /// On ESP32, ESP32-C3 and ESP32-S3 this is the index of the connection in the controller,
+    /// 0 to 8. On ESP32-C2, ESP32-C6 and ESP32-H2 it's the HCI connection handle.
+    Connection(u16),
+}
+
+/// Transmit power levels of the BLE controller, named after their power in dBm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxPowerLevel {
+    /// -24 dBm
+    #[cfg(not(any(esp32, esp32c6)))]
+    N24,
+    /// -21 dBm
+    #[cfg(not(any(esp32, esp32c6)))]
+    N21,
+    /// -18 dBm
+    #[cfg(not(any(esp32, esp32c6)))]
+    N18,
+    /// -15 dBm
+    #[cfg(not(esp32))]
+    N15,
+    /// -12 dBm
+    N12,
+    /// -9 dBm
+    N9,
+    /// -6 dBm
+    N6,
+    /// -3 dBm
+    N3,
+    /// 0 dBm
+    N0,
+    /// +3 dBm
+    P3,
+    /// +6 dBm
+    P6,
+    /// +9 dBm
+    P9,
+    /// +12 dBm
+    #[cfg(not(esp32))]
+    P12,
+    /// +15 dBm
+    #[cfg(not(esp32))]
+    P15,
+    /// +18 dBm
+    #[cfg(not(esp32))]
+    P18,
+    /// +20 dBm
+    #[cfg(any(esp32c2, esp32c6, esp32h2))]
+    P20,
+    /// +21 dBm
+    #[cfg(any(esp32c3, esp32s3))]
+    P21,
+}
+
+/// The levels of the chip with their power in dBm, ordered by the controller's index.
+const LEVELS: &[(TxPowerLevel, i8)] = &[
+    #[cfg(not(any(esp32, esp32c6)))]
+    (TxPowerLevel::N24, -24),
+    #[cfg(not(any(esp32, esp32c6)))]
+    (TxPowerLevel::N21, -21),
+    #[cfg(not(any(esp32, esp32c6)))]
+    (TxPowerLevel::N18, -18),
+    #[cfg(not(esp32))]
+    (TxPowerLevel::N15, -15),
+    (TxPowerLevel::N12, -12),
+    (TxPowerLevel::N9, -9),
+    (TxPowerLevel::N6, -6),
+    (TxPowerLevel::N3, -3),
+    (TxPowerLevel::N0, 0),
+    (TxPowerLevel::P3, 3),
+    (TxPowerLevel::P6, 6),
+    (TxPowerLevel::P9, 9),
+    #[cfg(not(esp32))]
+    (TxPowerLevel::P12, 12),
+    #[cfg(not(esp32))]
+    (TxPowerLevel::P15, 15),
+    #[cfg(not(esp32))]
+    (TxPowerLevel::P18, 18),
+    #[cfg(any(esp32c2, esp32c6, esp32h2))]
+    (TxPowerLevel::P20, 20),
+    #[cfg(any(esp32c3, esp32s3))]
+    (TxPowerLevel::P21, 21),
+];
+
+/// Controller index of the first entry in [`LEVELS`], the ESP32-C6 doesn't support the lowest
+/// three levels of the other chips.
+#[cfg(esp32c6)]
+const FIRST_INDEX: i32 = 3;
+#[cfg(not(esp32c6))]
+const FIRST_INDEX: i32 = 0;
+
+impl TxPowerLevel {
+    /// Returns the transmit power in dBm.
+    pub fn dbm(&self) -> i8 {
+        unwrap!(LEVELS.iter().find(|(level, _)| level == self)).1
+    }
+
+    /// Returns the level with exactly the given power, see [`TxPowerLevel::at_most`] for the
+    /// closest one.
+    pub fn from_dbm(dbm: i8) -> Result<Self, TxPowerError> {
+        LEVELS
+            .iter()
+            .find(|(_, level_dbm)| *level_dbm == dbm)
+            .map(|(level, _)| *level)
+            .ok_or(TxPowerError::UnsupportedLevel { dbm })
+    }
+
+    /// Returns the strongest level which doesn't exceed `dbm`, e.g. to stay within a power
+    /// budget.
+    pub fn at_most(dbm: i8) -> Result<Self, TxPowerError> {
+        LEVELS
+            .iter()
+            .rev()
+            .find(|(_, level_dbm)| *level_dbm <= dbm)
+            .map(|(level, _)| *level)
+            .ok_or(TxPowerError::UnsupportedLevel { dbm })
+    }
+
+    /// Returns the lowest level of the chip.
+    pub fn min() -> Self {
+        LEVELS[0].0
+    }
+
+    /// Returns the highest level of the chip.
+    pub fn max() -> Self {
+        LEVELS[LEVELS.len() - 1].0
+    }
+
+    fn index(&self) -> i32 {
+        FIRST_INDEX + unwrap!(LEVELS.iter().position(|(level, _)| level == self)) as i32
+    }
+
+    fn from_index(index: i32) -> Option<Self> {
+        usize::try_from(index - FIRST_INDEX)
+            .ok()
+            .and_then(|i| LEVELS.get(i))
+            .map(|(level, _)| *level)
+    }
+}
+
+/// Error returned when setting or getting the transmit power.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TxPowerError {
+    /// The BLE controller isn't initialized
+    NotInitialized,
+    /// The chip has no transmit power level of `dbm`
+    UnsupportedLevel { dbm: i8 },
+    /// The connection doesn't exist or isn't supported by the controller
+    InvalidConnection(u16),
+    /// The controller rejected the request with the given code
+    Controller(i32),
+}
+
+#[cfg(any(esp32, esp32c3, esp32s3))]
+mod raw {
+    use super::*;
+    use crate::binary::include::*;
+
+    extern "C" {
+        fn ble_txpwr_set(power_type: esp_ble_power_type_t, power_level: i32) -> i32;
+        fn ble_txpwr_get(power_type: esp_ble_power_type_t) -> i32;
+    }
+
+    fn power_type(role: TxPowerRole) -> Result<esp_ble_power_type_t, TxPowerError> {
+        Ok(match role {
+            TxPowerRole::Default => esp_ble_power_type_t_ESP_BLE_PWR_TYPE_DEFAULT,
+            TxPowerRole::Advertising => esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV,
+            TxPowerRole::Scan => esp_ble_power_type_t_ESP_BLE_PWR_TYPE_SCAN,
+            TxPowerRole::Connection(index @ 0..=8) => {
+                esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL0 + index as esp_ble_power_type_t
+            }
+            TxPowerRole::Connection(index) => return Err(TxPowerError::InvalidConnection(index)),
+        })
+    }
+
+    pub(super) fn set(role: TxPowerRole, index: i32) -> Result<i32, TxPowerError> {
+        Ok(unsafe { ble_txpwr_set(power_type(role)?, index) })
+    }
+
+    pub(super) fn get(role: TxPowerRole) -> Result<i32, TxPowerError> {
+        Ok(unsafe { ble_txpwr_get(power_type(role)?) })
+    }
+}
+
+#[cfg(any(esp32c2, esp32c6, esp32h2))]
+mod raw {
+    use super::*;
+    use crate::binary::include::*;
+
+    extern "C" {
+        fn ble_txpwr_set(
+            power_type: esp_ble_enhanced_power_type_t,
+            handle: u16,
+            power_level: i32,
+        ) -> i32;
+        fn ble_txpwr_get(power_type: esp_ble_enhanced_power_type_t, handle: u16) -> i32;
+    }
+
+    fn power_type(role: TxPowerRole) -> (esp_ble_enhanced_power_type_t, u16) {
+        match role {
+            TxPowerRole::Default | TxPowerRole::Scan => (
+                esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_DEFAULT,
+                0,
+            ),
+            // the legacy advertising instance
+            TxPowerRole::Advertising => (
+                esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_ADV,
+                0,
+            ),
+            TxPowerRole::Connection(handle) => (
+                esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_CONN,
+                handle,
+            ),
+        }
+    }
+
+    pub(super) fn set(role: TxPowerRole, index: i32) -> Result<i32, TxPowerError> {
+        let (power_type, handle) = power_type(role);
+        Ok(unsafe { ble_txpwr_set(power_type, handle, index) })
+    }
+
+    pub(super) fn get(role: TxPowerRole) -> Result<i32, TxPowerError> {
+        let (power_type, handle) = power_type(role);
+        Ok(unsafe { ble_txpwr_get(power_type, handle) })
+    }
+}
+
+fn ensure_initialized() -> Result<(), TxPowerError> {
+    if !super::BLE_INITIALIZED.load(Ordering::Relaxed) {
+        return Err(TxPowerError::NotInitialized);
+    }
+
+    Ok(())
+}
+
+/// Sets the transmit power for `role`.
+///
+/// The power of a connection can only be set while it's established.
+pub fn set(role: TxPowerRole, level: TxPowerLevel) -> Result<(), TxPowerError> {
+    ensure_initialized()?;
+
+    debug!("ble tx power {:?} = {:?}", role, level);
+    match raw::set(role, level.index())? {
+        0 => Ok(()),
+        err => {
+            if let TxPowerRole::Connection(connection) = role {
+                warn!("Setting the tx power of connection {} failed", connection);
+                return Err(TxPowerError::InvalidConnection(connection));
+            }
+            Err(TxPowerError::Controller(err))
+        }
+    }
+}
+
+/// Returns the transmit power for `role`.
+pub fn get(role: TxPowerRole) -> Result<TxPowerLevel, TxPowerError> {
+    ensure_initialized()?;
+
+    let index = raw::get(role)?;
+    if index < 0 {
+        if let TxPowerRole::Connection(connection) = role {
+            return Err(TxPowerError::InvalidConnection(connection));
+        }
+        return Err(TxPowerError::Controller(index));
+    }
+
+    TxPowerLevel::from_index(index).ok_or(TxPowerError::Controller(index))
+}
@@ -0,0 +1,399 @@
+//! WiFi/Bluetooth coexistence.
+//!
+//! With WiFi and BLE running at the same time (the `coex` feature) both share the one radio of
+//! the chip. The coexistence arbiter in the driver grants the radio to one of them at a time,
+//! splitting each coexistence period into WiFi and Bluetooth time slices.
+//!
+//! [`set_preference`] shifts this split:
+//!
+//! - [`CoexPreference::Wifi`] grants most of the period to WiFi, BLE connection events and
+//!   advertisements are skipped more often.
+//! - [`CoexPreference::Bt`] grants the radio to Bluetooth whenever it needs it, WiFi frames wait
+//!   for the Bluetooth slices.
+//! - [`CoexPreference::Balance`] is the default and splits the period evenly.
+//!
+//! The exact ratios are decided by the driver and differ between chips and driver versions.
+//! There are no reference numbers for the effect on throughput, the `coex_preference` example
+//! measures the WiFi throughput for each setting while advertising via BLE.
+//!
+//! The arbiter itself can be switched off with [`disable`] and back on with [`enable`] when only
+//! WiFi is in use, e.g. after initializing with `EspWifiInitFor::Wifi` and before calling
+//! `add_ble` or after `remove_ble`. Whether this changes the WiFi throughput hasn't been
+//! measured, compare the `coex_preference` download with and without [`disable`] before relying
+//! on it. While the BLE controller is running the arbiter can't be disabled.
+//!
+//! [`metrics`] samples the state of the arbiter's scheme together with counters of the WiFi
+//! driver's radio requests, e.g. to correlate BLE supervision timeouts with WiFi bursts:
+//!
+//! ```ignore
+//! let before = coex::metrics()?;
+//! // ... traffic ...
+//! let after = coex::metrics()?;
+//! println!("denied: {}", after.wifi_requests_denied - before.wifi_requests_denied);
+//! ```
+//!
+//! Traffic which must not be missed, e.g. an ESP-NOW exchange with a tight deadline, can be
+//! wrapped in a [`wifi_priority_window`]. While the returned guard is alive the arbiter favors
+//! WiFi, dropping it restores the preference set with [`set_preference`]. The ESP-NOW senders
+//! offer `send_with_wifi_priority` for single frames, which waits for the window to take effect
+//! before sending. The `esp_now_coex` example compares the ESP-NOW delivery rate with and
+//! without it while BLE is busy.
+//!
+//! ```ignore
+//! {
+//!     let _window = coex::wifi_priority_window()?;
+//!     exchange_with_peer().await;
+//! }
+//! ```
+
+#[cfg(coex)]
+use core::cell::RefCell;
+
+#[cfg(coex)]
+use critical_section::Mutex;
+#[cfg(coex)]
+use portable_atomic::{AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+#[cfg(coex)]
+use crate::compat::blocking_checks;
+
+/// Which radio the coexistence arbiter should favor, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoexPreference {
+    /// Favor WiFi
+    Wifi,
+    /// Favor Bluetooth
+    Bt,
+    /// Split the radio time evenly
+    #[default]
+    Balance,
+}
+
+/// Error returned by the functions in this module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoexError {
+    /// esp-wifi was built without coexistence support, i.e. without the `coex`, `wifi` and `ble`
+    /// features
+    Unsupported,
+    /// WiFi isn't initialized
+    NotInitialized,
+    /// The BLE controller is running, the arbiter can't be disabled
+    BleActive,
+    /// The driver returned the given error code
+    Internal(i32),
+}
+
+/// Snapshot of the coexistence arbiter, see [`metrics`].
+///
+/// The counters start at 0 when esp-wifi is initialized and wrap around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CoexMetrics {
+    /// Index of the current period of the scheme
+    pub period: u8,
+    /// Index of the current phase within the period
+    pub phase: i32,
+    /// Length of a scheme interval as reported by the driver
+    ///
+    /// The driver doesn't expose how the interval is split between WiFi and Bluetooth, only
+    /// the total.
+    pub interval: u32,
+    /// The status bits, see [`status`]
+    pub status: u32,
+    /// Requests for the radio made by the WiFi driver
+    pub wifi_requests: u32,
+    /// Requests of the WiFi driver which the arbiter didn't grant
+    pub wifi_requests_denied: u32,
+    /// Times the WiFi driver released the radio
+    pub wifi_releases: u32,
+}
+
+#[cfg(coex)]
+static WIFI_REQUESTS: AtomicU32 = AtomicU32::new(0);
+#[cfg(coex)]
+static WIFI_REQUESTS_DENIED: AtomicU32 = AtomicU32::new(0);
+#[cfg(coex)]
+static WIFI_RELEASES: AtomicU32 = AtomicU32::new(0);
+
+/// Counts a `coex_wifi_request` of the WiFi driver, `res` is what the arbiter returned.
+#[cfg(coex)]
+pub(crate) fn record_wifi_request(res: i32) {
+    WIFI_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    if res != 0 {
+        WIFI_REQUESTS_DENIED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a `coex_wifi_release` of the WiFi driver.
+#[cfg(coex)]
+pub(crate) fn record_wifi_release() {
+    WIFI_RELEASES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The preference set by [`set_preference`], restored when the last priority window closes.
+#[cfg(coex)]
+static PREFERENCE: Mutex<RefCell<CoexPreference>> =
+    Mutex::new(RefCell::new(CoexPreference::Balance));
+
+/// Number of open [`WifiPriorityWindow`]s.
+#[cfg(coex)]
+static PRIORITY_WINDOWS: AtomicUsize = AtomicUsize::new(0);
+
+/// Period of the scheme and time in milliseconds when the first open window applied the WiFi
+/// preference, see [`WifiPriorityWindow::wait_until_effective`].
+#[cfg(coex)]
+static PRIORITY_PERIOD: AtomicU8 = AtomicU8::new(0);
+#[cfg(coex)]
+static PRIORITY_SINCE: AtomicU64 = AtomicU64::new(0);
+
+/// Upper bound for [`WifiPriorityWindow::wait_until_effective`], in case the scheme only has a
+/// single period and its index never changes.
+#[cfg(coex)]
+const PRIORITY_SETTLE_TIMEOUT_MS: u64 = 100;
+
+/// Resets the counters and the preference when WiFi is deinitialized.
+#[cfg(coex)]
+pub(crate) fn reset() {
+    WIFI_REQUESTS.store(0, Ordering::Relaxed);
+    WIFI_REQUESTS_DENIED.store(0, Ordering::Relaxed);
+    WIFI_RELEASES.store(0, Ordering::Relaxed);
+    blocking_checks::with(|cs| *PREFERENCE.borrow_ref_mut(cs) = CoexPreference::Balance);
+}
+
+/// Returns the current state of the coexistence arbiter and the WiFi request counters.
+///
+/// The scheme is read in a critical section so period and phase belong together. This only
+/// reads a few values of the driver, but it's meant to be polled from a task rather than an
+/// interrupt handler.
+pub fn metrics() -> Result<CoexMetrics, CoexError> {
+    #[cfg(coex)]
+    {
+        use crate::binary::include::{
+            coex_schm_curr_period_get, coex_schm_curr_phase_idx_get, coex_schm_interval_get,
+        };
+
+        if !crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+            return Err(CoexError::NotInitialized);
+        }
+
+        let (period, phase, interval) = blocking_checks::with(|_| unsafe {
+            (
+                coex_schm_curr_period_get(),
+                coex_schm_curr_phase_idx_get(),
+                coex_schm_interval_get(),
+            )
+        });
+
+        Ok(CoexMetrics {
+            period,
+            phase,
+            interval,
+            status: status()?,
+            wifi_requests: WIFI_REQUESTS.load(Ordering::Relaxed),
+            wifi_requests_denied: WIFI_REQUESTS_DENIED.load(Ordering::Relaxed),
+            wifi_releases: WIFI_RELEASES.load(Ordering::Relaxed),
+        })
+    }
+
+    #[cfg(not(coex))]
+    Err(CoexError::Unsupported)
+}
+
+/// Sets the preference of the coexistence arbiter.
+///
+/// Can be called at any time after initializing for WiFi and BLE, it takes effect with the next
+/// coexistence period.
+///
+/// While a [`wifi_priority_window`] is open the preference is only stored and applied once the
+/// last window is closed.
+pub fn set_preference(preference: CoexPreference) -> Result<(), CoexError> {
+    #[cfg(coex)]
+    {
+        if !crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+            return Err(CoexError::NotInitialized);
+        }
+
+        blocking_checks::with(|cs| *PREFERENCE.borrow_ref_mut(cs) = preference);
+        if PRIORITY_WINDOWS.load(Ordering::Relaxed) > 0 {
+            debug!("Deferring coexistence preference {:?}", preference);
+            return Ok(());
+        }
+
+        apply_preference(preference)
+    }
+
+    #[cfg(not(coex))]
+    {
+        warn!(
+            "Coexistence preference {:?} needs the `coex` feature",
+            preference
+        );
+        Err(CoexError::Unsupported)
+    }
+}
+
+#[cfg(coex)]
+fn apply_preference(preference: CoexPreference) -> Result<(), CoexError> {
+    use crate::binary::include::{
+        esp_coex_prefer_t_ESP_COEX_PREFER_BALANCE, esp_coex_prefer_t_ESP_COEX_PREFER_BT,
+        esp_coex_prefer_t_ESP_COEX_PREFER_WIFI, esp_coex_preference_set,
+    };
+
+    let prefer = match preference {
+        CoexPreference::Wifi => esp_coex_prefer_t_ESP_COEX_PREFER_WIFI,
+        CoexPreference::Bt => esp_coex_prefer_t_ESP_COEX_PREFER_BT,
+        CoexPreference::Balance => esp_coex_prefer_t_ESP_COEX_PREFER_BALANCE,
+    };
+
+    debug!("esp_coex_preference_set {:?}", preference);
+    match unsafe { esp_coex_preference_set(prefer) } {
+        0 => Ok(()),
+        err => Err(CoexError::Internal(err)),
+    }
+}
+
+/// Favors WiFi until the returned guard is dropped, see the [module documentation](self).
+///
+/// Windows can be nested or overlap, the preference set with [`set_preference`] is restored
+/// when the last one is closed. Like [`set_preference`] this takes effect with the next
+/// coexistence period, so a window should be opened a little before the traffic it protects,
+/// or the traffic should wait for [`WifiPriorityWindow::wait_until_effective`].
+pub fn wifi_priority_window() -> Result<WifiPriorityWindow, CoexError> {
+    #[cfg(coex)]
+    {
+        if !crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+            return Err(CoexError::NotInitialized);
+        }
+
+        if PRIORITY_WINDOWS.fetch_add(1, Ordering::Relaxed) == 0 {
+            if let Err(err) = apply_preference(CoexPreference::Wifi) {
+                PRIORITY_WINDOWS.fetch_sub(1, Ordering::Relaxed);
+                return Err(err);
+            }
+
+            let period = unsafe { crate::binary::include::coex_schm_curr_period_get() };
+            PRIORITY_PERIOD.store(period, Ordering::Relaxed);
+            PRIORITY_SINCE.store(crate::current_millis(), Ordering::Relaxed);
+        }
+
+        Ok(WifiPriorityWindow { _private: () })
+    }
+
+    #[cfg(not(coex))]
+    Err(CoexError::Unsupported)
+}
+
+/// An open window in which the arbiter favors WiFi, see [`wifi_priority_window`].
+#[must_use]
+pub struct WifiPriorityWindow {
+    _private: (),
+}
+
+impl WifiPriorityWindow {
+    /// Waits until the arbiter started a new period since the WiFi preference was applied, so
+    /// traffic sent afterwards is favored.
+    ///
+    /// Returns right away if the window was opened early enough. Waits at most 100 ms, the
+    /// driver doesn't report the length of a period.
+    pub fn wait_until_effective(&self) {
+        #[cfg(coex)]
+        {
+            let period = PRIORITY_PERIOD.load(Ordering::Relaxed);
+            let deadline = PRIORITY_SINCE.load(Ordering::Relaxed) + PRIORITY_SETTLE_TIMEOUT_MS;
+
+            while unsafe { crate::binary::include::coex_schm_curr_period_get() } == period
+                && crate::current_millis() < deadline
+            {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+impl Drop for WifiPriorityWindow {
+    fn drop(&mut self) {
+        #[cfg(coex)]
+        if PRIORITY_WINDOWS.fetch_sub(1, Ordering::Relaxed) == 1 {
+            let preference = blocking_checks::with(|cs| *PREFERENCE.borrow_ref(cs));
+            if apply_preference(preference).is_err() {
+                warn!(
+                    "Restoring the coexistence preference {:?} failed",
+                    preference
+                );
+            }
+        }
+    }
+}
+
+/// Enables the coexistence arbiter again after [`disable`].
+///
+/// The arbiter is enabled by default, enabling it again is harmless.
+pub fn enable() -> Result<(), CoexError> {
+    #[cfg(coex)]
+    {
+        if !crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+            return Err(CoexError::NotInitialized);
+        }
+
+        debug!("coex_enable");
+        match unsafe { crate::binary::include::coex_enable() } {
+            0 => Ok(()),
+            err => Err(CoexError::Internal(err)),
+        }
+    }
+
+    #[cfg(not(coex))]
+    {
+        warn!("Enabling coexistence needs the `coex` feature");
+        Err(CoexError::Unsupported)
+    }
+}
+
+/// Disables the coexistence arbiter, giving the radio to WiFi all the time.
+///
+/// Fails with [`CoexError::BleActive`] while the BLE controller is initialized, i.e. until
+/// `remove_ble`, Bluetooth would lose the radio in the middle of its connection events. Call
+/// [`enable`] before `add_ble`, the BLE initialization enables the arbiter on its own as well.
+pub fn disable() -> Result<(), CoexError> {
+    #[cfg(coex)]
+    {
+        if !crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+            return Err(CoexError::NotInitialized);
+        }
+
+        if crate::ble::BLE_INITIALIZED.load(Ordering::Relaxed) {
+            warn!("Coexistence can't be disabled while BLE is active");
+            return Err(CoexError::BleActive);
+        }
+
+        debug!("coex_disable");
+        unsafe { crate::binary::include::coex_disable() };
+        Ok(())
+    }
+
+    #[cfg(not(coex))]
+    {
+        warn!("Disabling coexistence needs the `coex` feature");
+        Err(CoexError::Unsupported)
+    }
+}
+
+/// Returns the status bits reported by the arbiter's `coex_status_get`.
+///
+/// The bits flag which radio activities the arbiter currently schedules, their meaning is
+/// defined by the driver and differs between driver versions.
+pub fn status() -> Result<u32, CoexError> {
+    #[cfg(coex)]
+    {
+        if !crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+            return Err(CoexError::NotInitialized);
+        }
+
+        Ok(unsafe { crate::binary::include::coex_status_get() })
+    }
+
+    #[cfg(not(coex))]
+    Err(CoexError::Unsupported)
+}
@@ -1,6 +1,7 @@
 use super::phy_init_data::PHY_INIT_DATA_DEFAULT;
 use crate::binary::include::*;
 use crate::common_adapter::RADIO_CLOCKS;
+use crate::compat::blocking_checks;
 use crate::hal::prelude::ram;
 use crate::hal::system::RadioClockController;
 use crate::hal::system::RadioPeripherals;
@@ -39,7 +40,7 @@ pub(crate) fn phy_mem_init() {
 pub(crate) unsafe fn phy_enable() {
     let count = PHY_ACCESS_REF.fetch_add(1, Ordering::SeqCst);
     if count == 0 {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             // #if CONFIG_IDF_TARGET_ESP32
             //     // Update time stamp
             //     s_phy_rf_en_ts = esp_timer_get_time();
@@ -50,17 +51,9 @@ pub(crate) unsafe fn phy_enable() {
             phy_enable_clock();
 
             if G_IS_PHY_CALIBRATED == false {
-                let mut cal_data: [u8; core::mem::size_of::<esp_phy_calibration_data_t>()] =
-                    [0u8; core::mem::size_of::<esp_phy_calibration_data_t>()];
-
                 let init_data = &PHY_INIT_DATA_DEFAULT;
 
-                register_chipv7_phy(
-                    init_data,
-                    &mut cal_data as *mut _
-                        as *mut crate::binary::include::esp_phy_calibration_data_t,
-                    esp_phy_calibration_mode_t_PHY_RF_CAL_FULL,
-                );
+                super::calibrate_phy(init_data);
 
                 G_IS_PHY_CALIBRATED = true;
             } else {
@@ -80,7 +73,7 @@ pub(crate) unsafe fn phy_enable() {
 pub(crate) unsafe fn phy_disable() {
     let count = PHY_ACCESS_REF.fetch_sub(1, Ordering::SeqCst);
     if count == 1 {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             phy_digital_regs_store();
             // Disable PHY and RF.
             phy_close_rf();
@@ -119,7 +112,7 @@ pub(crate) unsafe fn phy_enable_clock() {
 
     let count = PHY_CLOCK_ENABLE_REF.fetch_add(1, Ordering::SeqCst);
     if count == 0 {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             unwrap!(RADIO_CLOCKS.as_mut()).enable(RadioPeripherals::Phy);
         });
     }
@@ -131,7 +124,7 @@ pub(crate) unsafe fn phy_disable_clock() {
 
     let count = PHY_CLOCK_ENABLE_REF.fetch_sub(1, Ordering::SeqCst);
     if count == 1 {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             unwrap!(RADIO_CLOCKS.as_mut()).disable(RadioPeripherals::Phy);
         });
     }
@@ -177,7 +170,9 @@ unsafe extern "C" fn esp_dport_access_reg_read(reg: u32) -> u32 {
 unsafe extern "C" fn phy_enter_critical() -> u32 {
     trace!("phy_enter_critical");
 
-    core::mem::transmute(critical_section::acquire())
+    let state = core::mem::transmute(critical_section::acquire());
+    crate::compat::blocking_checks::critical_section_entered();
+    state
 }
 
 /****************************************************************************
@@ -197,6 +192,7 @@ unsafe extern "C" fn phy_enter_critical() -> u32 {
 #[no_mangle]
 unsafe extern "C" fn phy_exit_critical(level: u32) {
     trace!("phy_exit_critical {}", level);
+    crate::compat::blocking_checks::critical_section_left();
 
     critical_section::release(core::mem::transmute(level));
 }
@@ -1,6 +1,7 @@
 use super::phy_init_data::PHY_INIT_DATA_DEFAULT;
 use crate::binary::include::*;
 use crate::common_adapter::RADIO_CLOCKS;
+use crate::compat::blocking_checks;
 use crate::compat::common::str_from_c;
 use crate::hal::system::RadioClockController;
 use crate::hal::system::RadioPeripherals;
@@ -29,13 +30,10 @@ pub(crate) fn phy_mem_init() {
 pub(crate) unsafe fn phy_enable() {
     let count = PHY_ACCESS_REF.fetch_add(1, Ordering::SeqCst);
     if count == 0 {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             phy_enable_clock();
 
             if G_IS_PHY_CALIBRATED == false {
-                let mut cal_data: [u8; core::mem::size_of::<esp_phy_calibration_data_t>()] =
-                    [0u8; core::mem::size_of::<esp_phy_calibration_data_t>()];
-
                 let phy_version = get_phy_version_str();
                 trace!("phy_version {}", str_from_c(phy_version as *const u8));
 
@@ -50,12 +48,7 @@ pub(crate) unsafe fn phy_enable() {
                     phy_bbpll_en_usb(true);
                 }
 
-                register_chipv7_phy(
-                    init_data,
-                    &mut cal_data as *mut _
-                        as *mut crate::binary::include::esp_phy_calibration_data_t,
-                    esp_phy_calibration_mode_t_PHY_RF_CAL_FULL,
-                );
+                super::calibrate_phy(init_data);
 
                 G_IS_PHY_CALIBRATED = true;
             } else {
@@ -80,7 +73,7 @@ pub(crate) unsafe fn phy_enable() {
 pub(crate) unsafe fn phy_disable() {
     let count = PHY_ACCESS_REF.fetch_sub(1, Ordering::SeqCst);
     if count == 1 {
-        critical_section::with(|_| {
+        blocking_checks::with(|_| {
             phy_digital_regs_store();
             // Disable PHY and RF.
             phy_close_rf();
@@ -119,7 +112,7 @@ fn phy_digital_regs_store() {
 
 pub(crate) unsafe fn phy_enable_clock() {
     trace!("phy_enable_clock");
-    critical_section::with(|_| {
+    blocking_checks::with(|_| {
         unwrap!(RADIO_CLOCKS.as_mut()).enable(RadioPeripherals::Phy);
     });
 
@@ -129,7 +122,7 @@ pub(crate) unsafe fn phy_enable_clock() {
 #[allow(unused)]
 pub(crate) unsafe fn phy_disable_clock() {
     trace!("phy_disable_clock");
-    critical_section::with(|_| {
+    blocking_checks::with(|_| {
         unwrap!(RADIO_CLOCKS.as_mut()).disable(RadioPeripherals::Phy);
     });
 
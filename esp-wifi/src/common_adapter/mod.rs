@@ -3,6 +3,7 @@ use crate::binary::include::esp_timer_create_args_t;
 use crate::binary::include::esp_timer_get_time;
 use crate::binary::include::esp_timer_handle_t;
 
+use crate::compat::blocking_checks;
 use crate::compat::common::*;
 use crate::compat::syslog::syslog;
 use crate::compat::timer_compat::*;
@@ -15,6 +16,9 @@ use hal::Rng;
 
 use hal::macros::ram;
 
+use crate::EntropySource;
+use core::ptr::addr_of_mut;
+
 #[cfg_attr(esp32c3, path = "common_adapter_esp32c3.rs")]
 #[cfg_attr(esp32c2, path = "common_adapter_esp32c2.rs")]
 #[cfg_attr(esp32c6, path = "common_adapter_esp32c6.rs")]
@@ -33,18 +37,95 @@ pub(crate) mod chip_specific;
 #[cfg_attr(esp32s2, path = "phy_init_data_esp32s2.rs")]
 pub(crate) mod phy_init_data;
 
-pub(crate) static mut RANDOM_GENERATOR: Option<Rng> = None;
+static mut HARDWARE_RNG: Option<Rng> = None;
+
+static mut ENTROPY_SOURCE: Option<&'static mut dyn EntropySource> = None;
 
 pub(crate) static mut RADIO_CLOCKS: Option<RadioClockControl> = None;
 
+/// Uses the hardware RNG as the entropy source.
 pub(crate) fn init_rng(rng: Rng) {
-    unsafe { RANDOM_GENERATOR = Some(core::mem::transmute(rng)) };
+    unsafe {
+        HARDWARE_RNG = Some(core::mem::transmute(rng));
+        init_entropy_source(unwrap!((*addr_of_mut!(HARDWARE_RNG)).as_mut()));
+    }
+}
+
+pub(crate) fn init_entropy_source(source: &'static mut dyn EntropySource) {
+    blocking_checks::with(|_| unsafe { ENTROPY_SOURCE = Some(source) });
+}
+
+/// Fills `buf` from the entropy source, returns `false` if there is none.
+pub(crate) fn fill_random(buf: &mut [u8]) -> bool {
+    blocking_checks::with(|_| unsafe {
+        match &mut *addr_of_mut!(ENTROPY_SOURCE) {
+            Some(source) => {
+                source.fill(buf);
+                true
+            }
+            None => false,
+        }
+    })
 }
 
 pub(crate) fn init_radio_clock_control(rcc: RadioClockControl) {
     unsafe { RADIO_CLOCKS = Some(core::mem::transmute(rcc)) };
 }
 
+/// Size of the RF calibration data, see [`crate::wifi::WarmStartData`].
+pub(crate) const PHY_CALIBRATION_DATA_SIZE: usize =
+    core::mem::size_of::<crate::binary::include::esp_phy_calibration_data_t>();
+
+/// Result of the last RF calibration, or the data restored with [`set_phy_calibration_data`].
+static mut PHY_CALIBRATION_DATA: [u8; PHY_CALIBRATION_DATA_SIZE] = [0; PHY_CALIBRATION_DATA_SIZE];
+static mut PHY_CALIBRATION_DATA_VALID: bool = false;
+
+/// Calibrates the RF, skipping the calibration if there is calibration data from a previous
+/// boot.
+pub(crate) unsafe fn calibrate_phy(init_data: &crate::binary::include::esp_phy_init_data_t) {
+    use crate::binary::include::{
+        esp_phy_calibration_data_t, esp_phy_calibration_mode_t_PHY_RF_CAL_FULL,
+        esp_phy_calibration_mode_t_PHY_RF_CAL_NONE, register_chipv7_phy, ESP_CAL_DATA_CHECK_FAIL,
+    };
+
+    let cal_data = addr_of_mut!(PHY_CALIBRATION_DATA) as *mut esp_phy_calibration_data_t;
+
+    if PHY_CALIBRATION_DATA_VALID {
+        let res = register_chipv7_phy(
+            init_data,
+            cal_data,
+            esp_phy_calibration_mode_t_PHY_RF_CAL_NONE,
+        );
+        if res as u32 != ESP_CAL_DATA_CHECK_FAIL {
+            debug!("Reused RF calibration data");
+            return;
+        }
+        warn!("RF calibration data is invalid, doing a full calibration");
+    }
+
+    register_chipv7_phy(
+        init_data,
+        cal_data,
+        esp_phy_calibration_mode_t_PHY_RF_CAL_FULL,
+    );
+    PHY_CALIBRATION_DATA_VALID = true;
+}
+
+/// The calibration data of the last RF calibration, `None` if the RF wasn't calibrated yet.
+pub(crate) fn phy_calibration_data() -> Option<[u8; PHY_CALIBRATION_DATA_SIZE]> {
+    blocking_checks::with(|_| unsafe { PHY_CALIBRATION_DATA_VALID.then_some(PHY_CALIBRATION_DATA) })
+}
+
+/// Makes the next RF calibration reuse `data` instead of calibrating.
+///
+/// This only has an effect before the RF is enabled for the first time.
+pub(crate) fn set_phy_calibration_data(data: &[u8; PHY_CALIBRATION_DATA_SIZE]) {
+    blocking_checks::with(|_| unsafe {
+        PHY_CALIBRATION_DATA = *data;
+        PHY_CALIBRATION_DATA_VALID = true;
+    });
+}
+
 /****************************************************************************
  * Name: esp_semphr_create
  *
@@ -103,6 +184,9 @@ pub unsafe extern "C" fn semphr_take(
     semphr: *mut crate::binary::c_types::c_void,
     tick: u32,
 ) -> i32 {
+    if tick != 0 {
+        crate::compat::blocking_checks::assert_may_block("semphr_take");
+    }
     sem_take(semphr, tick)
 }
 
@@ -133,11 +217,9 @@ pub unsafe extern "C" fn semphr_give(semphr: *mut crate::binary::c_types::c_void
 pub unsafe extern "C" fn random() -> crate::binary::c_types::c_ulong {
     trace!("random");
 
-    if let Some(ref mut rng) = RANDOM_GENERATOR {
-        rng.random()
-    } else {
-        0
-    }
+    let mut bytes = [0u8; 4];
+    fill_random(&mut bytes);
+    u32::from_le_bytes(bytes) as crate::binary::c_types::c_ulong
 }
 
 /****************************************************************************
@@ -345,12 +427,7 @@ pub unsafe extern "C" fn esp_fill_random(dst: *mut u8, len: u32) {
     trace!("esp_fill_random");
     let dst = core::slice::from_raw_parts_mut(dst, len as usize);
 
-    if let Some(ref mut rng) = RANDOM_GENERATOR {
-        for chunk in dst.chunks_mut(4) {
-            let bytes = rng.random().to_le_bytes();
-            chunk.copy_from_slice(&bytes[..chunk.len()]);
-        }
-    }
+    fill_random(dst);
 }
 
 #[no_mangle]
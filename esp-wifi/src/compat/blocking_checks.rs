@@ -0,0 +1,128 @@
+//! Debug checks for blocking OS primitives, enabled by the `debug-blocking-checks` feature.
+//!
+//! The driver blobs call primitives like `semphr_take` or `queue_recv` which may yield to the
+//! scheduler until they time out. Doing so from an interrupt handler, or while a critical
+//! section is held, dead-locks or corrupts the scheduler in ways which are hard to trace back.
+//! With the feature enabled these primitives panic instead, naming the primitive and the context
+//! it was entered from.
+//!
+//! Critical sections are tracked when the blobs take them through the OS adapter and when
+//! esp-wifi takes them itself, which is why the crate uses [`with`] instead of
+//! [`critical_section::with`].
+//!
+//! Without the feature everything in here compiles to nothing.
+
+#[cfg(feature = "debug-blocking-checks")]
+use portable_atomic::{AtomicUsize, Ordering};
+
+#[cfg(all(feature = "debug-blocking-checks", any(esp32, esp32s3)))]
+const CORES: usize = 2;
+#[cfg(all(feature = "debug-blocking-checks", not(any(esp32, esp32s3))))]
+const CORES: usize = 1;
+
+#[cfg(feature = "debug-blocking-checks")]
+#[allow(clippy::declare_interior_mutable_const)]
+const DEPTH_ZERO: AtomicUsize = AtomicUsize::new(0);
+
+/// Nesting depth of the radio interrupt handlers, per core.
+#[cfg(feature = "debug-blocking-checks")]
+static ISR_DEPTH: [AtomicUsize; CORES] = [DEPTH_ZERO; CORES];
+
+/// Nesting depth of the critical sections taken through the OS adapter or [`with`], per core.
+#[cfg(feature = "debug-blocking-checks")]
+static CRITICAL_SECTION_DEPTH: [AtomicUsize; CORES] = [DEPTH_ZERO; CORES];
+
+#[cfg(feature = "debug-blocking-checks")]
+#[inline(always)]
+fn core_index() -> usize {
+    #[cfg(any(esp32, esp32s3))]
+    {
+        crate::hal::get_core() as usize
+    }
+    #[cfg(not(any(esp32, esp32s3)))]
+    {
+        0
+    }
+}
+
+/// Marks the current core as running a radio interrupt handler until dropped.
+pub(crate) struct IsrGuard {
+    #[cfg(feature = "debug-blocking-checks")]
+    core: usize,
+}
+
+impl IsrGuard {
+    #[inline(always)]
+    pub(crate) fn enter() -> Self {
+        #[cfg(feature = "debug-blocking-checks")]
+        {
+            let core = core_index();
+            ISR_DEPTH[core].fetch_add(1, Ordering::Relaxed);
+            Self { core }
+        }
+        #[cfg(not(feature = "debug-blocking-checks"))]
+        {
+            Self {}
+        }
+    }
+}
+
+#[cfg(feature = "debug-blocking-checks")]
+impl Drop for IsrGuard {
+    fn drop(&mut self) {
+        ISR_DEPTH[self.core].fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Records that a critical section was entered through the OS adapter.
+#[inline(always)]
+pub(crate) fn critical_section_entered() {
+    #[cfg(feature = "debug-blocking-checks")]
+    CRITICAL_SECTION_DEPTH[core_index()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records that a critical section entered through the OS adapter was left.
+#[inline(always)]
+pub(crate) fn critical_section_left() {
+    #[cfg(feature = "debug-blocking-checks")]
+    {
+        let depth = &CRITICAL_SECTION_DEPTH[core_index()];
+        // The blobs may restore a state they didn't save through us, don't wrap around
+        let _ = depth.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |d| d.checked_sub(1));
+    }
+}
+
+/// Runs `f` in a critical section like [`critical_section::with`], tracking it for
+/// [`assert_may_block`].
+#[inline(always)]
+pub(crate) fn with<R>(f: impl FnOnce(critical_section::CriticalSection<'_>) -> R) -> R {
+    critical_section::with(|cs| {
+        critical_section_entered();
+        let res = f(cs);
+        critical_section_left();
+        res
+    })
+}
+
+/// Panics if `primitive` is about to block from an interrupt handler or a critical section.
+#[inline(always)]
+pub(crate) fn assert_may_block(_primitive: &str) {
+    #[cfg(feature = "debug-blocking-checks")]
+    {
+        let core = core_index();
+        if ISR_DEPTH[core].load(Ordering::Relaxed) > 0 {
+            panic!(
+                "blocking primitive `{}` entered from interrupt context on core {}",
+                _primitive, core
+            );
+        }
+
+        let depth = CRITICAL_SECTION_DEPTH[core].load(Ordering::Relaxed);
+        if depth > 0 {
+            panic!(
+                "blocking primitive `{}` entered while holding a critical section (depth {}) on core {}",
+                _primitive, depth, core
+            );
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use core::{ffi::VaListImpl, fmt::Write, ptr::addr_of_mut};
 
-use super::queue::SimpleQueue;
+use super::{blocking_checks, queue::SimpleQueue};
 use crate::{
     binary::{
         c_types::{c_int, c_void},
@@ -25,14 +25,17 @@ struct Mutex {
     locking_pid: usize,
     count: u32,
     recursive: bool,
+    allocated: bool,
 }
 
-static mut MUTEXES: [Mutex; 10] = [Mutex {
+const UNUSED_MUTEX: Mutex = Mutex {
     locking_pid: 0xffff_ffff,
     count: 0,
     recursive: false,
-}; 10];
-static mut MUTEX_IDX_CURRENT: usize = 0;
+    allocated: false,
+};
+
+static mut MUTEXES: [Mutex; 10] = [UNUSED_MUTEX; 10];
 
 static mut FAKE_WIFI_QUEUE: &SimpleQueue<[u8; 8], 200> = unsafe { &REAL_WIFI_QUEUE };
 static mut REAL_WIFI_QUEUE: SimpleQueue<[u8; 8], 200> = SimpleQueue::new(); // first there is a ptr to the real queue - driver checks it's not null
@@ -56,7 +59,7 @@ unsafe extern "C" fn strnlen(chars: *const u8, maxlen: usize) -> usize {
 }
 
 pub fn sem_create(max: u32, init: u32) -> *mut c_void {
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         let mut res = 0xffff;
         memory_fence();
         for (i, sem) in CURR_SEM.iter().enumerate() {
@@ -81,7 +84,7 @@ pub fn sem_create(max: u32, init: u32) -> *mut c_void {
 
 pub fn sem_delete(semphr: *mut c_void) {
     trace!(">>> sem delete");
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         CURR_SEM[semphr as usize - 1] = None;
         memory_fence();
     })
@@ -97,7 +100,7 @@ pub fn sem_take(semphr: *mut c_void, tick: u32) -> i32 {
     let sem_idx = semphr as usize - 1;
 
     'outer: loop {
-        let res = critical_section::with(|_| unsafe {
+        let res = blocking_checks::with(|_| unsafe {
             memory_fence();
             if let Some(cnt) = CURR_SEM[sem_idx] {
                 if cnt > 0 {
@@ -131,7 +134,7 @@ pub fn sem_give(semphr: *mut c_void) -> i32 {
     trace!("semphr_give {:?}", semphr);
     let sem_idx = semphr as usize - 1;
 
-    let res = critical_section::with(|_| unsafe {
+    let res = blocking_checks::with(|_| unsafe {
         if let Some(cnt) = CURR_SEM[sem_idx] {
             CURR_SEM[sem_idx] = Some(cnt + 1);
             memory_fence();
@@ -146,7 +149,7 @@ pub fn sem_give(semphr: *mut c_void) -> i32 {
 
 pub fn thread_sem_get() -> *mut c_void {
     trace!("wifi_thread_semphr_get");
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         let tid = current_task();
         if let Some(sem) = PER_THREAD_SEM[tid] {
             trace!("wifi_thread_semphr_get - return for {} {:?}", tid, sem);
@@ -161,16 +164,35 @@ pub fn thread_sem_get() -> *mut c_void {
 }
 
 pub fn create_recursive_mutex() -> *mut c_void {
-    critical_section::with(|_| unsafe {
-        let ptr = &mut MUTEXES[MUTEX_IDX_CURRENT] as *mut _ as *mut Mutex;
-        (*ptr).recursive = true;
-        MUTEX_IDX_CURRENT += 1;
+    blocking_checks::with(|_| unsafe {
+        memory_fence();
+        let Some(mutex) = MUTEXES.iter_mut().find(|mutex| !mutex.allocated) else {
+            panic!("ran out of mutexes");
+        };
+        mutex.allocated = true;
+        mutex.recursive = true;
+        let ptr = mutex as *mut Mutex;
         memory_fence();
         trace!("recursive_mutex_create called {:?}", ptr);
         ptr as *mut c_void
     })
 }
 
+/// Returns a mutex to the pool so it can be handed out again.
+pub fn delete_mutex(mutex: *mut c_void) {
+    trace!("mutex_delete {:?}", mutex);
+
+    blocking_checks::with(|_| unsafe {
+        *(mutex as *mut Mutex) = UNUSED_MUTEX;
+        memory_fence();
+    })
+}
+
+/// Drops the events left in the driver's queue, e.g. when the driver is deinitialized.
+pub fn clear_wifi_queue() {
+    blocking_checks::with(|_| unsafe { while REAL_WIFI_QUEUE.dequeue().is_some() {} })
+}
+
 /// Lock a mutex. Block until successful.
 pub fn lock_mutex(mutex: *mut c_void) -> i32 {
     trace!("mutex_lock ptr = {:?}", mutex);
@@ -179,7 +201,7 @@ pub fn lock_mutex(mutex: *mut c_void) -> i32 {
     let current_task = current_task();
 
     loop {
-        let mutex_locked = critical_section::with(|_| unsafe {
+        let mutex_locked = blocking_checks::with(|_| unsafe {
             if (*ptr).count == 0 {
                 (*ptr).locking_pid = current_task;
                 (*ptr).count += 1;
@@ -205,7 +227,7 @@ pub fn unlock_mutex(mutex: *mut c_void) -> i32 {
     trace!("mutex_unlock {:?}", mutex);
 
     let ptr = mutex as *mut Mutex;
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         memory_fence();
         if (*ptr).count > 0 {
             (*ptr).count -= 1;
@@ -256,7 +278,7 @@ pub fn send_queued(queue: *mut c_void, item: *mut c_void, block_time_tick: u32)
     let mut data: [u8; 8] = unwrap!(message.try_into());
     trace!("queue posting {:?}", data);
 
-    critical_section::with(|_| {
+    blocking_checks::with(|_| {
         if unsafe { REAL_WIFI_QUEUE.enqueue(data).is_ok() } {
             memory_fence();
             1
@@ -286,7 +308,7 @@ pub fn receive_queued(queue: *mut c_void, item: *mut c_void, block_time_tick: u3
     }
 
     loop {
-        let message = critical_section::with(|_| unsafe { REAL_WIFI_QUEUE.dequeue() });
+        let message = blocking_checks::with(|_| unsafe { REAL_WIFI_QUEUE.dequeue() });
 
         if let Some(message) = message {
             let out_message = unsafe {
@@ -1,5 +1,6 @@
 use core::alloc::Layout;
 
+use crate::compat::blocking_checks;
 use crate::HEAP;
 
 pub unsafe extern "C" fn malloc(size: usize) -> *mut u8 {
@@ -8,7 +9,7 @@ pub unsafe extern "C" fn malloc(size: usize) -> *mut u8 {
     let total_size = size as usize + 4;
 
     let layout = Layout::from_size_align_unchecked(total_size, 4);
-    let ptr = critical_section::with(|cs| {
+    let ptr = blocking_checks::with(|cs| {
         HEAP.borrow_ref_mut(cs)
             .allocate_first_fit(layout)
             .ok()
@@ -35,7 +36,7 @@ pub unsafe extern "C" fn free(ptr: *mut u8) {
     let total_size = *(ptr as *const usize);
 
     let layout = Layout::from_size_align_unchecked(total_size, 4);
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         HEAP.borrow_ref_mut(cs)
             .deallocate(core::ptr::NonNull::new_unchecked(ptr as *mut u8), layout)
     });
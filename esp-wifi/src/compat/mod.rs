@@ -1,3 +1,4 @@
+pub(crate) mod blocking_checks;
 pub mod common;
 pub mod malloc;
 pub mod syslog;
@@ -1,11 +1,27 @@
-use crate::{binary::c_types, compat::common::str_from_c, timer::yield_task};
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::{
+    binary::c_types,
+    compat::{blocking_checks, common::str_from_c},
+    preempt::current_task,
+    timer::yield_task,
+};
 
 use super::queue::SimpleQueue;
 
+/// `tskNO_AFFINITY` of FreeRTOS, passed by tasks which can run on any core
+const NO_AFFINITY: u32 = 0x7fff_ffff;
+
 type TaskFunc = (extern "C" fn(*mut c_types::c_void), *mut c_types::c_void);
 
 static mut TASK_SPAWN_QUEUE: SimpleQueue<TaskFunc, 4> = SimpleQueue::new();
 
+/// The C tasks currently running, as the id of the task running them and their function.
+static RUNNING_TASKS: Mutex<RefCell<heapless::Vec<(usize, usize), 2>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
 pub fn spawn_task(
     task_func: *mut c_types::c_void,
     name: *const c_types::c_char,
@@ -13,7 +29,7 @@ pub fn spawn_task(
     param: *mut c_types::c_void,
     prio: u32,
     _task_handle: *mut c_types::c_void,
-    _core_id: u32,
+    core_id: u32,
 ) -> bool {
     debug!(
         "spawning task {}: {:?} param {:?} prio {}",
@@ -23,9 +39,21 @@ pub fn spawn_task(
         prio
     );
 
+    // There is a single scheduler on the core given by `EspWifiConfig::core_id`, so every task
+    // runs there whatever core it asks for
+    let scheduler_core = crate::esp_wifi_config().core_id as u32;
+    if core_id != NO_AFFINITY && core_id != scheduler_core {
+        warn!(
+            "task {} asked for core {}, it runs on core {}",
+            unsafe { str_from_c(name.cast()) },
+            core_id,
+            scheduler_core
+        );
+    }
+
     // TODO: allocate a stack and insert into the task queue
 
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         if TASK_SPAWN_QUEUE
             .enqueue((core::mem::transmute(task_func), param))
             .is_ok()
@@ -38,14 +66,52 @@ pub fn spawn_task(
     })
 }
 
+/// Stops the C task running `task_func`, e.g. when the driver deletes one of its tasks.
+///
+/// The task running it starts over and picks up the next spawned C task.
+pub(crate) fn delete_task(task_func: usize) {
+    let id = blocking_checks::with(|cs| {
+        let mut running = RUNNING_TASKS.borrow_ref_mut(cs);
+        let index = running.iter().position(|(_, f)| *f == task_func)?;
+        Some(running.swap_remove(index).0)
+    });
+
+    match id {
+        Some(id) if id == current_task() => crate::preempt::restart_current_task(),
+        Some(id) => crate::preempt::restart_task(id),
+        None => warn!("task {:x} to delete isn't running", task_func),
+    }
+}
+
+fn forget_running_task() {
+    blocking_checks::with(|cs| {
+        RUNNING_TASKS
+            .borrow_ref_mut(cs)
+            .retain(|(id, _)| *id != current_task())
+    });
+}
+
 /// This function runs a single C task started by the wifi stack.
 pub(crate) extern "C" fn run_c_task() {
+    // A restarted task no longer runs what it ran before
+    forget_running_task();
+
     loop {
         // Take a task and run it.
-        if let Some((f, p)) = critical_section::with(|_| unsafe { TASK_SPAWN_QUEUE.dequeue() }) {
+        if let Some((f, p)) = blocking_checks::with(|cs| {
+            let task = unsafe { TASK_SPAWN_QUEUE.dequeue() };
+            if let Some((f, _)) = task {
+                // there is one entry per task running C tasks
+                let _ = RUNNING_TASKS
+                    .borrow_ref_mut(cs)
+                    .push((current_task(), f as usize));
+            }
+            task
+        }) {
             debug!("task started: {:?} {:?}", f, p);
             f(p);
             debug!("task finished: {:?} {:?}", f, p);
+            forget_running_task();
         }
         yield_task();
     }
@@ -2,6 +2,7 @@ use crate::binary::{
     c_types,
     include::{esp_timer_create_args_t, esp_timer_handle_t, ets_timer},
 };
+use crate::compat::blocking_checks;
 
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct TimerCallback {
@@ -58,7 +59,7 @@ pub fn compat_timer_arm_us(ets_timer: *mut ets_timer, us: u32, repeat: bool) {
         ets_timer as usize, systick, ticks, repeat
     );
 
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         if let Some(timer) = TIMERS.iter_mut().find(|t| t.ets_timer == ets_timer) {
             timer.started = systick;
             timer.timeout = ticks;
@@ -71,7 +72,7 @@ pub fn compat_timer_arm_us(ets_timer: *mut ets_timer, us: u32, repeat: bool) {
 }
 
 pub fn compat_timer_disarm(ets_timer: *mut ets_timer) {
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         if let Some(timer) = TIMERS.iter_mut().find(|t| t.ets_timer == ets_timer) {
             debug!("timer_disarm {:x}", timer.id());
             timer.active = false;
@@ -82,10 +83,11 @@ pub fn compat_timer_disarm(ets_timer: *mut ets_timer) {
 }
 
 pub fn compat_timer_done(ets_timer: *mut ets_timer) {
-    critical_section::with(|_| unsafe {
-        if let Some(timer) = TIMERS.iter_mut().find(|t| t.ets_timer == ets_timer) {
-            debug!("timer_done {:x}", timer.id());
-            timer.active = false;
+    blocking_checks::with(|_| unsafe {
+        if let Some(idx) = TIMERS.iter().position(|t| t.ets_timer == ets_timer) {
+            debug!("timer_done {:x}", TIMERS[idx].id());
+            // The driver might free the timer after this, so it mustn't stay in the list
+            TIMERS.swap_remove(idx);
 
             (*ets_timer).priv_ = core::ptr::null_mut();
             (*ets_timer).expire = 0;
@@ -105,7 +107,7 @@ pub fn compat_timer_setfn(
         ets_timer as usize, pfunction, parg
     );
 
-    let set = critical_section::with(|_| unsafe {
+    let set = blocking_checks::with(|_| unsafe {
         if let Some(timer) = TIMERS.iter_mut().find(|t| t.ets_timer == ets_timer) {
             timer.callback = TimerCallback::new(pfunction, parg);
             timer.active = false;
@@ -144,7 +146,7 @@ pub fn compat_esp_timer_create(
         debug!("esp_timer_create {:?} {:?}", (*args).callback, (*args).arg);
     }
 
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         if TIMERS.is_full() {
             // TODO: should we return -1 instead?
             panic!("ran out of timers");
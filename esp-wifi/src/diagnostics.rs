@@ -0,0 +1,141 @@
+//! Periodic snapshots of the driver's internals for post-mortem analysis.
+//!
+//! With the `diagnostics` feature the scheduler records a [`Sample`] of the RX queue depths,
+//! the frames in flight, the free heap and the number of events every
+//! [`set_sample_interval`] milliseconds into a ring buffer in RAM. The ring buffer keeps the
+//! last `diagnostics_history_len` samples (set in `cfg.toml`, 16 by default).
+//!
+//! A single counter read rarely explains an intermittent throughput collapse, the history
+//! shows how the driver got there, e.g. a full RX queue which isn't drained because the
+//! application's task doesn't get to run.
+//!
+//! ```ignore
+//! let diagnostics = esp_wifi::diagnostics::snapshot();
+//! for sample in diagnostics.history.iter() {
+//!     println!("{:?}", sample);
+//! }
+//! ```
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use heapless::HistoryBuffer;
+use portable_atomic::{AtomicU32, AtomicU64, Ordering};
+
+use crate::compat::blocking_checks;
+use crate::timer::{get_systimer_count, millis_to_ticks, ticks_to_millis};
+use crate::wifi::{DATA_QUEUE_RX_AP, DATA_QUEUE_RX_STA, WIFI_TX_INFLIGHT};
+
+/// Number of samples kept, set by `diagnostics_history_len` in `cfg.toml`.
+pub const HISTORY_LEN: usize = crate::CONFIG.diagnostics_history_len;
+
+/// Compact snapshot of the driver's internals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample {
+    /// Time of the sample, see [`crate::current_millis`]
+    pub timestamp_ms: u64,
+    /// Frames waiting in the station's RX queue
+    pub rx_queue_sta: usize,
+    /// Frames waiting in the access point's RX queue
+    pub rx_queue_ap: usize,
+    /// Frames handed to the driver for transmission which it hasn't freed yet
+    pub tx_inflight: usize,
+    /// Free bytes in the esp-wifi heap, see [`crate::heap_free`]
+    pub heap_free: usize,
+    /// Events posted by the driver since the previous sample
+    pub events: u32,
+}
+
+/// The current state and the recorded history, see [`snapshot`].
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// Sample taken when the snapshot was created, its `events` count the events since the last
+    /// recorded sample
+    pub current: Sample,
+    /// Recorded samples, the oldest first
+    pub history: heapless::Vec<Sample, HISTORY_LEN>,
+}
+
+static HISTORY: Mutex<RefCell<HistoryBuffer<Sample, HISTORY_LEN>>> =
+    Mutex::new(RefCell::new(HistoryBuffer::new()));
+
+/// Interval between samples in timer ticks, 0 disables sampling.
+static SAMPLE_INTERVAL_TICKS: AtomicU64 = AtomicU64::new(
+    crate::CONFIG.diagnostics_interval_ms as u64 * (crate::timer::TICKS_PER_SECOND / 1_000),
+);
+
+static NEXT_SAMPLE_AT: AtomicU64 = AtomicU64::new(0);
+
+/// Events posted by the driver since the last recorded sample.
+static EVENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Sets the interval between samples in milliseconds, 0 stops sampling.
+///
+/// The default is `diagnostics_interval_ms` from `cfg.toml`, 100 ms unless set. Samples are
+/// taken when the scheduler switches tasks, so the interval is rounded up to the next task
+/// switch.
+pub fn set_sample_interval(interval_ms: u32) {
+    SAMPLE_INTERVAL_TICKS.store(millis_to_ticks(interval_ms as u64), Ordering::Relaxed);
+    NEXT_SAMPLE_AT.store(0, Ordering::Relaxed);
+}
+
+/// Returns the recorded samples, the oldest first.
+pub fn history() -> heapless::Vec<Sample, HISTORY_LEN> {
+    blocking_checks::with(|cs| HISTORY.borrow_ref(cs).oldest_ordered().copied().collect())
+}
+
+/// Returns the current state of the driver together with the recorded history.
+pub fn snapshot() -> Diagnostics {
+    Diagnostics {
+        current: take_sample(EVENTS.load(Ordering::Relaxed)),
+        history: history(),
+    }
+}
+
+/// Forgets the recorded samples.
+pub fn clear_history() {
+    blocking_checks::with(|cs| HISTORY.borrow_ref_mut(cs).clear());
+}
+
+pub(crate) fn record_event() {
+    EVENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn take_sample(events: u32) -> Sample {
+    let (rx_queue_sta, rx_queue_ap, heap_free) = blocking_checks::with(|cs| {
+        (
+            DATA_QUEUE_RX_STA.borrow_ref(cs).len(),
+            DATA_QUEUE_RX_AP.borrow_ref(cs).len(),
+            crate::HEAP.borrow_ref(cs).free(),
+        )
+    });
+
+    Sample {
+        timestamp_ms: ticks_to_millis(get_systimer_count()),
+        rx_queue_sta,
+        rx_queue_ap,
+        tx_inflight: WIFI_TX_INFLIGHT.load(Ordering::Relaxed),
+        heap_free,
+        events,
+    }
+}
+
+/// Records a sample if the interval elapsed, called by the scheduler on every task switch.
+///
+/// This only reads a few counters so it's cheap enough to run in the scheduler's interrupt.
+pub(crate) fn sample_if_due() {
+    let interval = SAMPLE_INTERVAL_TICKS.load(Ordering::Relaxed);
+    if interval == 0 {
+        return;
+    }
+
+    let now = get_systimer_count();
+    if now < NEXT_SAMPLE_AT.load(Ordering::Relaxed) {
+        return;
+    }
+    NEXT_SAMPLE_AT.store(now + interval, Ordering::Relaxed);
+
+    let sample = take_sample(EVENTS.swap(0, Ordering::Relaxed));
+    blocking_checks::with(|cs| HISTORY.borrow_ref_mut(cs).write(sample));
+}
@@ -13,6 +13,7 @@ use portable_atomic::{AtomicBool, AtomicU8, Ordering};
 
 use critical_section::Mutex;
 
+use crate::compat::blocking_checks;
 use crate::compat::queue::SimpleQueue;
 use crate::hal::peripheral::{Peripheral, PeripheralRef};
 use crate::EspWifiInitialization;
@@ -25,6 +26,9 @@ pub const ESP_NOW_MAX_DATA_LEN: usize = 250;
 /// Broadcast address
 pub const BROADCAST_ADDRESS: [u8; 6] = [0xffu8, 0xffu8, 0xffu8, 0xffu8, 0xffu8, 0xffu8];
 
+/// Maximum number of encrypted peers, 2 on the ESP32-C2 and 7 on the other chips
+pub const MAX_ENCRYPTED_PEERS: usize = crate::wifi::ESPNOW_MAX_ENCRYPT_NUM as usize;
+
 static RECEIVE_QUEUE: Mutex<RefCell<SimpleQueue<ReceivedData, 10>>> =
     Mutex::new(RefCell::new(SimpleQueue::new()));
 /// This atomic behaves like a guard, so we need strict memory ordering when
@@ -82,6 +86,8 @@ pub enum EspNowError {
     SendFailed,
     /// Attempt to create EspNow instance twice
     DuplicateInstance,
+    /// There are already [`MAX_ENCRYPTED_PEERS`] encrypted peers
+    TooManyEncryptedPeers,
 }
 
 #[derive(Debug)]
@@ -91,81 +97,10 @@ pub struct PeerCount {
     pub encrypted_count: i32,
 }
 
-#[repr(u32)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum WifiPhyRate {
-    /// < 1 Mbps with long preamble
-    Rate1mL = 0,
-    /// < 2 Mbps with long preamble
-    Rate2m,
-    /// < 5.5 Mbps with long preamble
-    Rate5mL,
-    /// < 11 Mbps with long preamble
-    Rate11mL,
-    /// < 2 Mbps with short preamble
-    Rate2mS,
-    /// < 5.5 Mbps with short preamble
-    Rate5mS,
-    /// < 11 Mbps with short preamble
-    Rate11mS,
-    /// < 48 Mbps
-    Rate48m,
-    /// < 24 Mbps
-    Rate24m,
-    /// < 12 Mbps
-    Rate12m,
-    /// < 6 Mbps
-    Rate6m,
-    /// < 54 Mbps
-    Rate54m,
-    /// < 36 Mbps
-    Rate36m,
-    /// < 18 Mbps
-    Rate18m,
-    /// < 9 Mbps
-    Rate9m,
-    /// < MCS0 with long GI, 6.5 Mbps for 20MHz, 13.5 Mbps for 40MHz
-    RateMcs0Lgi,
-    /// < MCS1 with long GI, 13 Mbps for 20MHz, 27 Mbps for 40MHz
-    RateMcs1Lgi,
-    /// < MCS2 with long GI, 19.5 Mbps for 20MHz, 40.5 Mbps for 40MHz
-    RateMcs2Lgi,
-    /// < MCS3 with long GI, 26 Mbps for 20MHz, 54 Mbps for 40MHz
-    RateMcs3Lgi,
-    /// < MCS4 with long GI, 39 Mbps for 20MHz, 81 Mbps for 40MHz
-    RateMcs4Lgi,
-    /// < MCS5 with long GI, 52 Mbps for 20MHz, 108 Mbps for 40MHz
-    RateMcs5Lgi,
-    /// < MCS6 with long GI, 58.5 Mbps for 20MHz, 121.5 Mbps for 40MHz
-    RateMcs6Lgi,
-    /// < MCS7 with long GI, 65 Mbps for 20MHz, 135 Mbps for 40MHz
-    RateMcs7Lgi,
-    /// < MCS0 with short GI, 7.2 Mbps for 20MHz, 15 Mbps for 40MHz
-    RateMcs0Sgi,
-    /// < MCS1 with short GI, 14.4 Mbps for 20MHz, 30 Mbps for 40MHz
-    RateMcs1Sgi,
-    /// < MCS2 with short GI, 21.7 Mbps for 20MHz, 45 Mbps for 40MHz
-    RateMcs2Sgi,
-    /// < MCS3 with short GI, 28.9 Mbps for 20MHz, 60 Mbps for 40MHz
-    RateMcs3Sgi,
-    /// < MCS4 with short GI, 43.3 Mbps for 20MHz, 90 Mbps for 40MHz
-    RateMcs4Sgi,
-    /// < MCS5 with short GI, 57.8 Mbps for 20MHz, 120 Mbps for 40MHz
-    RateMcs5Sgi,
-    /// < MCS6 with short GI, 65 Mbps for 20MHz, 135 Mbps for 40MHz
-    RateMcs6Sgi,
-    /// < MCS7 with short GI, 72.2 Mbps for 20MHz, 150 Mbps for 40MHz
-    RateMcs7Sgi,
-    /// < 250 Kbps
-    RateLora250k,
-    /// < 500 Kbps
-    RateLora500k,
-    /// Max
-    RateMax,
-}
+// Kept here for code written before `WifiPhyRate` moved to the `wifi` module
+pub use crate::wifi::WifiPhyRate;
 
-#[derive(Debug, Clone, Copy)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy)]
 pub struct PeerInfo {
     pub peer_address: [u8; 6],
     pub lmk: Option<[u8; 16]>,
@@ -174,6 +109,32 @@ pub struct PeerInfo {
     // we always use STA for now
 }
 
+// The local master key is a secret, so it's redacted when formatting
+impl Debug for PeerInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PeerInfo")
+            .field("peer_address", &self.peer_address)
+            .field("lmk", &self.lmk.map(|_| "<redacted>"))
+            .field("channel", &self.channel)
+            .field("encrypt", &self.encrypt)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PeerInfo {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "PeerInfo {{ peer_address: {}, lmk: {}, channel: {}, encrypt: {} }}",
+            self.peer_address,
+            self.lmk.map(|_| "<redacted>"),
+            self.channel,
+            self.encrypt
+        )
+    }
+}
+
 #[cfg(not(any(esp32c6)))]
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -295,6 +256,51 @@ impl<'d> EspNowManager<'d> {
         check_error!({ esp_now_add_peer(&raw_peer as *const _) })
     }
 
+    /// Adds a peer which exchanges encrypted frames, using `lmk` as its local master key.
+    ///
+    /// The local master keys are encrypted with the primary master key, so both sides need the
+    /// same one set with [`Self::set_pmk`] before adding the peer. Fails with
+    /// [`EspNowError::TooManyEncryptedPeers`] if there are [`MAX_ENCRYPTED_PEERS`] encrypted
+    /// peers already.
+    pub fn add_encrypted_peer(
+        &self,
+        peer_address: [u8; 6],
+        lmk: [u8; 16],
+        channel: Option<u8>,
+    ) -> Result<(), EspNowError> {
+        if self.peer_count()?.encrypted_count as usize >= MAX_ENCRYPTED_PEERS {
+            warn!(
+                "ESP-NOW supports only {} encrypted peers",
+                MAX_ENCRYPTED_PEERS
+            );
+            return Err(EspNowError::TooManyEncryptedPeers);
+        }
+
+        self.add_peer(PeerInfo {
+            peer_address,
+            lmk: Some(lmk),
+            channel,
+            encrypt: true,
+        })
+    }
+
+    /// Adds the broadcast peer unless it exists, which is needed to send to
+    /// [`BROADCAST_ADDRESS`].
+    ///
+    /// It's added when ESP-NOW is initialized, this restores it after it was removed.
+    pub fn add_broadcast_peer(&self) -> Result<(), EspNowError> {
+        if self.peer_exists(&BROADCAST_ADDRESS) {
+            return Ok(());
+        }
+
+        self.add_peer(PeerInfo {
+            peer_address: BROADCAST_ADDRESS,
+            lmk: None,
+            channel: None,
+            encrypt: false,
+        })
+    }
+
     /// Remove the given peer
     pub fn remove_peer(&self, peer_address: &[u8; 6]) -> Result<(), EspNowError> {
         check_error!({ esp_now_del_peer(peer_address.as_ptr()) })
@@ -431,6 +437,33 @@ impl<'d> EspNowSender<'d> {
         check_error!({ esp_now_send(dst_addr.as_ptr(), data.as_ptr(), data.len()) })?;
         Ok(SendWaiter(PhantomData))
     }
+
+    /// Send data to every device in range, see [`EspNowManager::add_broadcast_peer`]
+    ///
+    /// Broadcasts are never encrypted and not acknowledged, so waiting for the result only tells
+    /// whether the frame was sent.
+    pub fn broadcast<'s>(&'s mut self, data: &[u8]) -> Result<SendWaiter<'s>, EspNowError> {
+        self.send(&BROADCAST_ADDRESS, data)
+    }
+
+    /// Sends data to a peer and waits for the result, favoring WiFi in the coexistence arbiter
+    /// until the frame is sent.
+    ///
+    /// See [`crate::coex::wifi_priority_window`], the frame is only sent once the window took
+    /// effect, which can take up to 100 ms. Without coexistence this is the same as
+    /// [`Self::send`] followed by [`SendWaiter::wait`].
+    pub fn send_with_wifi_priority(
+        &mut self,
+        dst_addr: &[u8; 6],
+        data: &[u8],
+    ) -> Result<(), EspNowError> {
+        let window = crate::coex::wifi_priority_window().ok();
+        if let Some(window) = &window {
+            window.wait_until_effective();
+        }
+
+        self.send(dst_addr, data)?.wait()
+    }
 }
 
 /// This struct is returned by a sync esp now send. Invoking `wait` method of this
@@ -475,7 +508,7 @@ pub struct EspNowReceiver<'d>(EspNowRc<'d>);
 
 impl<'d> EspNowReceiver<'d> {
     pub fn receive(&self) -> Option<ReceivedData> {
-        critical_section::with(|cs| {
+        blocking_checks::with(|cs| {
             let mut queue = RECEIVE_QUEUE.borrow_ref_mut(cs);
             queue.dequeue()
         })
@@ -575,27 +608,12 @@ impl<'d> EspNow<'d> {
         check_error!({ esp_wifi_set_mode(wifi_mode_t_WIFI_MODE_STA) })?;
         check_error!({ esp_wifi_start() })?;
         check_error!({
-            esp_wifi_set_inactive_time(wifi_interface_t_WIFI_IF_STA, crate::CONFIG.beacon_timeout)
+            esp_wifi_set_inactive_time(
+                wifi_interface_t_WIFI_IF_STA,
+                crate::esp_wifi_config().beacon_timeout,
+            )
         })?;
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "ps-min-modem")] {
-                check_error!({esp_wifi_set_ps(
-                    crate::binary::include::wifi_ps_type_t_WIFI_PS_MIN_MODEM
-                )})?;
-            } else if #[cfg(feature = "ps-max-modem")] {
-                check_error!({esp_wifi_set_ps(
-                    crate::binary::include::wifi_ps_type_t_WIFI_PS_MAX_MODEM
-                )})?;
-            } else if #[cfg(coex)] {
-                check_error!({esp_wifi_set_ps(
-                    crate::binary::include::wifi_ps_type_t_WIFI_PS_MIN_MODEM
-                )})?;
-            } else {
-                check_error!({esp_wifi_set_ps(
-                    crate::binary::include::wifi_ps_type_t_WIFI_PS_NONE
-                )})?;
-            }
-        };
+        check_error!({ esp_wifi_set_ps(crate::esp_wifi_config().power_save_mode.to_raw()) })?;
         check_error!({ esp_now_init() })?;
         check_error!({ esp_now_register_recv_cb(Some(rcv_cb)) })?;
         check_error!({ esp_now_register_send_cb(Some(send_cb)) })?;
@@ -630,6 +648,21 @@ impl<'d> EspNow<'d> {
         self.manager.add_peer(peer)
     }
 
+    /// Adds a peer which exchanges encrypted frames, see [`EspNowManager::add_encrypted_peer`]
+    pub fn add_encrypted_peer(
+        &self,
+        peer_address: [u8; 6],
+        lmk: [u8; 16],
+        channel: Option<u8>,
+    ) -> Result<(), EspNowError> {
+        self.manager.add_encrypted_peer(peer_address, lmk, channel)
+    }
+
+    /// Adds the broadcast peer unless it exists, see [`EspNowManager::add_broadcast_peer`]
+    pub fn add_broadcast_peer(&self) -> Result<(), EspNowError> {
+        self.manager.add_broadcast_peer()
+    }
+
     /// Remove the given peer
     pub fn remove_peer(&self, peer_address: &[u8; 6]) -> Result<(), EspNowError> {
         self.manager.remove_peer(peer_address)
@@ -691,6 +724,21 @@ impl<'d> EspNow<'d> {
         self.sender.send(dst_addr, data)
     }
 
+    /// Send data to every device in range, see [`EspNowSender::broadcast`]
+    pub fn broadcast<'s>(&'s mut self, data: &[u8]) -> Result<SendWaiter<'s>, EspNowError> {
+        self.sender.broadcast(data)
+    }
+
+    /// Sends data to a peer and waits for the result, favoring WiFi in the coexistence arbiter
+    /// until the frame is sent, see [`EspNowSender::send_with_wifi_priority`].
+    pub fn send_with_wifi_priority(
+        &mut self,
+        dst_addr: &[u8; 6],
+        data: &[u8],
+    ) -> Result<(), EspNowError> {
+        self.sender.send_with_wifi_priority(dst_addr, data)
+    }
+
     /// Receive data
     pub fn receive(&self) -> Option<ReceivedData> {
         self.receiver.receive()
@@ -698,7 +746,7 @@ impl<'d> EspNow<'d> {
 }
 
 unsafe extern "C" fn send_cb(_mac_addr: *const u8, status: esp_now_send_status_t) {
-    critical_section::with(|_| {
+    blocking_checks::with(|_| {
         let is_success = status == esp_now_send_status_t_ESP_NOW_SEND_SUCCESS;
         ESP_NOW_SEND_STATUS.store(is_success, Ordering::Relaxed);
 
@@ -786,7 +834,7 @@ unsafe extern "C" fn rcv_cb(
         rx_control,
     };
     let slice = core::slice::from_raw_parts(data, data_len as usize);
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         let mut queue = RECEIVE_QUEUE.borrow_ref_mut(cs);
         let mut data = [0u8; 256];
         data[..slice.len()].copy_from_slice(slice);
@@ -839,6 +887,22 @@ mod asynch {
                 sent: false,
             }
         }
+
+        /// Async version of [`EspNowSender::broadcast`]
+        pub fn broadcast_async<'s, 'r>(&'s mut self, data: &'r [u8]) -> SendFuture<'s, 'r> {
+            self.send_async(&BROADCAST_ADDRESS, data)
+        }
+
+        /// Like [`Self::send_async`], favoring WiFi in the coexistence arbiter until the frame
+        /// is sent, see [`crate::coex::wifi_priority_window`].
+        pub async fn send_with_wifi_priority_async(
+            &mut self,
+            addr: &[u8; 6],
+            data: &[u8],
+        ) -> Result<(), EspNowError> {
+            let _window = crate::coex::wifi_priority_window().ok();
+            self.send_async(addr, data).await
+        }
     }
 
     impl<'d> EspNow<'d> {
@@ -859,6 +923,25 @@ mod asynch {
         ) -> SendFuture<'s, 'r> {
             self.sender.send_async(dst_addr, data)
         }
+
+        /// Async version of [`EspNow::broadcast`], the returned future must not be dropped
+        /// before it's ready either.
+        #[must_use]
+        pub fn broadcast_async<'s, 'r>(&'s mut self, data: &'r [u8]) -> SendFuture<'s, 'r> {
+            self.sender.broadcast_async(data)
+        }
+
+        /// Like [`Self::send_async`], favoring WiFi in the coexistence arbiter until the frame
+        /// is sent, see [`EspNowSender::send_with_wifi_priority_async`].
+        pub async fn send_with_wifi_priority_async(
+            &mut self,
+            dst_addr: &[u8; 6],
+            data: &[u8],
+        ) -> Result<(), EspNowError> {
+            self.sender
+                .send_with_wifi_priority_async(dst_addr, data)
+                .await
+        }
     }
 
     pub struct SendFuture<'s, 'r> {
@@ -905,7 +988,7 @@ mod asynch {
         fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
             ESP_NOW_RX_WAKER.register(cx.waker());
 
-            if let Some(data) = critical_section::with(|cs| {
+            if let Some(data) = blocking_checks::with(|cs| {
                 let mut queue = RECEIVE_QUEUE.borrow_ref_mut(cs);
                 queue.dequeue()
             }) {
@@ -84,6 +84,7 @@ macro_rules! todo {
 macro_rules! unreachable {
     ($($x:tt)*) => {
         {
+            $crate::fmt::dump_state();
             #[cfg(not(feature = "defmt"))]
             ::core::unreachable!($($x)*);
             #[cfg(feature = "defmt")]
@@ -95,6 +96,7 @@ macro_rules! unreachable {
 macro_rules! panic {
     ($($x:tt)*) => {
         {
+            $crate::fmt::dump_state();
             #[cfg(not(feature = "defmt"))]
             ::core::panic!($($x)*);
             #[cfg(feature = "defmt")]
@@ -168,19 +170,27 @@ macro_rules! error {
     };
 }
 
-#[cfg(feature = "defmt")]
+#[cfg(all(feature = "defmt", not(feature = "dump-state-on-panic")))]
 macro_rules! unwrap {
     ($($x:tt)*) => {
         ::defmt::unwrap!($($x)*)
     };
 }
 
+#[cfg(all(feature = "defmt", feature = "dump-state-on-panic"))]
+macro_rules! unwrap {
+    ($arg:expr $(, $($msg:tt)*)?) => {
+        ::defmt::unwrap!($crate::fmt::dump_state_on_err($arg) $(, $($msg)*)?)
+    };
+}
+
 #[cfg(not(feature = "defmt"))]
 macro_rules! unwrap {
     ($arg:expr) => {
         match $crate::fmt::Try::into_result($arg) {
             ::core::result::Result::Ok(t) => t,
             ::core::result::Result::Err(e) => {
+                $crate::fmt::dump_state();
                 ::core::panic!("unwrap of `{}` failed: {:?}", ::core::stringify!($arg), e);
             }
         }
@@ -189,6 +199,7 @@ macro_rules! unwrap {
         match $crate::fmt::Try::into_result($arg) {
             ::core::result::Result::Ok(t) => t,
             ::core::result::Result::Err(e) => {
+                $crate::fmt::dump_state();
                 ::core::panic!("unwrap of `{}` failed: {}: {:?}", ::core::stringify!($arg), ::core::format_args!($($msg,)*), e);
             }
         }
@@ -196,6 +207,7 @@ macro_rules! unwrap {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct NoneError;
 
 pub trait Try {
@@ -223,3 +235,21 @@ impl<T, E> Try for Result<T, E> {
         self
     }
 }
+
+/// Logs the state of the driver right before the crate panics internally.
+///
+/// Does nothing unless the `dump-state-on-panic` feature is enabled.
+#[inline(always)]
+pub(crate) fn dump_state() {
+    #[cfg(all(feature = "dump-state-on-panic", feature = "wifi"))]
+    crate::wifi::dump_state();
+}
+
+#[allow(unused)]
+pub(crate) fn dump_state_on_err<T: Try>(value: T) -> Result<T::Ok, T::Error> {
+    let result = value.into_result();
+    if result.is_err() {
+        dump_state();
+    }
+    result
+}
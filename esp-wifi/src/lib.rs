@@ -13,7 +13,9 @@ use core::cell::RefCell;
 use core::mem::MaybeUninit;
 
 use common_adapter::RADIO_CLOCKS;
+use compat::blocking_checks;
 use critical_section::Mutex;
+use portable_atomic::{AtomicBool, Ordering};
 
 #[cfg(esp32)]
 use esp32_hal as hal;
@@ -30,9 +32,6 @@ use esp32s2_hal as hal;
 #[cfg(esp32s3)]
 use esp32s3_hal as hal;
 
-#[cfg(any(esp32c2, esp32c3, esp32c6, esp32h2))]
-use hal::systimer::{Alarm, Target};
-
 use common_adapter::init_radio_clock_control;
 use hal::system::RadioClockController;
 
@@ -42,7 +41,7 @@ use linked_list_allocator::Heap;
 #[cfg(feature = "wifi")]
 use wifi::WifiError;
 
-use crate::common_adapter::init_rng;
+use crate::common_adapter::{init_entropy_source, init_rng};
 use crate::tasks::init_tasks;
 use crate::timer::setup_timer_isr;
 use common_adapter::chip_specific::phy_mem_init;
@@ -64,6 +63,11 @@ pub mod ble;
 #[cfg(feature = "esp-now")]
 pub mod esp_now;
 
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+
+pub mod coex;
+
 pub(crate) mod common_adapter;
 
 #[doc(hidden)]
@@ -71,7 +75,6 @@ pub mod tasks;
 
 pub(crate) mod memory_fence;
 
-use critical_section;
 use timer::{get_systimer_count, ticks_to_millis};
 
 #[cfg(all(feature = "wifi", any(feature = "tcp", feature = "udp")))]
@@ -137,6 +140,246 @@ struct Config {
     failure_retry_cnt: u8,
     #[default(0)]
     scan_method: u32,
+    #[default(16)]
+    diagnostics_history_len: usize,
+    #[default(100)]
+    diagnostics_interval_ms: u32,
+}
+
+/// Runtime configuration of the WiFi driver, see [`initialize_with_config`].
+///
+/// The defaults are taken from the build time configuration (`cfg.toml`). Queue sizes, the MTU
+/// and the heap size can only be set at build time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EspWifiConfig {
+    /// WiFi static RX buffer number, 2 to 25
+    pub static_rx_buf_num: usize,
+    /// WiFi dynamic RX buffer number, up to 1024
+    pub dynamic_rx_buf_num: usize,
+    /// WiFi static TX buffer number, up to 64
+    pub static_tx_buf_num: usize,
+    /// WiFi dynamic TX buffer number, 1 to 128
+    pub dynamic_tx_buf_num: usize,
+    /// Enable AMPDU RX
+    pub ampdu_rx_enable: bool,
+    /// Enable AMPDU TX
+    pub ampdu_tx_enable: bool,
+    /// Enable AMSDU TX
+    pub amsdu_tx_enable: bool,
+    /// Block Ack RX window size, 2 to 32
+    pub rx_ba_win: usize,
+    /// Two letter country code
+    pub country_code: &'static str,
+    /// Third octet of the country string: `b' '` (or 0) for any environment, `b'O'` outdoor,
+    /// `b'I'` indoor, `b'X'` non-country entity, or an Operating Class table number below 32
+    pub country_code_operating_class: u8,
+    /// Interval for the station to listen to beacons, in beacon intervals
+    pub listen_interval: u16,
+    /// Seconds without beacons after which the station disconnects, 6 to 30
+    pub beacon_timeout: u16,
+    /// Seconds without data after which the access point deauthenticates a station
+    pub ap_beacon_timeout: u16,
+    /// Number of connection retries before the station moves on to the next AP
+    pub failure_retry_cnt: u8,
+    /// 0 = WIFI_FAST_SCAN, 1 = WIFI_ALL_CHANNEL_SCAN
+    pub scan_method: u32,
+    /// Tick rate of the internal task scheduler in hertz, 10 to 1000
+    ///
+    /// Higher rates make the driver react faster but take more CPU time away from the
+    /// application, see `docs/tuning.md`.
+    pub tick_rate_hz: u32,
+    /// Core the scheduler, the driver's tasks and the radio interrupts run on, 0 or 1 on dual
+    /// core chips and 0 otherwise
+    ///
+    /// Everything esp-wifi does runs on the core which calls the initialization function, so it
+    /// has to be called on this core.
+    pub core_id: u8,
+    /// Priority of the WiFi MAC and power interrupts, from 1 (lowest) up to 3 on Xtensa and 15 on
+    /// RISC-V chips
+    ///
+    /// Other interrupts with a higher priority aren't delayed by the WiFi interrupts, but the
+    /// lower their priority the more likely received frames are dropped, see `docs/tuning.md`.
+    #[cfg(feature = "wifi")]
+    pub wifi_interrupt_priority: u8,
+    /// Power save mode of the station
+    #[cfg(feature = "wifi")]
+    pub power_save_mode: wifi::PowerSaveMode,
+    /// Let the driver keep its state, e.g. the station and access point configuration, in NVS
+    ///
+    /// Needs a storage registered with [`wifi::nvs::set_storage`], initialization fails
+    /// otherwise.
+    #[cfg(feature = "wifi")]
+    pub nvs_enable: bool,
+    /// Let the driver set up channel state information (CSI) support
+    ///
+    /// Applications which never use CSI can turn this off, see `docs/tuning.md`.
+    #[cfg(feature = "wifi")]
+    pub csi_enable: bool,
+}
+
+impl EspWifiConfig {
+    /// Returns the build time configuration.
+    pub const fn new() -> Self {
+        Self {
+            static_rx_buf_num: CONFIG.static_rx_buf_num,
+            dynamic_rx_buf_num: CONFIG.dynamic_rx_buf_num,
+            static_tx_buf_num: CONFIG.static_tx_buf_num,
+            dynamic_tx_buf_num: CONFIG.dynamic_tx_buf_num,
+            ampdu_rx_enable: CONFIG.ampdu_rx_enable != 0,
+            ampdu_tx_enable: CONFIG.ampdu_tx_enable != 0,
+            amsdu_tx_enable: CONFIG.amsdu_tx_enable != 0,
+            rx_ba_win: CONFIG.rx_ba_win,
+            country_code: CONFIG.country_code,
+            country_code_operating_class: CONFIG.country_code_operating_class,
+            listen_interval: CONFIG.listen_interval,
+            beacon_timeout: CONFIG.beacon_timeout,
+            ap_beacon_timeout: CONFIG.ap_beacon_timeout,
+            failure_retry_cnt: CONFIG.failure_retry_cnt,
+            scan_method: CONFIG.scan_method,
+            tick_rate_hz: CONFIG.tick_rate_hz,
+            core_id: 0,
+            #[cfg(feature = "wifi")]
+            wifi_interrupt_priority: 1,
+            #[cfg(feature = "wifi")]
+            power_save_mode: wifi::PowerSaveMode::default_for_features(),
+            #[cfg(feature = "wifi")]
+            nvs_enable: false,
+            #[cfg(feature = "wifi")]
+            csi_enable: true,
+        }
+    }
+
+    /// Checks all values are in the range accepted by the driver.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(2..=25).contains(&self.static_rx_buf_num)
+            || self.dynamic_rx_buf_num > 1024
+            || self.static_tx_buf_num > 64
+            || !(1..=128).contains(&self.dynamic_tx_buf_num)
+        {
+            return Err(ConfigError::InvalidBufferCounts);
+        }
+
+        if !(2..=32).contains(&self.rx_ba_win) {
+            return Err(ConfigError::InvalidRxBaWindow);
+        }
+
+        if self.country_code.len() != 2
+            || !self
+                .country_code
+                .bytes()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        {
+            return Err(ConfigError::InvalidCountryCode);
+        }
+
+        if !matches!(
+            self.country_code_operating_class,
+            0..=31 | b' ' | b'O' | b'I' | b'X'
+        ) {
+            return Err(ConfigError::InvalidOperatingClass);
+        }
+
+        if self.listen_interval == 0 {
+            return Err(ConfigError::InvalidListenInterval);
+        }
+
+        if !(6..=30).contains(&self.beacon_timeout) || self.ap_beacon_timeout == 0 {
+            return Err(ConfigError::InvalidBeaconTimeout);
+        }
+
+        if self.scan_method > 1 {
+            return Err(ConfigError::InvalidScanMethod);
+        }
+
+        if !(10..=1000).contains(&self.tick_rate_hz) {
+            return Err(ConfigError::InvalidTickRate);
+        }
+
+        #[cfg(any(esp32, esp32s3))]
+        const MAX_CORE_ID: u8 = 1;
+        #[cfg(not(any(esp32, esp32s3)))]
+        const MAX_CORE_ID: u8 = 0;
+
+        if self.core_id > MAX_CORE_ID {
+            return Err(ConfigError::InvalidCoreId);
+        }
+
+        #[cfg(feature = "wifi")]
+        wifi::interrupt_priority(self.wifi_interrupt_priority)?;
+
+        // The build time settings can't be overridden, check them here anyway so a bad `cfg.toml`
+        // is reported at init time
+        if !(576..=1500).contains(&CONFIG.mtu) {
+            return Err(ConfigError::InvalidMtu);
+        }
+
+        // The RX queue holds one frame less than its size
+        if CONFIG.rx_queue_size < 2 || CONFIG.tx_queue_size == 0 {
+            return Err(ConfigError::InvalidQueueSize);
+        }
+
+        // The radio has to be shared with BLE
+        #[cfg(coex)]
+        if self.power_save_mode == wifi::PowerSaveMode::None {
+            return Err(ConfigError::InvalidPowerSaveMode);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for EspWifiConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A field of [`EspWifiConfig`] or of the build time configuration which is out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// One of the RX/TX buffer numbers is out of range
+    InvalidBufferCounts,
+    /// `rx_ba_win` is out of range
+    InvalidRxBaWindow,
+    /// `country_code` is not a two letter country code
+    InvalidCountryCode,
+    /// `country_code_operating_class` is neither an environment nor an Operating Class table
+    InvalidOperatingClass,
+    /// `listen_interval` is zero
+    InvalidListenInterval,
+    /// `beacon_timeout` or `ap_beacon_timeout` is out of range
+    InvalidBeaconTimeout,
+    /// `scan_method` is neither 0 nor 1
+    InvalidScanMethod,
+    /// `tick_rate_hz` is out of range
+    InvalidTickRate,
+    /// `core_id` doesn't exist on this chip
+    InvalidCoreId,
+    /// `wifi_interrupt_priority` can't be used on this chip
+    InvalidInterruptPriority,
+    /// `mtu` in `cfg.toml` is not between 576 and 1500
+    InvalidMtu,
+    /// `rx_queue_size` in `cfg.toml` is less than 2 or `tx_queue_size` is zero
+    InvalidQueueSize,
+    /// Power save can't be disabled when WiFi and BLE are used at the same time
+    InvalidPowerSaveMode,
+}
+
+static ESP_WIFI_CONFIG: Mutex<RefCell<EspWifiConfig>> =
+    Mutex::new(RefCell::new(EspWifiConfig::new()));
+
+/// The configuration passed to [`initialize_with_config`].
+#[allow(unused)]
+pub(crate) fn esp_wifi_config() -> EspWifiConfig {
+    blocking_checks::with(|cs| *ESP_WIFI_CONFIG.borrow_ref(cs))
+}
+
+/// Updates the configuration for changes made at runtime, e.g. so they survive restarting WiFi.
+#[allow(unused)]
+pub(crate) fn update_esp_wifi_config(f: impl FnOnce(&mut EspWifiConfig)) {
+    blocking_checks::with(|cs| f(&mut ESP_WIFI_CONFIG.borrow_ref_mut(cs)))
 }
 
 const HEAP_SIZE: usize = crate::CONFIG.heap_size;
@@ -147,17 +390,14 @@ static mut HEAP_DATA: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEA
 pub(crate) static HEAP: Mutex<RefCell<Heap>> = Mutex::new(RefCell::new(Heap::empty()));
 
 fn init_heap() {
-    critical_section::with(|cs| {
+    blocking_checks::with(|cs| {
         HEAP.borrow_ref_mut(cs)
             .init_from_slice(unsafe { &mut HEAP_DATA })
     });
 }
 
-#[cfg(any(esp32c3, esp32c2, esp32c6, esp32h2))]
-pub(crate) type EspWifiTimer = Alarm<Target, 0>;
-
-#[cfg(any(esp32, esp32s3, esp32s2))]
-pub(crate) type EspWifiTimer = hal::timer::Timer<hal::timer::Timer0<hal::peripherals::TIMG1>>;
+/// The timer driving the scheduler, selected via the `timer-*` features.
+pub(crate) type EspWifiTimer = timer::TimeBase;
 
 #[derive(Debug, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -165,6 +405,30 @@ pub(crate) type EspWifiTimer = hal::timer::Timer<hal::timer::Timer0<hal::periphe
 /// An internal struct designed to make [`EspWifiInitialization`] uncreatable outside of this crate.
 pub struct EspWifiInitializationInternal;
 
+/// A source of random numbers for the WiFi and BLE drivers, see
+/// [`initialize_with_entropy_source`].
+pub trait EntropySource {
+    /// Fills `buf` with random bytes.
+    fn fill(&mut self, buf: &mut [u8]);
+}
+
+impl EntropySource for hal::Rng {
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let bytes = self.random().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// Fills `buf` from the entropy source used by esp-wifi.
+///
+/// This allows sharing the `RNG` peripheral passed to [`initialize`] with the application.
+/// Nothing is written before esp-wifi is initialized.
+pub fn fill_random(buf: &mut [u8]) {
+    common_adapter::fill_random(buf);
+}
+
 #[derive(Debug, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Initialized the driver for WiFi, Bluetooth or both.
@@ -237,6 +501,104 @@ pub fn initialize(
     radio_clocks: hal::system::RadioClockControl,
     clocks: &Clocks,
 ) -> Result<EspWifiInitialization, InitializationError> {
+    initialize_with_config(
+        init_for,
+        timer,
+        rng,
+        radio_clocks,
+        clocks,
+        EspWifiConfig::default(),
+    )
+}
+
+/// Like [`initialize_with_config`] but reuses the RF calibration and the access point of a
+/// previous boot, to reconnect quickly after deep sleep.
+///
+/// `persisted` comes from [`wifi::WifiController::export_warm_start_data`], invalid data results
+/// in a cold start. Connect with [`wifi::WifiController::fast_reconnect_sync`] (or its async
+/// version) to skip the scan, see `docs/tuning.md`.
+#[cfg(feature = "wifi")]
+pub fn initialize_warm(
+    init_for: EspWifiInitFor,
+    timer: EspWifiTimer,
+    rng: hal::Rng,
+    radio_clocks: hal::system::RadioClockControl,
+    clocks: &Clocks,
+    config: EspWifiConfig,
+    persisted: &wifi::WarmStartData,
+) -> Result<EspWifiInitialization, InitializationError> {
+    persisted.restore();
+
+    initialize_with_config(init_for, timer, rng, radio_clocks, clocks, config)
+}
+
+/// Set once the heap, the scheduler and its timer are set up, these stay in place after [`deinit`].
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Initialize for using WiFi and or BLE with a runtime configuration overriding the build time
+/// configuration.
+///
+/// This can only be done once, use [`reinitialize`] to bring up WiFi again after [`deinit`].
+pub fn initialize_with_config(
+    init_for: EspWifiInitFor,
+    timer: EspWifiTimer,
+    rng: hal::Rng,
+    radio_clocks: hal::system::RadioClockControl,
+    clocks: &Clocks,
+    config: EspWifiConfig,
+) -> Result<EspWifiInitialization, InitializationError> {
+    initialize_internal(
+        init_for,
+        timer,
+        || init_rng(rng),
+        radio_clocks,
+        clocks,
+        config,
+    )
+}
+
+/// Like [`initialize_with_config`] but takes the random numbers from `entropy` instead of the
+/// `RNG` peripheral, which stays available to the application.
+///
+/// The WiFi supplicant derives nonces, keys and SAE secrets from these numbers, so `entropy` has
+/// to be a cryptographically secure random number generator. Note that the hardware RNG of the
+/// ESP32 chips only produces true random numbers while the radio is on (or another entropy
+/// source is enabled), see the technical reference manual.
+pub fn initialize_with_entropy_source(
+    init_for: EspWifiInitFor,
+    timer: EspWifiTimer,
+    entropy: &'static mut dyn EntropySource,
+    radio_clocks: hal::system::RadioClockControl,
+    clocks: &Clocks,
+    config: EspWifiConfig,
+) -> Result<EspWifiInitialization, InitializationError> {
+    initialize_internal(
+        init_for,
+        timer,
+        || init_entropy_source(entropy),
+        radio_clocks,
+        clocks,
+        config,
+    )
+}
+
+fn initialize_internal(
+    init_for: EspWifiInitFor,
+    timer: EspWifiTimer,
+    init_entropy: impl FnOnce(),
+    radio_clocks: hal::system::RadioClockControl,
+    clocks: &Clocks,
+    config: EspWifiConfig,
+) -> Result<EspWifiInitialization, InitializationError> {
+    config.validate()?;
+
+    #[cfg(any(esp32, esp32s3))]
+    if hal::get_core() as u8 != config.core_id {
+        return Err(InitializationError::WrongCore);
+    }
+
+    blocking_checks::with(|cs| *ESP_WIFI_CONFIG.borrow_ref_mut(cs) = config);
+
     #[cfg(any(esp32, esp32s3, esp32s2))]
     const MAX_CLOCK: u32 = 240;
 
@@ -266,17 +628,19 @@ pub fn initialize(
     }
 
     info!("esp-wifi configuration {:?}", crate::CONFIG);
+    debug!("esp-wifi runtime configuration {:?}", config);
 
     crate::common_adapter::chip_specific::enable_wifi_power_domain();
 
     init_heap();
     phy_mem_init();
     init_radio_clock_control(radio_clocks);
-    init_rng(rng);
+    init_entropy();
     init_tasks();
     setup_timer_isr(timer);
     wifi_set_log_verbose();
     init_clocks();
+    INITIALIZED.store(true, Ordering::Relaxed);
 
     #[cfg(coex)]
     match crate::wifi::coex_initialize() {
@@ -312,6 +676,103 @@ pub fn initialize(
     }
 }
 
+/// Deinitializes WiFi and returns the `WIFI` peripheral.
+///
+/// This stops the driver, drops frames which are still queued and deinitializes the driver and
+/// the supplicant. Everything the driver allocated is returned to the esp-wifi heap, which
+/// can be checked with [`heap_free`]. The esp-wifi heap itself, the task stacks and the RX/TX
+/// queues are statically allocated and stay reserved. The internal scheduler keeps running
+/// since BLE might still be in use, and WiFi can be brought up again with [`reinitialize`].
+///
+/// The PHY reference of the driver is dropped, so the RF is powered down unless BLE still uses
+/// it, and the WiFi clock is gated.
+#[cfg(feature = "wifi")]
+pub fn deinit<'d>(
+    init: EspWifiInitialization,
+    controller: wifi::WifiController<'d>,
+    _devices: impl wifi::WifiDevices<'d>,
+) -> Result<hal::peripheral::PeripheralRef<'d, hal::peripherals::WIFI>, WifiError> {
+    if !init.is_wifi() {
+        return Err(WifiError::NotInitialized);
+    }
+
+    debug!("wifi deinit, heap free before: {}", heap_free());
+    crate::wifi::wifi_deinit()?;
+    debug!("wifi deinit, heap free after: {}", heap_free());
+
+    Ok(controller.into_peripheral())
+}
+
+/// Initializes WiFi again after it was deinitialized by [`deinit`].
+///
+/// The timer, RNG and radio clocks passed to [`initialize`] are still owned by esp-wifi, so only
+/// the driver is brought up again, using `config` as its runtime configuration.
+#[cfg(feature = "wifi")]
+pub fn reinitialize(config: EspWifiConfig) -> Result<EspWifiInitialization, InitializationError> {
+    if !INITIALIZED.load(Ordering::Relaxed) {
+        return Err(InitializationError::NotInitialized);
+    }
+
+    if crate::wifi::WIFI_INITIALIZED.load(Ordering::Relaxed) {
+        return Err(InitializationError::AlreadyInitialized);
+    }
+
+    config.validate()?;
+    blocking_checks::with(|cs| *ESP_WIFI_CONFIG.borrow_ref_mut(cs) = config);
+    debug!("esp-wifi runtime configuration {:?}", config);
+
+    debug!("wifi reinit, heap free: {}", heap_free());
+    crate::wifi::wifi_init()?;
+
+    Ok(EspWifiInitialization::Wifi(EspWifiInitializationInternal))
+}
+
+/// Brings up BLE on top of an initialization which is only for WiFi.
+///
+/// WiFi keeps running while the BLE controller is initialized, afterwards `inited` can be used
+/// for both. Nothing is done if BLE is already initialized.
+#[cfg(coex)]
+pub fn add_ble(inited: &mut EspWifiInitialization) -> Result<(), InitializationError> {
+    if inited.is_ble() {
+        return Ok(());
+    }
+
+    debug!("ble init");
+    crate::ble::ble_init();
+    *inited = EspWifiInitialization::WifiBle(EspWifiInitializationInternal);
+
+    Ok(())
+}
+
+/// Shuts down BLE, keeping WiFi running.
+///
+/// The BLE controller is stopped, its coexistence slot is released to WiFi and the BT clocks
+/// and the PHY reference it took are dropped. Everything the controller allocated is returned
+/// to the esp-wifi heap, which can be checked with [`heap_free`]. Its statically placed data
+/// and the task stack running it stay reserved. Afterwards `inited` is for WiFi only and BLE
+/// can be brought up again with [`add_ble`].
+///
+/// HCI packets which weren't read by the `BleConnector` are discarded.
+#[cfg(coex)]
+pub fn remove_ble(inited: &mut EspWifiInitialization) -> Result<(), InitializationError> {
+    if !inited.is_ble() {
+        return Ok(());
+    }
+
+    debug!("ble deinit, heap free before: {}", heap_free());
+    crate::ble::ble_deinit().map_err(InitializationError::General)?;
+    debug!("ble deinit, heap free after: {}", heap_free());
+
+    *inited = EspWifiInitialization::Wifi(EspWifiInitializationInternal);
+
+    Ok(())
+}
+
+/// Returns the number of free bytes in the esp-wifi heap used by the WiFi and BLE drivers.
+pub fn heap_free() -> usize {
+    blocking_checks::with(|cs| HEAP.borrow_ref(cs).free())
+}
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Error which can be returned during [`initialize`].
@@ -320,6 +781,19 @@ pub enum InitializationError {
     #[cfg(feature = "wifi")]
     WifiError(WifiError),
     WrongClockConfig,
+    /// The initialization function was called on another core than [`EspWifiConfig::core_id`]
+    WrongCore,
+    InvalidConfig(ConfigError),
+    /// [`reinitialize`] was called before [`initialize`]
+    NotInitialized,
+    /// [`reinitialize`] was called while WiFi is still initialized
+    AlreadyInitialized,
+}
+
+impl From<ConfigError> for InitializationError {
+    fn from(value: ConfigError) -> Self {
+        InitializationError::InvalidConfig(value)
+    }
 }
 
 #[cfg(feature = "wifi")]
@@ -18,15 +18,49 @@ macro_rules! task_stack {
 static mut TASK_TOP: usize = 1;
 static mut CTX_NOW: usize = 0;
 
-fn allocate_task() -> usize {
+static mut TASK_ENTRY: [Option<extern "C" fn()>; MAX_TASK] = [None; MAX_TASK];
+static mut TASK_RESTART: [bool; MAX_TASK] = [false; MAX_TASK];
+
+fn allocate_task(task: extern "C" fn()) -> usize {
     unsafe {
         let i = TASK_TOP - 1;
         CTX_NOW = TASK_TOP;
         TASK_TOP += 1;
+        TASK_ENTRY[i] = Some(task);
         i
     }
 }
 
+/// Lets the current task start over from its entry function with an empty stack.
+///
+/// The context is reset before the task is scheduled again, so this never returns.
+pub fn restart_current_task() -> ! {
+    restart_task(current_task());
+
+    loop {
+        crate::timer::yield_task();
+    }
+}
+
+/// Lets task `id` start over from its entry function with an empty stack the next time it is
+/// scheduled, dropping whatever it was doing.
+pub(crate) fn restart_task(id: usize) {
+    unsafe {
+        TASK_RESTART[id] = true;
+    }
+}
+
+/// Returns the entry function of task `id` if it should be restarted.
+fn take_restart(id: usize) -> Option<extern "C" fn()> {
+    unsafe {
+        if core::mem::take(&mut TASK_RESTART[id]) {
+            TASK_ENTRY[id]
+        } else {
+            None
+        }
+    }
+}
+
 fn next_task() {
     unsafe {
         CTX_NOW = (CTX_NOW + 1) % TASK_TOP;
@@ -50,9 +50,13 @@ static mut CTX_TASKS: [Context; MAX_TASK] = [Context {
 }; MAX_TASK];
 
 pub fn task_create(task: extern "C" fn()) {
-    unsafe {
-        let i = allocate_task();
+    let i = allocate_task(task);
+    init_task_context(i, task);
+}
 
+fn init_task_context(i: usize, task: extern "C" fn()) {
+    unsafe {
+        CTX_TASKS[i] = Context::default();
         CTX_TASKS[i].trap_frame.pc = task as usize;
 
         let task_stack_size = TASK_STACK_SIZE[i];
@@ -148,8 +152,15 @@ pub fn task_switch(trap_frame: &mut TrapFrame) {
 
     save_task_context(current_task(), old_mepc, trap_frame);
 
+    #[cfg(feature = "diagnostics")]
+    crate::diagnostics::sample_if_due();
+
     next_task();
 
+    if let Some(task) = take_restart(current_task()) {
+        init_task_context(current_task(), task);
+    }
+
     let new_pc = restore_task_context(current_task(), trap_frame);
     trap_frame.pc = new_pc;
 
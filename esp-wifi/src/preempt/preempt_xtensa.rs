@@ -7,7 +7,7 @@ pub struct TaskContext {
     trap_frame: TrapFrame,
 }
 
-static mut CTX_TASKS: [TaskContext; MAX_TASK] = [TaskContext {
+const EMPTY_TASK_CONTEXT: TaskContext = TaskContext {
     trap_frame: TrapFrame {
         PC: 0,
         PS: 0,
@@ -64,12 +64,18 @@ static mut CTX_TASKS: [TaskContext; MAX_TASK] = [TaskContext {
         F14: 0,
         F15: 0,
     },
-}; MAX_TASK];
+};
+
+static mut CTX_TASKS: [TaskContext; MAX_TASK] = [EMPTY_TASK_CONTEXT; MAX_TASK];
 
 pub fn task_create(task: extern "C" fn()) {
-    unsafe {
-        let i = allocate_task();
+    let i = allocate_task(task);
+    init_task_context(i, task);
+}
 
+fn init_task_context(i: usize, task: extern "C" fn()) {
+    unsafe {
+        CTX_TASKS[i] = EMPTY_TASK_CONTEXT;
         CTX_TASKS[i].trap_frame.PC = task as u32;
 
         let task_stack_size = TASK_STACK_SIZE[i];
@@ -107,7 +113,16 @@ fn save_task_context(id: usize, trap_frame: &TrapFrame) {
 
 pub fn task_switch(trap_frame: &mut TrapFrame) {
     save_task_context(current_task(), trap_frame);
+
+    #[cfg(feature = "diagnostics")]
+    crate::diagnostics::sample_if_due();
+
     next_task();
+
+    if let Some(task) = take_restart(current_task()) {
+        init_task_context(current_task(), task);
+    }
+
     restore_task_context(current_task(), trap_frame);
 
     // debug aid! remove when not needed anymore!!!!!
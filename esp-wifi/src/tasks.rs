@@ -1,5 +1,5 @@
 use crate::{
-    compat::{self, queue::SimpleQueue, timer_compat::TIMERS},
+    compat::{self, blocking_checks, queue::SimpleQueue, timer_compat::TIMERS},
     memory_fence::memory_fence,
     preempt::preempt::task_create,
     timer::{get_systimer_count, yield_task},
@@ -19,7 +19,7 @@ pub extern "C" fn timer_task() {
         let mut to_run = SimpleQueue::<_, 20>::new();
 
         let current_timestamp = get_systimer_count();
-        critical_section::with(|_| unsafe {
+        blocking_checks::with(|_| unsafe {
             memory_fence();
             for timer in TIMERS.iter_mut() {
                 if timer.active
@@ -44,6 +44,9 @@ pub extern "C" fn timer_task() {
             trace!("timer callback called");
         }
 
+        #[cfg(feature = "wifi")]
+        crate::wifi::tcp_rst::send_pending_resets();
+
         yield_task();
     }
 }
@@ -11,10 +11,26 @@ mod chip_specific;
 #[cfg_attr(any(esp32c2, esp32c3, esp32c6, esp32h2), path = "riscv.rs")]
 mod arch_specific;
 
+#[cfg_attr(any(timebase_timg0, timebase_timg1), path = "timebase_timg.rs")]
+#[cfg_attr(timebase_systimer, path = "timebase_systimer.rs")]
+mod timebase;
+
+use portable_atomic::{AtomicU32, Ordering};
+
 pub use arch_specific::*;
 pub use chip_specific::*;
+pub use timebase::TimeBase;
+
+/// Tick rate of the scheduler, taken from the runtime configuration when the timer is set up.
+static TICK_RATE_HZ: AtomicU32 = AtomicU32::new(crate::CONFIG.tick_rate_hz);
+
+pub(crate) fn timeslice_frequency() -> fugit::HertzU32 {
+    fugit::HertzU32::from_raw(TICK_RATE_HZ.load(Ordering::Relaxed))
+}
 
 pub fn setup_timer_isr(timebase: TimeBase) {
+    TICK_RATE_HZ.store(crate::esp_wifi_config().tick_rate_hz, Ordering::Relaxed);
+
     setup_radio_isr();
 
     setup_timer(timebase);
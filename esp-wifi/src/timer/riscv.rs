@@ -1,47 +1,30 @@
-use core::cell::RefCell;
-
-use critical_section::Mutex;
-
 use crate::{
     hal::{
         interrupt::{self, TrapFrame},
         peripherals::{self, Interrupt},
         prelude::*,
         riscv,
-        systimer::{Alarm, Periodic, SystemTimer, Target},
+        systimer::SystemTimer,
     },
     preempt::preempt::task_switch,
 };
 
+use super::timebase::{restart_timebase, setup_timebase, TimeBase};
+
 #[cfg(any(feature = "esp32c6", feature = "esp32h2"))]
 use peripherals::INTPRI as SystemPeripheral;
 #[cfg(not(any(feature = "esp32c6", feature = "esp32h2")))]
 use peripherals::SYSTEM as SystemPeripheral;
 
-/// The timer responsible for time slicing.
-pub type TimeBase = Alarm<Target, 0>;
-static ALARM0: Mutex<RefCell<Option<Alarm<Periodic, 0>>>> = Mutex::new(RefCell::new(None));
-const TIMESLICE_FREQUENCY: fugit::HertzU32 = fugit::HertzU32::from_raw(crate::CONFIG.tick_rate_hz);
-
 // Time keeping
 
 pub const TICKS_PER_SECOND: u64 = 16_000_000;
 
-pub fn setup_timer(systimer: TimeBase) {
+pub fn setup_timer(timebase: TimeBase) {
     // make sure the scheduling won't start before everything is setup
     riscv::interrupt::disable();
 
-    let alarm0 = systimer.into_periodic();
-    alarm0.set_period(TIMESLICE_FREQUENCY.into_duration());
-    alarm0.clear_interrupt();
-    alarm0.enable_interrupt(true);
-
-    critical_section::with(|cs| ALARM0.borrow_ref_mut(cs).replace(alarm0));
-
-    unwrap!(interrupt::enable(
-        Interrupt::SYSTIMER_TARGET0,
-        interrupt::Priority::Priority1,
-    ));
+    setup_timebase(timebase, interrupt::Priority::Priority1);
 }
 
 pub fn setup_multitasking() {
@@ -55,16 +38,6 @@ pub fn setup_multitasking() {
     }
 }
 
-#[interrupt]
-fn SYSTIMER_TARGET0(trap_frame: &mut TrapFrame) {
-    // clear the systimer intr
-    critical_section::with(|cs| {
-        unwrap!(ALARM0.borrow_ref_mut(cs).as_mut()).clear_interrupt();
-    });
-
-    task_switch(trap_frame);
-}
-
 #[interrupt]
 fn FROM_CPU_INTR3(trap_frame: &mut TrapFrame) {
     unsafe {
@@ -74,13 +47,7 @@ fn FROM_CPU_INTR3(trap_frame: &mut TrapFrame) {
             .modify(|_, w| w.cpu_intr_from_cpu_3().clear_bit());
     }
 
-    critical_section::with(|cs| {
-        let mut alarm0 = ALARM0.borrow_ref_mut(cs);
-        let alarm0 = unwrap!(alarm0.as_mut());
-
-        alarm0.set_period(TIMESLICE_FREQUENCY.into_duration());
-        alarm0.clear_interrupt();
-    });
+    restart_timebase();
 
     task_switch(trap_frame);
 }
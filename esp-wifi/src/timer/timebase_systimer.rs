@@ -0,0 +1,57 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+#[cfg(target_arch = "riscv32")]
+use crate::hal::interrupt::TrapFrame;
+#[cfg(target_arch = "xtensa")]
+use crate::hal::trapframe::TrapFrame;
+use crate::{
+    compat::blocking_checks,
+    hal::{
+        interrupt,
+        macros::interrupt,
+        peripherals::Interrupt,
+        prelude::*,
+        systimer::{Alarm, Periodic, Target},
+    },
+    preempt::preempt::task_switch,
+};
+
+use super::timeslice_frequency;
+
+/// The timer responsible for time slicing.
+pub type TimeBase = Alarm<Target, 0>;
+static ALARM0: Mutex<RefCell<Option<Alarm<Periodic, 0>>>> = Mutex::new(RefCell::new(None));
+
+pub(super) fn setup_timebase(systimer: TimeBase, priority: interrupt::Priority) {
+    let alarm0 = systimer.into_periodic();
+    alarm0.set_period(timeslice_frequency().into_duration());
+    alarm0.clear_interrupt();
+    alarm0.enable_interrupt(true);
+
+    blocking_checks::with(|cs| ALARM0.borrow_ref_mut(cs).replace(alarm0));
+
+    unwrap!(interrupt::enable(Interrupt::SYSTIMER_TARGET0, priority));
+}
+
+/// Starts a new time slice, e.g. when a task yields.
+pub(super) fn restart_timebase() {
+    blocking_checks::with(|cs| {
+        let mut alarm0 = ALARM0.borrow_ref_mut(cs);
+        let alarm0 = unwrap!(alarm0.as_mut());
+
+        alarm0.set_period(timeslice_frequency().into_duration());
+        alarm0.clear_interrupt();
+    });
+}
+
+#[interrupt]
+fn SYSTIMER_TARGET0(trap_frame: &mut TrapFrame) {
+    // clear the systimer intr
+    blocking_checks::with(|cs| {
+        unwrap!(ALARM0.borrow_ref_mut(cs).as_mut()).clear_interrupt();
+    });
+
+    task_switch(trap_frame);
+}
@@ -0,0 +1,69 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+#[cfg(target_arch = "riscv32")]
+use crate::hal::interrupt::TrapFrame;
+#[cfg(target_arch = "xtensa")]
+use crate::hal::trapframe::TrapFrame;
+use crate::{
+    compat::blocking_checks,
+    hal::{
+        interrupt,
+        macros::interrupt,
+        peripherals,
+        prelude::*,
+        timer::{Timer, Timer0},
+    },
+    preempt::preempt::task_switch,
+};
+
+use super::timeslice_frequency;
+
+#[cfg(timebase_timg0)]
+use peripherals::TIMG0 as TIMG;
+#[cfg(timebase_timg1)]
+use peripherals::TIMG1 as TIMG;
+
+/// The timer responsible for time slicing.
+pub type TimeBase = Timer<Timer0<TIMG>>;
+static TIMEBASE: Mutex<RefCell<Option<TimeBase>>> = Mutex::new(RefCell::new(None));
+
+pub(super) fn setup_timebase(mut timer: TimeBase, priority: interrupt::Priority) {
+    #[cfg(timebase_timg0)]
+    let irq = peripherals::Interrupt::TG0_T0_LEVEL;
+    #[cfg(timebase_timg1)]
+    let irq = peripherals::Interrupt::TG1_T0_LEVEL;
+
+    unwrap!(interrupt::enable(irq, priority));
+
+    timer.listen();
+    timer.start(timeslice_frequency().into_duration());
+    blocking_checks::with(|cs| {
+        TIMEBASE.borrow_ref_mut(cs).replace(timer);
+    });
+}
+
+/// Starts a new time slice, e.g. when a task yields.
+pub(super) fn restart_timebase() {
+    blocking_checks::with(|cs| {
+        let mut timer = TIMEBASE.borrow_ref_mut(cs);
+        let timer = unwrap!(timer.as_mut());
+        timer.clear_interrupt();
+        timer.start(timeslice_frequency().into_duration());
+    });
+}
+
+#[cfg(timebase_timg0)]
+#[interrupt]
+fn TG0_T0_LEVEL(context: &mut TrapFrame) {
+    restart_timebase();
+    task_switch(context);
+}
+
+#[cfg(timebase_timg1)]
+#[interrupt]
+fn TG1_T0_LEVEL(context: &mut TrapFrame) {
+    restart_timebase();
+    task_switch(context);
+}
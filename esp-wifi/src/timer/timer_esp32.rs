@@ -17,8 +17,8 @@ pub fn setup_radio_isr() {
 
         // It's a mystery why these interrupts are enabled now since it worked without this before
         // Now at least without disabling these nothing will work
-        interrupt::disable(crate::hal::Cpu::ProCpu, peripherals::Interrupt::ETH_MAC);
-        interrupt::disable(crate::hal::Cpu::ProCpu, peripherals::Interrupt::UART0);
+        interrupt::disable(crate::hal::get_core(), peripherals::Interrupt::ETH_MAC);
+        interrupt::disable(crate::hal::get_core(), peripherals::Interrupt::UART0);
     }
 }
 
@@ -32,6 +32,7 @@ fn Software0(_level: u32) {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     }
@@ -46,6 +47,7 @@ fn WIFI_MAC() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     }
@@ -60,6 +62,7 @@ fn RWBT() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     }
@@ -74,6 +77,7 @@ fn RWBLE() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     }
@@ -88,6 +92,7 @@ fn BT_BB() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     }
@@ -30,6 +30,7 @@ fn WIFI_MAC() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -47,6 +48,7 @@ fn WIFI_PWR() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -68,6 +70,7 @@ fn LP_TIMER() {
             trace!("interrupt LP_TIMER call");
 
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
             trace!("LP_TIMER done");
         }
@@ -90,6 +93,7 @@ fn BT_MAC() {
             trace!("interrupt BT_MAC call");
 
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
             trace!("BT_MAC done");
         }
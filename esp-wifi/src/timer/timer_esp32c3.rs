@@ -34,6 +34,7 @@ fn WIFI_MAC() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -51,6 +52,7 @@ fn WIFI_PWR() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -68,6 +70,7 @@ fn RWBT() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -85,6 +88,7 @@ fn RWBLE() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -102,6 +106,7 @@ fn BT_BB(_trap_frame: &mut crate::hal::interrupt::TrapFrame) {
 
         if !fnc.is_null() {
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
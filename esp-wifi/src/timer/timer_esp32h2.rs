@@ -32,6 +32,7 @@ fn LP_BLE_TIMER() {
             trace!("interrupt LP_TIMER call");
 
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
             trace!("LP_TIMER done");
         }
@@ -54,6 +55,7 @@ fn BT_MAC() {
             trace!("interrupt BT_MAC call");
 
             let fnc: fn(*mut binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
             trace!("BT_MAC done");
         }
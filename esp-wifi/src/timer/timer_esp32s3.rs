@@ -1,3 +1,5 @@
+#[cfg(feature = "ble")]
+use crate::compat::blocking_checks;
 #[cfg(any(feature = "wifi", feature = "ble"))]
 #[allow(unused_imports)]
 use crate::hal::{interrupt, macros::interrupt, peripherals};
@@ -26,6 +28,7 @@ fn WIFI_MAC() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     }
@@ -40,6 +43,7 @@ fn WIFI_PWR() {
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
 
@@ -50,11 +54,12 @@ fn WIFI_PWR() {
 #[cfg(feature = "ble")]
 #[interrupt]
 fn RWBLE() {
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         let (fnc, arg) = crate::ble::btdm::ble_os_adapter_chip_specific::ISR_INTERRUPT_5;
         trace!("interrupt RWBLE {:?} {:?}", fnc, arg);
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     });
@@ -63,12 +68,13 @@ fn RWBLE() {
 #[cfg(feature = "ble")]
 #[interrupt]
 fn BT_BB() {
-    critical_section::with(|_| unsafe {
+    blocking_checks::with(|_| unsafe {
         let (fnc, arg) = crate::ble::btdm::ble_os_adapter_chip_specific::ISR_INTERRUPT_8;
         trace!("interrupt RWBT {:?} {:?}", fnc, arg);
 
         if !fnc.is_null() {
             let fnc: fn(*mut crate::binary::c_types::c_void) = core::mem::transmute(fnc);
+            let _isr = crate::compat::blocking_checks::IsrGuard::enter();
             fnc(arg);
         }
     });
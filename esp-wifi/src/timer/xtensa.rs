@@ -1,26 +1,11 @@
-use core::cell::RefCell;
-
 use portable_atomic::{AtomicU32, Ordering};
 
-use critical_section::Mutex;
-
 use crate::{
-    hal::{
-        interrupt,
-        macros::interrupt,
-        peripherals::{self, TIMG1},
-        prelude::*,
-        timer::{Timer, Timer0},
-        trapframe::TrapFrame,
-        xtensa_lx, xtensa_lx_rt,
-    },
+    hal::{interrupt, trapframe::TrapFrame, xtensa_lx, xtensa_lx_rt},
     preempt::preempt::task_switch,
 };
 
-/// The timer responsible for time slicing.
-pub type TimeBase = Timer<Timer0<TIMG1>>;
-static TIMER1: Mutex<RefCell<Option<TimeBase>>> = Mutex::new(RefCell::new(None));
-const TIMESLICE_FREQUENCY: fugit::HertzU32 = fugit::HertzU32::from_raw(crate::CONFIG.tick_rate_hz);
+use super::timebase::{restart_timebase, setup_timebase, TimeBase};
 
 // Time keeping
 
@@ -47,17 +32,8 @@ pub fn get_systimer_count() -> u64 {
     (((overflow as u64) << 32) + counter_after as u64) / CLOCK_CYCLES_PER_TICK
 }
 
-pub fn setup_timer(mut timer1: TimeBase) {
-    unwrap!(interrupt::enable(
-        peripherals::Interrupt::TG1_T0_LEVEL,
-        interrupt::Priority::Priority2,
-    ));
-
-    timer1.listen();
-    timer1.start(TIMESLICE_FREQUENCY.into_duration());
-    critical_section::with(|cs| {
-        TIMER1.borrow_ref_mut(cs).replace(timer1);
-    });
+pub fn setup_timer(timebase: TimeBase) {
+    setup_timebase(timebase, interrupt::Priority::Priority2);
 
     // Set up the time keeping timer.
     xtensa_lx::timer::set_ccompare0(0xffffffff);
@@ -83,22 +59,6 @@ fn Timer0(_level: u32) {
     xtensa_lx::timer::set_ccompare0(0xffffffff);
 }
 
-fn do_task_switch(context: &mut TrapFrame) {
-    critical_section::with(|cs| {
-        let mut timer = TIMER1.borrow_ref_mut(cs);
-        let timer = unwrap!(timer.as_mut());
-        timer.clear_interrupt();
-        timer.start(TIMESLICE_FREQUENCY.into_duration());
-    });
-
-    task_switch(context);
-}
-
-#[interrupt]
-fn TG1_T0_LEVEL(context: &mut TrapFrame) {
-    do_task_switch(context);
-}
-
 #[allow(non_snake_case)]
 #[no_mangle]
 fn Software1(_level: u32, context: &mut TrapFrame) {
@@ -107,7 +67,8 @@ fn Software1(_level: u32, context: &mut TrapFrame) {
         core::arch::asm!("wsr.intclear  {0}", in(reg) intr, options(nostack));
     }
 
-    do_task_switch(context);
+    restart_timebase();
+    task_switch(context);
 }
 
 pub fn yield_task() {
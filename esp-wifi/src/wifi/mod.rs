@@ -1,8 +1,12 @@
 //! WiFi
 
+pub mod nvs;
 pub(crate) mod os_adapter;
 pub(crate) mod state;
+pub(crate) mod tcp_rst;
+pub(crate) mod vendor_ie;
 
+use core::ops::ControlFlow;
 use core::ptr::addr_of;
 use core::time::Duration;
 use core::{
@@ -10,9 +14,10 @@ use core::{
     mem::MaybeUninit,
 };
 
-use portable_atomic::{AtomicUsize, Ordering};
+use portable_atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 use crate::common_adapter::*;
+use crate::compat::blocking_checks;
 use crate::esp_wifi_result;
 use crate::hal::macros::ram;
 use crate::hal::peripheral::Peripheral;
@@ -184,6 +189,494 @@ impl Into<wifi_mode_t> for WifiMode {
     }
 }
 
+/// Power save mode of the station
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerSaveMode {
+    /// No power save
+    None,
+    /// Minimum modem sleep, the station wakes up for every DTIM beacon
+    Minimum,
+    /// Maximum modem sleep, the station wakes up every `listen_interval` beacons
+    Maximum,
+}
+
+impl PowerSaveMode {
+    /// The mode selected by the `ps-min-modem`/`ps-max-modem` features, coex requires power
+    /// save to be enabled.
+    pub(crate) const fn default_for_features() -> Self {
+        if cfg!(feature = "ps-min-modem") {
+            Self::Minimum
+        } else if cfg!(feature = "ps-max-modem") {
+            Self::Maximum
+        } else if cfg!(coex) {
+            Self::Minimum
+        } else {
+            Self::None
+        }
+    }
+
+    pub(crate) fn to_raw(self) -> include::wifi_ps_type_t {
+        match self {
+            Self::None => include::wifi_ps_type_t_WIFI_PS_NONE,
+            Self::Minimum => include::wifi_ps_type_t_WIFI_PS_MIN_MODEM,
+            Self::Maximum => include::wifi_ps_type_t_WIFI_PS_MAX_MODEM,
+        }
+    }
+
+    fn from_raw(raw: include::wifi_ps_type_t) -> Self {
+        match raw {
+            include::wifi_ps_type_t_WIFI_PS_MIN_MODEM => Self::Minimum,
+            include::wifi_ps_type_t_WIFI_PS_MAX_MODEM => Self::Maximum,
+            _ => Self::None,
+        }
+    }
+}
+
+/// WiFi interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiInterface {
+    /// The station interface
+    Sta,
+    /// The access point interface
+    Ap,
+}
+
+impl WifiInterface {
+    pub(crate) fn to_raw(self) -> wifi_interface_t {
+        match self {
+            Self::Sta => wifi_interface_t_WIFI_IF_STA,
+            Self::Ap => wifi_interface_t_WIFI_IF_AP,
+        }
+    }
+
+    fn pmf_config(self) -> &'static Mutex<RefCell<wifi_pmf_config_t>> {
+        match self {
+            Self::Sta => &STA_PMF_CONFIG,
+            Self::Ap => &AP_PMF_CONFIG,
+        }
+    }
+}
+
+const DEFAULT_PMF_CONFIG: wifi_pmf_config_t = wifi_pmf_config_t {
+    capable: true,
+    required: false,
+};
+
+/// PMF policy of the station, see [`WifiController::set_pmf`].
+static STA_PMF_CONFIG: Mutex<RefCell<wifi_pmf_config_t>> =
+    Mutex::new(RefCell::new(DEFAULT_PMF_CONFIG));
+
+/// PMF policy of the access point, see [`WifiController::set_pmf`].
+static AP_PMF_CONFIG: Mutex<RefCell<wifi_pmf_config_t>> =
+    Mutex::new(RefCell::new(DEFAULT_PMF_CONFIG));
+
+/// WPA3 SAE-PK policy of the station, see [`WifiController::set_sae_pk_mode`].
+///
+/// With SAE-PK the access point proves it owns the key pair the password was derived from,
+/// which protects against evil twins knowing the password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SaePkMode {
+    /// Use SAE-PK if the access point supports it
+    #[default]
+    Auto,
+    /// Never use SAE-PK
+    Disabled,
+    /// Only connect to access points using SAE-PK
+    Required,
+}
+
+impl SaePkMode {
+    fn to_raw(self) -> include::wifi_sae_pk_mode_t {
+        match self {
+            Self::Auto => include::wifi_sae_pk_mode_t_WPA3_SAE_PK_MODE_AUTOMATIC,
+            Self::Disabled => include::wifi_sae_pk_mode_t_WPA3_SAE_PK_MODE_DISABLED,
+            Self::Required => include::wifi_sae_pk_mode_t_WPA3_SAE_PK_MODE_ONLY,
+        }
+    }
+}
+
+static STA_SAE_PK_MODE: Mutex<RefCell<SaePkMode>> = Mutex::new(RefCell::new(SaePkMode::Auto));
+
+/// 802.11 PHY protocol, the discriminants match the bit positions of `WIFI_PROTOCOL_*`
+#[derive(Debug, EnumSetType)]
+#[enumset(repr = "u8")]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiProtocol {
+    /// 802.11b
+    P802D11B,
+    /// 802.11g
+    P802D11G,
+    /// 802.11n
+    P802D11N,
+    /// Espressif's long range mode
+    P802D11LR,
+    /// 802.11ax, only available on the ESP32-C6
+    P802D11AX,
+}
+
+/// Channel bandwidth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bandwidth {
+    /// 20 MHz
+    Ht20,
+    /// 40 MHz, the primary channel and a secondary channel above or below it
+    Ht40,
+}
+
+/// A set of [`WifiProtocol`]s which can be enabled on an interface at the same time
+pub type ProtocolSet = EnumSet<WifiProtocol>;
+
+/// Returns the protocols the chip the crate is compiled for supports on any interface, see
+/// [`chip_capabilities`] for the protocols per interface.
+pub fn supported_protocols() -> ProtocolSet {
+    let protocols = WifiProtocol::P802D11B
+        | WifiProtocol::P802D11G
+        | WifiProtocol::P802D11N
+        | WifiProtocol::P802D11LR;
+
+    #[cfg(esp32c6)]
+    let protocols = protocols | WifiProtocol::P802D11AX;
+
+    protocols
+}
+
+/// What the chip the crate is compiled for supports, see [`chip_capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChipCapabilities {
+    /// Protocols the station can use
+    pub sta_protocols: ProtocolSet,
+    /// Protocols the access point can use
+    pub ap_protocols: ProtocolSet,
+}
+
+impl ChipCapabilities {
+    /// Returns the protocols `interface` can use.
+    pub fn protocols(&self, interface: WifiInterface) -> ProtocolSet {
+        match interface {
+            WifiInterface::Sta => self.sta_protocols,
+            WifiInterface::Ap => self.ap_protocols,
+        }
+    }
+}
+
+/// Returns the capabilities of the chip the crate is compiled for.
+///
+/// The ESP32-C6 supports 802.11ax only as a station, its access point is limited to
+/// 802.11b/g/n and LR like the other chips.
+pub fn chip_capabilities() -> ChipCapabilities {
+    ChipCapabilities {
+        sta_protocols: supported_protocols(),
+        ap_protocols: supported_protocols() - WifiProtocol::P802D11AX,
+    }
+}
+
+/// Conversion of the embedded-svc [`Protocol`] into a [`ProtocolSet`]
+pub trait ProtocolExt {
+    fn to_protocol_set(&self) -> ProtocolSet;
+}
+
+impl ProtocolExt for Protocol {
+    fn to_protocol_set(&self) -> ProtocolSet {
+        match self {
+            Protocol::P802D11B => WifiProtocol::P802D11B.into(),
+            Protocol::P802D11BG => WifiProtocol::P802D11B | WifiProtocol::P802D11G,
+            Protocol::P802D11BGN => {
+                WifiProtocol::P802D11B | WifiProtocol::P802D11G | WifiProtocol::P802D11N
+            }
+            Protocol::P802D11BGNLR => {
+                WifiProtocol::P802D11B
+                    | WifiProtocol::P802D11G
+                    | WifiProtocol::P802D11N
+                    | WifiProtocol::P802D11LR
+            }
+            Protocol::P802D11LR => WifiProtocol::P802D11LR.into(),
+        }
+    }
+}
+
+/// PHY rate used to transmit frames
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiPhyRate {
+    /// 1 Mbps with long preamble
+    Rate1mL = include::wifi_phy_rate_t_WIFI_PHY_RATE_1M_L,
+    /// 2 Mbps with long preamble
+    Rate2m = include::wifi_phy_rate_t_WIFI_PHY_RATE_2M_L,
+    /// 5.5 Mbps with long preamble
+    Rate5mL = include::wifi_phy_rate_t_WIFI_PHY_RATE_5M_L,
+    /// 11 Mbps with long preamble
+    Rate11mL = include::wifi_phy_rate_t_WIFI_PHY_RATE_11M_L,
+    /// 2 Mbps with short preamble
+    Rate2mS = include::wifi_phy_rate_t_WIFI_PHY_RATE_2M_S,
+    /// 5.5 Mbps with short preamble
+    Rate5mS = include::wifi_phy_rate_t_WIFI_PHY_RATE_5M_S,
+    /// 11 Mbps with short preamble
+    Rate11mS = include::wifi_phy_rate_t_WIFI_PHY_RATE_11M_S,
+    /// 48 Mbps
+    Rate48m = include::wifi_phy_rate_t_WIFI_PHY_RATE_48M,
+    /// 24 Mbps
+    Rate24m = include::wifi_phy_rate_t_WIFI_PHY_RATE_24M,
+    /// 12 Mbps
+    Rate12m = include::wifi_phy_rate_t_WIFI_PHY_RATE_12M,
+    /// 6 Mbps
+    Rate6m = include::wifi_phy_rate_t_WIFI_PHY_RATE_6M,
+    /// 54 Mbps
+    Rate54m = include::wifi_phy_rate_t_WIFI_PHY_RATE_54M,
+    /// 36 Mbps
+    Rate36m = include::wifi_phy_rate_t_WIFI_PHY_RATE_36M,
+    /// 18 Mbps
+    Rate18m = include::wifi_phy_rate_t_WIFI_PHY_RATE_18M,
+    /// 9 Mbps
+    Rate9m = include::wifi_phy_rate_t_WIFI_PHY_RATE_9M,
+    /// MCS0 with long GI, 6.5 Mbps for 20MHz, 13.5 Mbps for 40MHz
+    RateMcs0Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS0_LGI,
+    /// MCS1 with long GI, 13 Mbps for 20MHz, 27 Mbps for 40MHz
+    RateMcs1Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS1_LGI,
+    /// MCS2 with long GI, 19.5 Mbps for 20MHz, 40.5 Mbps for 40MHz
+    RateMcs2Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS2_LGI,
+    /// MCS3 with long GI, 26 Mbps for 20MHz, 54 Mbps for 40MHz
+    RateMcs3Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS3_LGI,
+    /// MCS4 with long GI, 39 Mbps for 20MHz, 81 Mbps for 40MHz
+    RateMcs4Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS4_LGI,
+    /// MCS5 with long GI, 52 Mbps for 20MHz, 108 Mbps for 40MHz
+    RateMcs5Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS5_LGI,
+    /// MCS6 with long GI, 58.5 Mbps for 20MHz, 121.5 Mbps for 40MHz
+    RateMcs6Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS6_LGI,
+    /// MCS7 with long GI, 65 Mbps for 20MHz, 135 Mbps for 40MHz
+    RateMcs7Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS7_LGI,
+    /// MCS8 with long GI, 802.11ax only
+    #[cfg(esp32c6)]
+    RateMcs8Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS8_LGI,
+    /// MCS9 with long GI, 802.11ax only
+    #[cfg(esp32c6)]
+    RateMcs9Lgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS9_LGI,
+    /// MCS0 with short GI, 7.2 Mbps for 20MHz, 15 Mbps for 40MHz
+    RateMcs0Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS0_SGI,
+    /// MCS1 with short GI, 14.4 Mbps for 20MHz, 30 Mbps for 40MHz
+    RateMcs1Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS1_SGI,
+    /// MCS2 with short GI, 21.7 Mbps for 20MHz, 45 Mbps for 40MHz
+    RateMcs2Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS2_SGI,
+    /// MCS3 with short GI, 28.9 Mbps for 20MHz, 60 Mbps for 40MHz
+    RateMcs3Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS3_SGI,
+    /// MCS4 with short GI, 43.3 Mbps for 20MHz, 90 Mbps for 40MHz
+    RateMcs4Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS4_SGI,
+    /// MCS5 with short GI, 57.8 Mbps for 20MHz, 120 Mbps for 40MHz
+    RateMcs5Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS5_SGI,
+    /// MCS6 with short GI, 65 Mbps for 20MHz, 135 Mbps for 40MHz
+    RateMcs6Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS6_SGI,
+    /// MCS7 with short GI, 72.2 Mbps for 20MHz, 150 Mbps for 40MHz
+    RateMcs7Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS7_SGI,
+    /// MCS8 with short GI, 802.11ax only
+    #[cfg(esp32c6)]
+    RateMcs8Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS8_SGI,
+    /// MCS9 with short GI, 802.11ax only
+    #[cfg(esp32c6)]
+    RateMcs9Sgi = include::wifi_phy_rate_t_WIFI_PHY_RATE_MCS9_SGI,
+    /// 250 Kbps, ESP long range mode
+    RateLora250k = include::wifi_phy_rate_t_WIFI_PHY_RATE_LORA_250K,
+    /// 500 Kbps, ESP long range mode
+    RateLora500k = include::wifi_phy_rate_t_WIFI_PHY_RATE_LORA_500K,
+}
+
+impl WifiPhyRate {
+    /// The `WIFI_PROTOCOL_*` bit which has to be enabled on an interface to use this rate.
+    fn required_protocol(self) -> u8 {
+        let protocol = match self {
+            Self::Rate1mL
+            | Self::Rate2m
+            | Self::Rate5mL
+            | Self::Rate11mL
+            | Self::Rate2mS
+            | Self::Rate5mS
+            | Self::Rate11mS => include::WIFI_PROTOCOL_11B,
+            Self::Rate48m
+            | Self::Rate24m
+            | Self::Rate12m
+            | Self::Rate6m
+            | Self::Rate54m
+            | Self::Rate36m
+            | Self::Rate18m
+            | Self::Rate9m => include::WIFI_PROTOCOL_11G,
+            Self::RateLora250k | Self::RateLora500k => include::WIFI_PROTOCOL_LR,
+            _ => include::WIFI_PROTOCOL_11N,
+        };
+
+        protocol as u8
+    }
+}
+
+/// A known network for [`WifiController::scan_and_connect`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct NetworkCredential {
+    pub ssid: heapless::String<32>,
+    pub password: heapless::String<64>,
+    pub auth: AuthMethod,
+}
+
+// The password is a secret, so it's redacted when formatting
+impl core::fmt::Debug for NetworkCredential {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NetworkCredential")
+            .field("ssid", &self.ssid)
+            .field("password", &"<redacted>")
+            .field("auth", &self.auth)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for NetworkCredential {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "NetworkCredential {{ ssid: {}, password: <redacted>, auth: {} }}",
+            self.ssid.as_str(),
+            self.auth
+        )
+    }
+}
+
+/// One of the two antennas of an external antenna switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Antenna {
+    #[default]
+    Ant0,
+    Ant1,
+}
+
+impl Antenna {
+    fn to_raw(self) -> include::wifi_ant_t {
+        match self {
+            Self::Ant0 => include::wifi_ant_t_WIFI_ANT_ANT0,
+            Self::Ant1 => include::wifi_ant_t_WIFI_ANT_ANT1,
+        }
+    }
+}
+
+/// Which antennas the driver uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AntennaMode {
+    /// Use antenna 0 only
+    #[default]
+    Ant0,
+    /// Use antenna 1 only
+    Ant1,
+    /// Use both antennas and let the driver pick the better one
+    Auto,
+}
+
+impl AntennaMode {
+    fn to_raw(self) -> include::wifi_ant_mode_t {
+        match self {
+            Self::Ant0 => include::wifi_ant_mode_t_WIFI_ANT_MODE_ANT0,
+            Self::Ant1 => include::wifi_ant_mode_t_WIFI_ANT_MODE_ANT1,
+            Self::Auto => include::wifi_ant_mode_t_WIFI_ANT_MODE_AUTO,
+        }
+    }
+}
+
+/// Antenna selection, see [`WifiController::set_antenna`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AntennaConfig {
+    pub rx_mode: AntennaMode,
+    /// The antenna to receive with, ignored unless `rx_mode` is [`AntennaMode::Auto`]
+    pub rx_default: Antenna,
+    /// Can only be [`AntennaMode::Auto`] if `rx_mode` is too
+    pub tx_mode: AntennaMode,
+    /// Value driven onto the antenna switch GPIOs to select antenna 0, bit `n` controls the GPIO
+    /// at index `n` passed to [`WifiController::set_antenna_gpio`]
+    pub enabled_ant0: u8,
+    /// Value driven onto the antenna switch GPIOs to select antenna 1
+    pub enabled_ant1: u8,
+}
+
+/// Bit `n` is set if GPIO `n` can drive an antenna switch, i.e. exists and is output capable.
+#[cfg(esp32)]
+const ANTENNA_GPIO_MASK: u64 = 0x0000_0003_0eef_ffff;
+#[cfg(esp32s2)]
+const ANTENNA_GPIO_MASK: u64 = 0x0000_3fff_fc3f_ffff;
+#[cfg(esp32s3)]
+const ANTENNA_GPIO_MASK: u64 = 0x0001_ffff_fc3f_ffff;
+#[cfg(esp32c2)]
+const ANTENNA_GPIO_MASK: u64 = 0x0000_0000_001f_ffff;
+#[cfg(esp32c3)]
+const ANTENNA_GPIO_MASK: u64 = 0x0000_0000_003f_ffff;
+#[cfg(esp32c6)]
+const ANTENNA_GPIO_MASK: u64 = 0x0000_0000_7fff_ffff;
+
+/// SM (spatial multiplexing) power save mode advertised in the HT capabilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SmPowerSave {
+    Static = 0,
+    Dynamic = 1,
+    Disabled = 3,
+}
+
+/// 802.11n HT capabilities of the station, see [`WifiController::set_ht_capabilities`].
+///
+/// The default matches what the driver advertises for a 20 MHz channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HtCapabilities {
+    pub ldpc: bool,
+    /// Use 40 MHz channels if the access point supports them
+    pub ht40: bool,
+    pub sm_power_save: SmPowerSave,
+    pub short_gi_20: bool,
+    pub short_gi_40: bool,
+    pub tx_stbc: bool,
+    /// Number of spatial streams which can be received with STBC
+    pub rx_stbc: u8,
+    /// Maximum A-MSDU length in bytes
+    pub max_amsdu: u16,
+}
+
+impl Default for HtCapabilities {
+    fn default() -> Self {
+        Self {
+            ldpc: false,
+            ht40: false,
+            sm_power_save: SmPowerSave::Disabled,
+            short_gi_20: true,
+            short_gi_40: true,
+            tx_stbc: false,
+            rx_stbc: 1,
+            max_amsdu: 3839,
+        }
+    }
+}
+
+/// How a mesh node picks the node it connects to, see [`MeshConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MeshParentSelection {
+    /// Pick the parent with the best signal and fewest hops to the root
+    #[default]
+    Auto,
+    /// Always connect to the node with the given MAC address
+    Fixed([u8; 6]),
+}
+
+/// Configuration of an ESP-MESH network, see [`WifiController::enable_mesh`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MeshConfig {
+    /// Identifies the mesh network, all nodes of a network share it
+    pub mesh_id: [u8; 6],
+    pub parent_selection: MeshParentSelection,
+    /// Maximum number of hops between a node and the root
+    pub hop_limit: u8,
+}
+
 const DATA_FRAME_SIZE: usize = MTU + ETHERNET_FRAME_HEADER_SIZE;
 
 const RX_QUEUE_SIZE: usize = crate::CONFIG.rx_queue_size;
@@ -197,6 +690,33 @@ pub(crate) static DATA_QUEUE_RX_STA: Mutex<
     RefCell<SimpleQueue<EspWifiPacketBuffer, RX_QUEUE_SIZE>>,
 > = Mutex::new(RefCell::new(SimpleQueue::new()));
 
+/// Maximum number of stations which can be connected to the access point.
+pub const MAX_AP_STATIONS: usize = include::ESP_WIFI_MAX_CONN_NUM as usize;
+
+/// A station connected to the access point, see [`WifiController::ap_sta_list`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ApStationInfo {
+    /// MAC address of the station
+    pub mac: [u8; 6],
+    /// Average RSSI of the frames received from the station
+    pub rssi: i8,
+}
+
+fn ap_station_list() -> Result<heapless::Vec<ApStationInfo, MAX_AP_STATIONS>, WifiError> {
+    let mut list = MaybeUninit::<include::wifi_sta_list_t>::uninit();
+    esp_wifi_result!(unsafe { include::esp_wifi_ap_get_sta_list(list.as_mut_ptr()) })?;
+    let list = unsafe { list.assume_init_ref() };
+
+    Ok(list.sta[..(list.num as usize).min(MAX_AP_STATIONS)]
+        .iter()
+        .map(|sta| ApStationInfo {
+            mac: sta.mac,
+            rssi: sta.rssi,
+        })
+        .collect())
+}
+
 /// Common errors
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -206,6 +726,44 @@ pub enum WifiError {
     WrongClockConfig,
     Disconnected,
     UnknownWifiMode,
+    /// The operation needs an interface which is not enabled by the current configuration
+    WrongMode {
+        expected: WifiMode,
+        actual: WifiMode,
+    },
+    /// Power save can't be disabled while WiFi and BLE share the radio
+    PowerSaveRequiredByCoex,
+    /// None of the requested access points was found
+    ApNotFound,
+    /// The GPIO can't be used for the requested purpose on this chip
+    InvalidGpio(u8),
+    /// The configuration the driver would be initialized with is invalid
+    InvalidConfig(crate::ConfigError),
+    /// The operation needs WiFi to be started first
+    NotStarted,
+    /// A [`WifiDevice`] for the interface already exists, or still exists for an interface which
+    /// is removed
+    DeviceInUse,
+    /// [`crate::EspWifiConfig::nvs_enable`] is set but no storage was registered with
+    /// [`nvs::set_storage`]
+    NoNvsStorage,
+    /// The access point can't change its channel, the connected station keeps the radio on
+    /// `sta_channel`
+    ChannelDictatedBySta {
+        sta_channel: u8,
+    },
+    /// The country differs from the one the connected access point advertises, see
+    /// [`CountryCheck::Strict`]
+    CountryMismatch {
+        advertised: [u8; 2],
+    },
+    /// The channel the station or the access point uses isn't allowed in the country, see
+    /// [`WifiController::set_country`]
+    ChannelNotInCountry {
+        channel: u8,
+    },
+    /// The driver didn't finish the operation in time
+    Timeout,
 }
 
 /// Events generated by the WiFi driver
@@ -213,6 +771,7 @@ pub enum WifiError {
 #[derive(Debug, FromPrimitive, EnumSetType)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WifiEvent {
+    /// The driver is ready, see [`WifiController::wait_for_wifi_ready_sync`]
     WifiReady = 0,
     ScanDone,
     StaStart,
@@ -237,6 +796,31 @@ pub enum WifiEvent {
     StaBeaconTimeout,
 }
 
+/// Events esp-wifi itself waits for or tracks the state with, these can't be masked with
+/// [`WifiController::set_event_mask`].
+const UNMASKABLE_EVENTS: EnumSet<WifiEvent> = enumset::enum_set!(
+    WifiEvent::WifiReady
+        | WifiEvent::ScanDone
+        | WifiEvent::StaStart
+        | WifiEvent::StaStop
+        | WifiEvent::StaConnected
+        | WifiEvent::StaDisconnected
+        | WifiEvent::ApStart
+        | WifiEvent::ApStop
+);
+
+/// `WIFI_EVENT_MASK_AP_PROBEREQRECVED`, the only event the driver can mask itself.
+const WIFI_EVENT_MASK_AP_PROBEREQRECVED: u32 = 1 << 0;
+
+/// The events ignored because of [`WifiController::set_event_mask`].
+static MASKED_EVENTS: Mutex<RefCell<EnumSet<WifiEvent>>> =
+    Mutex::new(RefCell::new(enumset::enum_set!()));
+
+/// Whether `event` was masked with [`WifiController::set_event_mask`].
+pub(crate) fn is_event_masked(event: WifiEvent) -> bool {
+    blocking_checks::with(|cs| MASKED_EVENTS.borrow_ref(cs).contains(event))
+}
+
 /// Error originating from the underlying drivers
 #[repr(i32)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
@@ -248,6 +832,9 @@ pub enum InternalWifiError {
     /// Invalid argument
     EspErrInvalidArg = 0x102,
 
+    /// Operation or feature not supported
+    EspErrNotSupported = 0x106,
+
     /// WiFi driver was not installed by esp_wifi_init
     EspErrWifiNotInit = 0x3001,
 
@@ -601,10 +1188,16 @@ static mut G_CONFIG: wifi_init_config_t = wifi_init_config_t {
     mgmt_sbuf_num: 32,
     feature_caps: WIFI_FEATURE_CAPS,
     sta_disconnected_pm: false,
-    espnow_max_encrypt_num: 7, // 2 for ESP32-C2
+    espnow_max_encrypt_num: ESPNOW_MAX_ENCRYPT_NUM,
     magic: WIFI_INIT_CONFIG_MAGIC as i32,
 };
 
+/// Number of encrypted ESP-NOW peers the driver supports.
+#[cfg(esp32c2)]
+pub(crate) const ESPNOW_MAX_ENCRYPT_NUM: i32 = 2;
+#[cfg(not(esp32c2))]
+pub(crate) const ESPNOW_MAX_ENCRYPT_NUM: i32 = 7;
+
 /// Get the STA MAC address
 pub fn get_sta_mac(mac: &mut [u8; 6]) {
     unsafe {
@@ -619,11 +1212,83 @@ pub fn get_ap_mac(mac: &mut [u8; 6]) {
     }
 }
 
+/// The priority to enable the WiFi interrupts with, see
+/// [`crate::EspWifiConfig::wifi_interrupt_priority`].
+///
+/// [`wifi_init`] validates the configuration before the driver sets up its interrupts, so they
+/// can unwrap this.
+pub(crate) fn wifi_interrupt_priority(
+) -> Result<crate::hal::interrupt::Priority, crate::ConfigError> {
+    interrupt_priority(crate::esp_wifi_config().wifi_interrupt_priority)
+}
+
+/// Converts a configured interrupt priority, rejecting priorities which can't be handled by
+/// esp-hal's interrupt handlers on this chip.
+pub(crate) fn interrupt_priority(
+    priority: u8,
+) -> Result<crate::hal::interrupt::Priority, crate::ConfigError> {
+    use crate::hal::interrupt::Priority;
+
+    Ok(match priority {
+        1 => Priority::Priority1,
+        2 => Priority::Priority2,
+        3 => Priority::Priority3,
+        #[cfg(target_arch = "riscv32")]
+        4 => Priority::Priority4,
+        #[cfg(target_arch = "riscv32")]
+        5 => Priority::Priority5,
+        #[cfg(target_arch = "riscv32")]
+        6 => Priority::Priority6,
+        #[cfg(target_arch = "riscv32")]
+        7 => Priority::Priority7,
+        #[cfg(target_arch = "riscv32")]
+        8 => Priority::Priority8,
+        #[cfg(target_arch = "riscv32")]
+        9 => Priority::Priority9,
+        #[cfg(target_arch = "riscv32")]
+        10 => Priority::Priority10,
+        #[cfg(target_arch = "riscv32")]
+        11 => Priority::Priority11,
+        #[cfg(target_arch = "riscv32")]
+        12 => Priority::Priority12,
+        #[cfg(target_arch = "riscv32")]
+        13 => Priority::Priority13,
+        #[cfg(target_arch = "riscv32")]
+        14 => Priority::Priority14,
+        #[cfg(target_arch = "riscv32")]
+        15 => Priority::Priority15,
+        _ => return Err(crate::ConfigError::InvalidInterruptPriority),
+    })
+}
+
+/// Whether the driver is initialized, i.e. between [`wifi_init`] and [`wifi_deinit`].
+pub(crate) static WIFI_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
 pub(crate) fn wifi_init() -> Result<(), WifiError> {
+    let config = crate::esp_wifi_config();
+    config.validate().map_err(WifiError::InvalidConfig)?;
+    if config.nvs_enable && !nvs::has_storage() {
+        error!("`nvs_enable` is set but no storage is registered, see `nvs::set_storage`");
+        return Err(WifiError::NoNvsStorage);
+    }
+    set_active_channel_plan(ChannelPlan::for_country(config.country_code));
+
     unsafe {
         G_CONFIG.wpa_crypto_funcs = g_wifi_default_wpa_crypto_funcs;
         G_CONFIG.feature_caps = g_wifi_feature_caps;
 
+        G_CONFIG.static_rx_buf_num = config.static_rx_buf_num as i32;
+        G_CONFIG.dynamic_rx_buf_num = config.dynamic_rx_buf_num as i32;
+        G_CONFIG.static_tx_buf_num = config.static_tx_buf_num as i32;
+        G_CONFIG.dynamic_tx_buf_num = config.dynamic_tx_buf_num as i32;
+        G_CONFIG.ampdu_rx_enable = config.ampdu_rx_enable as i32;
+        G_CONFIG.ampdu_tx_enable = config.ampdu_tx_enable as i32;
+        G_CONFIG.amsdu_tx_enable = config.amsdu_tx_enable as i32;
+        G_CONFIG.rx_ba_win = config.rx_ba_win as i32;
+        G_CONFIG.wifi_task_core_id = config.core_id as i32;
+        G_CONFIG.nvs_enable = config.nvs_enable as i32;
+        G_CONFIG.csi_enable = config.csi_enable as i32;
+
         #[cfg(coex)]
         esp_wifi_result!(coex_init())?;
 
@@ -651,22 +1316,87 @@ pub(crate) fn wifi_init() -> Result<(), WifiError> {
             chip_specific::g_misc_nvs = addr_of!(NVS_STRUCT) as u32;
         }
 
+        WIFI_INITIALIZED.store(true, Ordering::Relaxed);
+
         Ok(())
     }
 }
 
+/// Stops and deinitializes the driver, see [`crate::deinit`].
+pub(crate) fn wifi_deinit() -> Result<(), WifiError> {
+    esp_wifi_result!(unsafe { esp_wifi_stop() })?;
+
+    // Queued frames hold buffers of the driver which have to be returned before it goes away.
+    // EspWifiPacketBuffer::drop must not be called in a critical section.
+    for queue in [&DATA_QUEUE_RX_STA, &DATA_QUEUE_RX_AP] {
+        while let Some(packet) = blocking_checks::with(|cs| queue.borrow_ref_mut(cs).dequeue()) {
+            drop(packet);
+        }
+    }
+
+    unsafe {
+        esp_wifi_result!(include::esp_supplicant_deinit())?;
+        esp_wifi_result!(include::esp_wifi_deinit_internal())?;
+        os_adapter::release_phy_and_clock();
+    }
+
+    // Everything below would otherwise leak into the next initialization
+    crate::compat::common::clear_wifi_queue();
+    reset_sta_state();
+    reset_ap_state();
+    reset_sta_connection_stats();
+    reset_ap_traffic_stats();
+    #[cfg(coex)]
+    crate::coex::reset();
+    blocking_checks::with(|cs| {
+        WIFI_EVENTS.borrow_ref_mut(cs).clear();
+        *STA_PMF_CONFIG.borrow_ref_mut(cs) = DEFAULT_PMF_CONFIG;
+        *AP_PMF_CONFIG.borrow_ref_mut(cs) = DEFAULT_PMF_CONFIG;
+        *STA_SAE_PK_MODE.borrow_ref_mut(cs) = SaePkMode::Auto;
+        *COUNTRY.borrow_ref_mut(cs) = None;
+        *COUNTRY_CHECK.borrow_ref_mut(cs) = CountryCheck::Warn;
+        MASKED_EVENTS.borrow_ref_mut(cs).clear();
+        *TX_POWER.borrow_ref_mut(cs) = None;
+        #[cfg(feature = "embassy-net")]
+        {
+            *embassy::STA_LINK_SETTLE_DELAY.borrow_ref_mut(cs) = Duration::ZERO;
+            embassy::stop_sta_link_settle(cs);
+        }
+    });
+
+    tcp_rst::set_enabled(false);
+    vendor_ie::stop_capture();
+    nvs::close_all();
+    RX_DROPS_STA.store(0, Ordering::Relaxed);
+    RX_DROPS_AP.store(0, Ordering::Relaxed);
+    WIFI_INITIALIZED.store(false, Ordering::Relaxed);
+
+    Ok(())
+}
+
 unsafe extern "C" fn recv_cb_sta(
     buffer: *mut c_types::c_void,
     len: u16,
     eb: *mut c_types::c_void,
 ) -> esp_err_t {
     let packet = EspWifiPacketBuffer { buffer, len, eb };
+    tcp_rst::snoop_rx(core::slice::from_raw_parts(
+        buffer as *const u8,
+        len as usize,
+    ));
+
     // We must handle the result outside of the critical section because
     // EspWifiPacketBuffer::drop must not be called in a critical section.
     // Dropping an EspWifiPacketBuffer will call `esp_wifi_internal_free_rx_buffer` which
     // will try to lock an internal mutex. If the mutex is already taken, the function will
     // try to trigger a context switch, which will fail if we are in a critical section.
-    match critical_section::with(|cs| DATA_QUEUE_RX_STA.borrow_ref_mut(cs).enqueue(packet)) {
+    match blocking_checks::with(|cs| {
+        let res = DATA_QUEUE_RX_STA.borrow_ref_mut(cs).enqueue(packet);
+        if res.is_ok() {
+            STA_CONNECTION_STATS.borrow_ref_mut(cs).rx_bytes += len as u64;
+        }
+        res
+    }) {
         Ok(_) => {
             #[cfg(feature = "embassy-net")]
             embassy::STA_RECEIVE_WAKER.wake();
@@ -674,6 +1404,8 @@ unsafe extern "C" fn recv_cb_sta(
         }
         Err(_) => {
             debug!("RX QUEUE FULL");
+            let depth = blocking_checks::with(|cs| DATA_QUEUE_RX_STA.borrow_ref(cs).len());
+            report_rx_drop(WifiInterface::Sta, depth);
             include::ESP_ERR_NO_MEM as esp_err_t
         }
     }
@@ -685,12 +1417,21 @@ unsafe extern "C" fn recv_cb_ap(
     eb: *mut c_types::c_void,
 ) -> esp_err_t {
     let packet = EspWifiPacketBuffer { buffer, len, eb };
+    let frame = core::slice::from_raw_parts(buffer as *const u8, len as usize);
+    let src = frame.get(6..12);
+
     // We must handle the result outside of the critical section because
     // EspWifiPacketBuffer::drop must not be called in a critical section.
     // Dropping an EspWifiPacketBuffer will call `esp_wifi_internal_free_rx_buffer` which
     // will try to lock an internal mutex. If the mutex is already taken, the function will
     // try to trigger a context switch, which will fail if we are in a critical section.
-    match critical_section::with(|cs| DATA_QUEUE_RX_AP.borrow_ref_mut(cs).enqueue(packet)) {
+    match blocking_checks::with(|cs| {
+        let res = DATA_QUEUE_RX_AP.borrow_ref_mut(cs).enqueue(packet);
+        if res.is_ok() {
+            AP_TRAFFIC.borrow_ref_mut(cs).record_rx(src, len as usize);
+        }
+        res
+    }) {
         Ok(_) => {
             #[cfg(feature = "embassy-net")]
             embassy::AP_RECEIVE_WAKER.wake();
@@ -698,11 +1439,195 @@ unsafe extern "C" fn recv_cb_ap(
         }
         Err(_) => {
             debug!("RX QUEUE FULL");
+            let depth = blocking_checks::with(|cs| DATA_QUEUE_RX_AP.borrow_ref(cs).len());
+            report_rx_drop(WifiInterface::Ap, depth);
             include::ESP_ERR_NO_MEM as esp_err_t
         }
     }
 }
 
+/// Received packets dropped because the RX queue of an interface was full, see
+/// [`WifiController::set_drop_threshold_callback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DroppedPacketReport {
+    pub interface: WifiInterface,
+    /// Packets dropped on this interface since WiFi was initialized
+    pub total_drops: u64,
+    /// Packets in the RX queue when the last one was dropped
+    pub queue_depth: usize,
+}
+
+static RX_DROPS_STA: AtomicU64 = AtomicU64::new(0);
+static RX_DROPS_AP: AtomicU64 = AtomicU64::new(0);
+
+static DROP_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+static DROP_CALLBACK: Mutex<RefCell<Option<fn(DroppedPacketReport)>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Counts a dropped packet and calls the callback every `DROP_THRESHOLD` drops.
+fn report_rx_drop(interface: WifiInterface, queue_depth: usize) {
+    let drops = match interface {
+        WifiInterface::Sta => &RX_DROPS_STA,
+        WifiInterface::Ap => &RX_DROPS_AP,
+    };
+    let total_drops = drops.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let threshold = DROP_THRESHOLD.load(Ordering::Relaxed) as u64;
+    if threshold == 0 || total_drops % threshold != 0 {
+        return;
+    }
+
+    if let Some(callback) = blocking_checks::with(|cs| *DROP_CALLBACK.borrow_ref(cs)) {
+        callback(DroppedPacketReport {
+            interface,
+            total_drops,
+            queue_depth,
+        });
+    }
+}
+
+/// Traffic counters of the current station connection, see [`WifiController::connection_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionStats {
+    /// Bytes handed to the driver for transmission
+    pub tx_bytes: u64,
+    /// Bytes received
+    pub rx_bytes: u64,
+    /// Frames the driver reported as not delivered after exhausting its retries
+    pub tx_retries: u32,
+    /// Time the connection was established, in microseconds since boot
+    pub connect_time_us: u64,
+}
+
+static STA_CONNECTION_STATS: Mutex<RefCell<ConnectionStats>> =
+    Mutex::new(RefCell::new(ConnectionStats {
+        tx_bytes: 0,
+        rx_bytes: 0,
+        tx_retries: 0,
+        connect_time_us: 0,
+    }));
+
+/// Called when the station connected to start counting from zero.
+pub(crate) fn reset_sta_connection_stats() {
+    let now = crate::timer::ticks_to_micros(crate::timer::get_systimer_count());
+    blocking_checks::with(|cs| {
+        *STA_CONNECTION_STATS.borrow_ref_mut(cs) = ConnectionStats {
+            connect_time_us: now,
+            ..Default::default()
+        };
+    });
+}
+
+/// Details about a connection established by [`WifiController::connect_with_report`].
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectReport {
+    /// Time from the first connection attempt until the station was connected
+    pub elapsed: Duration,
+    /// Number of connection attempts, including the successful one
+    pub attempts: u32,
+    /// The `WIFI_REASON_*` code of the last failed attempt, if any
+    pub last_disconnect_reason: Option<u8>,
+}
+
+/// Traffic counters of the access point, see [`WifiController::ap_traffic_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ApTrafficStats {
+    /// Bytes received
+    pub rx_bytes: u64,
+    /// Frames received
+    pub rx_packets: u64,
+    /// Bytes handed to the driver for transmission
+    pub tx_bytes: u64,
+    /// Frames handed to the driver for transmission
+    pub tx_packets: u64,
+}
+
+impl ApTrafficStats {
+    fn add_rx(&mut self, len: usize) {
+        self.rx_bytes += len as u64;
+        self.rx_packets += 1;
+    }
+
+    fn add_tx(&mut self, len: usize) {
+        self.tx_bytes += len as u64;
+        self.tx_packets += 1;
+    }
+}
+
+/// Traffic counters of a single station associated with the access point, see
+/// [`WifiController::ap_station_stats`].
+pub type StationStats = ApTrafficStats;
+
+/// Number of stations [`WifiController::ap_station_stats`] keeps counters for.
+const AP_TRAFFIC_STATIONS: usize = 16;
+
+struct ApTraffic {
+    total: ApTrafficStats,
+    stations: heapless::FnvIndexMap<[u8; 6], StationStats, AP_TRAFFIC_STATIONS>,
+}
+
+impl ApTraffic {
+    /// Returns the counters of the station with the given MAC address, `None` for group
+    /// addresses or if the table is full.
+    fn station(&mut self, mac: Option<&[u8]>) -> Option<&mut StationStats> {
+        let mac: [u8; 6] = mac?.try_into().ok()?;
+        if mac[0] & 0x01 != 0 {
+            return None;
+        }
+
+        if !self.stations.contains_key(&mac) {
+            self.stations.insert(mac, StationStats::default()).ok()?;
+        }
+
+        self.stations.get_mut(&mac)
+    }
+
+    fn record_rx(&mut self, src: Option<&[u8]>, len: usize) {
+        if let Some(stats) = self.station(src) {
+            stats.add_rx(len);
+        }
+        self.total.add_rx(len);
+    }
+
+    fn record_tx(&mut self, dst: Option<&[u8]>, len: usize) {
+        if let Some(stats) = self.station(dst) {
+            stats.add_tx(len);
+        }
+        self.total.add_tx(len);
+    }
+}
+
+static AP_TRAFFIC: Mutex<RefCell<ApTraffic>> = Mutex::new(RefCell::new(ApTraffic {
+    total: ApTrafficStats {
+        rx_bytes: 0,
+        rx_packets: 0,
+        tx_bytes: 0,
+        tx_packets: 0,
+    },
+    stations: heapless::FnvIndexMap::new(),
+}));
+
+/// Called when the access point started to start counting from zero.
+pub(crate) fn reset_ap_traffic_stats() {
+    blocking_checks::with(|cs| {
+        let mut traffic = AP_TRAFFIC.borrow_ref_mut(cs);
+        traffic.total = ApTrafficStats::default();
+        traffic.stations.clear();
+    });
+}
+
+/// Called when a station left the access point, so the entry is free for another station.
+pub(crate) fn remove_ap_station_traffic_stats(mac: &[u8; 6]) {
+    blocking_checks::with(|cs| {
+        AP_TRAFFIC.borrow_ref_mut(cs).stations.remove(mac);
+    });
+}
+
 pub(crate) static WIFI_TX_INFLIGHT: AtomicUsize = AtomicUsize::new(0);
 
 fn decrement_inflight_counter() {
@@ -713,22 +1638,40 @@ fn decrement_inflight_counter() {
         .unwrap();
 }
 
+/// Takes one of the [`TX_QUEUE_SIZE`] slots for frames in flight, `false` if all are in use.
+///
+/// The slot is released by [`esp_wifi_tx_done_cb`], or by [`esp_wifi_send_data`] if the driver
+/// rejects the frame.
+pub(crate) fn try_reserve_tx_slot() -> bool {
+    WIFI_TX_INFLIGHT
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| {
+            (x < TX_QUEUE_SIZE).then_some(x + 1)
+        })
+        .is_ok()
+}
+
 #[ram]
 unsafe extern "C" fn esp_wifi_tx_done_cb(
-    _ifidx: u8,
+    ifidx: u8,
     _data: *mut u8,
     _data_len: *mut u16,
-    _tx_status: bool,
+    tx_status: bool,
 ) {
     trace!("esp_wifi_tx_done_cb");
 
     decrement_inflight_counter();
 
+    if !tx_status && ifidx as wifi_interface_t == wifi_interface_t_WIFI_IF_STA {
+        blocking_checks::with(|cs| STA_CONNECTION_STATS.borrow_ref_mut(cs).tx_retries += 1);
+    }
+
     #[cfg(feature = "embassy-net")]
     embassy::TRANSMIT_WAKER.wake();
 }
 
 pub(crate) fn wifi_start() -> Result<(), WifiError> {
+    let config = crate::esp_wifi_config();
+
     unsafe {
         esp_wifi_result!(esp_wifi_start())?;
 
@@ -738,44 +1681,26 @@ pub(crate) fn wifi_start() -> Result<(), WifiError> {
         if mode.is_ap() {
             esp_wifi_result!(include::esp_wifi_set_inactive_time(
                 wifi_interface_t_WIFI_IF_AP,
-                crate::CONFIG.ap_beacon_timeout
+                config.ap_beacon_timeout
             ))?;
         }
         if mode.is_sta() {
             esp_wifi_result!(include::esp_wifi_set_inactive_time(
                 wifi_interface_t_WIFI_IF_STA,
-                crate::CONFIG.beacon_timeout
+                config.beacon_timeout
             ))?;
         };
 
-        let ps_mode;
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "ps-min-modem")] {
-                ps_mode = include::wifi_ps_type_t_WIFI_PS_MIN_MODEM;
-            } else if #[cfg(feature = "ps-max-modem")] {
-                ps_mode = include::wifi_ps_type_t_WIFI_PS_MAX_MODEM;
-            } else if #[cfg(coex)] {
-                ps_mode = include::wifi_ps_type_t_WIFI_PS_MIN_MODEM;
-            } else {
-                ps_mode = include::wifi_ps_type_t_WIFI_PS_NONE;
-            }
-        };
-
-        esp_wifi_result!(esp_wifi_set_ps(ps_mode))?;
+        esp_wifi_result!(esp_wifi_set_ps(config.power_save_mode.to_raw()))?;
+    }
 
-        let mut cntry_code = [0u8; 3];
-        cntry_code[..crate::CONFIG.country_code.len()]
-            .copy_from_slice(crate::CONFIG.country_code.as_bytes());
-        cntry_code[2] = crate::CONFIG.country_code_operating_class;
+    let country = blocking_checks::with(|cs| *COUNTRY.borrow_ref(cs))
+        .unwrap_or_else(|| CountryInfo::from_config(&config));
+    apply_country(&country)?;
 
-        let country = wifi_country_t {
-            cc: core::mem::transmute(cntry_code), // [u8] -> [i8] conversion
-            schan: 1,
-            nchan: 13,
-            max_tx_power: 20,
-            policy: wifi_country_policy_t_WIFI_COUNTRY_POLICY_MANUAL,
-        };
-        esp_wifi_result!(esp_wifi_set_country(&country))?;
+    // The driver only accepts the power once started
+    if let Some(power) = blocking_checks::with(|cs| *TX_POWER.borrow_ref(cs)) {
+        esp_wifi_result!(unsafe { include::esp_wifi_set_max_tx_power(power) })?;
     }
 
     Ok(())
@@ -799,7 +1724,7 @@ unsafe extern "C" fn coex_register_start_cb(
 ///|--------------------------------------|------------|-------------|
 ///| **Power consumption**                |    High    |     Low     |
 ///| **Time required (typical behavior)** |     Low    |     High    |
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScanTypeConfig {
     /// Active scan with min and max scan time per channel. This is the default and recommended if
     /// you are unsure.
@@ -809,6 +1734,11 @@ pub enum ScanTypeConfig {
     /// 2. Wait for probe response. Wait at least `min` time, but if no response is received, wait up to `max` time.
     /// 3. Switch channel.
     /// 4. Repeat from 1.
+    ///
+    /// # Note
+    /// `min` must not be longer than `max`, otherwise the scan fails with
+    /// [`InternalWifiError::EspErrInvalidArg`]. Like for passive scans it is recommended to keep
+    /// `max` below 1500ms.
     Active {
         /// Minimum scan time per channel. Defaults to 10ms.
         min: Duration,
@@ -837,17 +1767,47 @@ impl Default for ScanTypeConfig {
     }
 }
 
-impl ScanTypeConfig {
-    fn validate(&self) {
-        if matches!(self, Self::Passive(dur) if *dur > Duration::from_millis(1500)) {
-            warn!("Passive scan duration longer than 1500ms may cause a station to disconnect from the AP");
+#[cfg(feature = "defmt")]
+impl defmt::Format for ScanTypeConfig {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Self::Active { min, max } => defmt::write!(
+                fmt,
+                "Active {{ min: {}ms, max: {}ms }}",
+                min.as_millis() as u64,
+                max.as_millis() as u64
+            ),
+            Self::Passive(dur) => defmt::write!(fmt, "Passive({}ms)", dur.as_millis() as u64),
         }
     }
 }
 
-/// Scan configuration
-#[derive(Clone, Copy, Default, PartialEq, Eq)]
-pub struct ScanConfig<'a> {
+impl ScanTypeConfig {
+    fn validate(&self) -> Result<(), WifiError> {
+        match self {
+            Self::Active { min, max } if min > max => {
+                warn!("Active scan time min is longer than max");
+                return Err(WifiError::InternalError(
+                    InternalWifiError::EspErrInvalidArg,
+                ));
+            }
+            Self::Active { max, .. } if *max > Duration::from_millis(1500) => {
+                warn!("Active scan time longer than 1500ms may cause a station to disconnect from the AP");
+            }
+            Self::Passive(dur) if *dur > Duration::from_millis(1500) => {
+                warn!("Passive scan duration longer than 1500ms may cause a station to disconnect from the AP");
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Scan configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanConfig<'a> {
     /// SSID to filter for.
     /// If [`None`] is passed, all SSIDs will be returned.
     /// If [`Some`] is passed, only the APs matching the given SSID will be returned.
@@ -866,7 +1826,114 @@ pub struct ScanConfig<'a> {
     pub scan_type: ScanTypeConfig,
 }
 
-pub(crate) fn wifi_start_scan(
+/// A BSSID advertising a given SSID, as seen during a scan.
+///
+/// See [`WifiController::sta_get_bssid_rssi_map`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BssidRssi {
+    /// MAC address of the access point.
+    pub bssid: [u8; 6],
+    /// Signal strength in dBm.
+    pub rssi: i8,
+    /// Primary channel of the access point.
+    pub channel: u8,
+}
+
+/// Length of one observation of [`WifiController::measure_channel_occupancy`], a little more
+/// than the common beacon interval of 102.4 ms.
+const OCCUPANCY_PERIOD: Duration = Duration::from_millis(110);
+
+/// Dwell time on passive channels of a mixed scan, the driver's default for passive scans.
+const MIXED_SCAN_PASSIVE_TIME: Duration = Duration::from_millis(360);
+
+/// Time a background scan stays on the home channel between two scanned channels, a little more
+/// than the common beacon interval of 102.4 ms so a beacon of the access point is received.
+const BACKGROUND_SCAN_PAUSE: Duration = Duration::from_millis(110);
+
+/// Time [`WifiController::fast_reconnect_sync`] waits for the pinned connection attempt, the
+/// driver reports the outcome within a few seconds even if the access point doesn't answer.
+const FAST_RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time to wait for the driver to report a disconnect, it's reported right after the
+/// deauthentication was sent.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Splits a background scan into one scan per channel, see
+/// [`WifiController::background_scan_sync`].
+fn background_scan_configs(config: ScanConfig<'_>) -> impl Iterator<Item = ScanConfig<'_>> {
+    let plan = active_channel_plan();
+    let channels = match config.channel {
+        Some(channel) => channel..=channel,
+        None => plan.first_channel..=plan.first_channel + plan.channel_count - 1,
+    };
+
+    channels.map(move |channel| ScanConfig {
+        channel: Some(channel),
+        ..config
+    })
+}
+
+/// Splits a mixed scan into one scan per channel, see [`WifiController::scan_mixed_sync`].
+fn mixed_scan_configs<'a>(
+    active_channels: &'a [u8],
+    passive_channels: &'a [u8],
+    config: ScanConfig<'a>,
+) -> Result<impl Iterator<Item = ScanConfig<'a>> + 'a, WifiError> {
+    if config.channel.is_some()
+        || active_channels
+            .iter()
+            .any(|channel| passive_channels.contains(channel))
+    {
+        return Err(WifiError::InternalError(
+            InternalWifiError::EspErrInvalidArg,
+        ));
+    }
+
+    let (active, passive) = match config.scan_type {
+        active @ ScanTypeConfig::Active { .. } => {
+            (active, ScanTypeConfig::Passive(MIXED_SCAN_PASSIVE_TIME))
+        }
+        passive @ ScanTypeConfig::Passive(_) => (ScanTypeConfig::default(), passive),
+    };
+
+    let with_type = move |scan_type| {
+        move |&channel| ScanConfig {
+            channel: Some(channel),
+            scan_type,
+            ..config
+        }
+    };
+
+    Ok(active_channels
+        .iter()
+        .map(with_type(active))
+        .chain(passive_channels.iter().map(with_type(passive))))
+}
+
+/// Adds the results of one scan of a mixed scan to `merged`, keeping the strongest record of
+/// each BSSID.
+fn merge_scan_results<const N: usize>(
+    merged: &mut heapless::Vec<AccessPointInfo, N>,
+    results: heapless::Vec<AccessPointInfo, N>,
+) {
+    for ap in results {
+        match merged.iter_mut().find(|known| known.bssid == ap.bssid) {
+            Some(known) if known.signal_strength < ap.signal_strength => *known = ap,
+            Some(_) => {}
+            None => {
+                merged.push(ap).ok();
+            }
+        }
+    }
+}
+
+pub(crate) fn wifi_start_scan(block: bool, config: ScanConfig<'_>) -> i32 {
+    wifi_start_scan_with_raw_config(block, config, |_| {})
+}
+
+/// Like [`wifi_start_scan`], but lets `f` change the configuration passed to the driver.
+fn wifi_start_scan_with_raw_config(
     block: bool,
     ScanConfig {
         ssid,
@@ -875,8 +1942,22 @@ pub(crate) fn wifi_start_scan(
         show_hidden,
         scan_type,
     }: ScanConfig<'_>,
+    f: impl FnOnce(&mut wifi_scan_config_t),
 ) -> i32 {
-    scan_type.validate();
+    if scan_type.validate().is_err() {
+        return include::ESP_ERR_INVALID_ARG as i32;
+    }
+
+    if let Some(channel) = channel {
+        if !active_channel_plan().contains(channel) {
+            warn!(
+                "Channel {} is not allowed in the configured country",
+                channel
+            );
+            return include::ESP_ERR_INVALID_ARG as i32;
+        }
+    }
+
     let (scan_time, scan_type) = match scan_type {
         ScanTypeConfig::Active { min, max } => (
             wifi_scan_time_t {
@@ -912,7 +1993,7 @@ pub(crate) fn wifi_start_scan(
         .map(|e| e.as_mut_ptr())
         .unwrap_or_else(core::ptr::null_mut);
 
-    let scan_config = wifi_scan_config_t {
+    let mut scan_config = wifi_scan_config_t {
         ssid,
         bssid,
         channel: channel.unwrap_or(0),
@@ -921,6 +2002,9 @@ pub(crate) fn wifi_start_scan(
         scan_time,
         home_chan_dwell_time: 0,
     };
+    f(&mut scan_config);
+
+    vendor_ie::start_capture();
 
     unsafe { esp_wifi_scan_start(&scan_config, block) }
 }
@@ -1065,7 +2149,7 @@ mod sealed {
         }
 
         fn rx_token(self) -> Option<(WifiRxToken<Self>, WifiTxToken<Self>)> {
-            let is_empty = critical_section::with(|cs| self.data_queue_rx(cs).is_empty());
+            let is_empty = blocking_checks::with(|cs| self.data_queue_rx(cs).is_empty());
 
             if !is_empty {
                 self.tx_token().map(|tx| (WifiRxToken { mode: self }, tx))
@@ -1077,6 +2161,9 @@ mod sealed {
 
         fn interface(self) -> wifi_interface_t;
 
+        /// Set while a [`WifiDevice`] of this interface exists.
+        fn device_taken(self) -> &'static AtomicBool;
+
         #[cfg(feature = "embassy-net")]
         fn register_transmit_waker(self, cx: &mut core::task::Context) {
             embassy::TRANSMIT_WAKER.register(cx.waker())
@@ -1088,10 +2175,24 @@ mod sealed {
         #[cfg(feature = "embassy-net")]
         fn register_link_state_waker(self, cx: &mut core::task::Context);
 
+        fn is_link_up(self) -> bool;
+
         #[cfg(feature = "embassy-net")]
-        fn link_state(self) -> embassy_net_driver::LinkState;
+        fn link_state(self, _cx: &mut core::task::Context) -> embassy_net_driver::LinkState {
+            if self.is_link_up() {
+                embassy_net_driver::LinkState::Up
+            } else {
+                embassy_net_driver::LinkState::Down
+            }
+        }
     }
 
+    pub trait SealedDevices {}
+
+    impl<MODE: WifiDeviceMode> SealedDevices for WifiDevice<'_, MODE> {}
+
+    impl SealedDevices for (WifiDevice<'_, WifiApDevice>, WifiDevice<'_, WifiStaDevice>) {}
+
     impl Sealed for WifiStaDevice {
         type Config = ClientConfiguration;
 
@@ -1114,6 +2215,10 @@ mod sealed {
             wifi_interface_t_WIFI_IF_STA
         }
 
+        fn device_taken(self) -> &'static AtomicBool {
+            &STA_DEVICE_TAKEN
+        }
+
         #[cfg(feature = "embassy-net")]
         fn register_receive_waker(self, cx: &mut core::task::Context) {
             embassy::STA_RECEIVE_WAKER.register(cx.waker());
@@ -1124,9 +2229,14 @@ mod sealed {
             embassy::STA_LINK_STATE_WAKER.register(cx.waker());
         }
 
+        fn is_link_up(self) -> bool {
+            matches!(get_sta_state(), WifiState::StaConnected)
+        }
+
         #[cfg(feature = "embassy-net")]
-        fn link_state(self) -> embassy_net_driver::LinkState {
-            if matches!(get_sta_state(), WifiState::StaConnected) {
+        fn link_state(self, _cx: &mut core::task::Context) -> embassy_net_driver::LinkState {
+            // The settle timer wakes the link state waker once the delay is over
+            if self.is_link_up() && !embassy::sta_link_settling() {
                 embassy_net_driver::LinkState::Up
             } else {
                 embassy_net_driver::LinkState::Down
@@ -1156,6 +2266,10 @@ mod sealed {
             wifi_interface_t_WIFI_IF_AP
         }
 
+        fn device_taken(self) -> &'static AtomicBool {
+            &AP_DEVICE_TAKEN
+        }
+
         #[cfg(feature = "embassy-net")]
         fn register_receive_waker(self, cx: &mut core::task::Context) {
             embassy::AP_RECEIVE_WAKER.register(cx.waker());
@@ -1166,13 +2280,8 @@ mod sealed {
             embassy::AP_LINK_STATE_WAKER.register(cx.waker());
         }
 
-        #[cfg(feature = "embassy-net")]
-        fn link_state(self) -> embassy_net_driver::LinkState {
-            if matches!(get_ap_state(), WifiState::ApStarted) {
-                embassy_net_driver::LinkState::Up
-            } else {
-                embassy_net_driver::LinkState::Down
-            }
+        fn is_link_up(self) -> bool {
+            matches!(get_ap_state(), WifiState::ApStarted)
         }
     }
 }
@@ -1217,7 +2326,13 @@ impl WifiDeviceMode for WifiApDevice {
     }
 }
 
+static STA_DEVICE_TAKEN: AtomicBool = AtomicBool::new(false);
+static AP_DEVICE_TAKEN: AtomicBool = AtomicBool::new(false);
+
 /// A wifi device implementing smoltcp's Device trait.
+///
+/// There is at most one device per interface, dropping it allows getting a new one from
+/// [`WifiController::sta_device`] or [`WifiController::ap_device`].
 pub struct WifiDevice<'d, MODE: WifiDeviceMode> {
     _device: PeripheralRef<'d, crate::hal::peripherals::WIFI>,
     mode: MODE,
@@ -1228,6 +2343,7 @@ impl<'d, MODE: WifiDeviceMode> WifiDevice<'d, MODE> {
         _device: PeripheralRef<'d, crate::hal::peripherals::WIFI>,
         mode: MODE,
     ) -> Self {
+        mode.device_taken().store(true, Ordering::Relaxed);
         Self { _device, mode }
     }
 
@@ -1235,6 +2351,12 @@ impl<'d, MODE: WifiDeviceMode> WifiDevice<'d, MODE> {
         self.mode.mac_address()
     }
 
+    /// Returns whether the link is up, i.e. the station is connected or the access point is
+    /// started.
+    pub fn is_link_up(&self) -> bool {
+        self.mode.is_link_up()
+    }
+
     #[cfg(not(feature = "smoltcp"))]
     pub fn receive(&mut self) -> Option<(WifiRxToken<MODE>, WifiTxToken<MODE>)> {
         self.mode.rx_token()
@@ -1246,19 +2368,61 @@ impl<'d, MODE: WifiDeviceMode> WifiDevice<'d, MODE> {
     }
 }
 
-fn convert_ap_info(record: &include::wifi_ap_record_t) -> AccessPointInfo {
-    let str_len = record
-        .ssid
-        .iter()
-        .position(|&c| c == 0)
-        .unwrap_or(record.ssid.len());
-    let ssid_ref = unsafe { core::str::from_utf8_unchecked(&record.ssid[..str_len]) };
+impl<MODE: WifiDeviceMode> Drop for WifiDevice<'_, MODE> {
+    fn drop(&mut self) {
+        self.mode.device_taken().store(false, Ordering::Release);
+    }
+}
+
+/// Converts a null terminated SSID received over the air, replacing invalid UTF-8 sequences
+/// with `U+FFFD` and truncating it if the result doesn't fit.
+fn ssid_from_raw(raw: &[u8]) -> heapless::String<32> {
+    let mut raw = &raw[..raw.iter().position(|&c| c == 0).unwrap_or(raw.len())];
+    let mut ssid = heapless::String::new();
+
+    let mut push = |chars: core::str::Chars<'_>| {
+        for c in chars {
+            if ssid.push(c).is_err() {
+                return false;
+            }
+        }
+        true
+    };
+
+    while !raw.is_empty() {
+        match core::str::from_utf8(raw) {
+            Ok(valid) => {
+                push(valid.chars());
+                break;
+            }
+            Err(err) => {
+                let (valid, invalid) = raw.split_at(err.valid_up_to());
+                let valid = unsafe { core::str::from_utf8_unchecked(valid) };
+                if !push(valid.chars()) || !push("\u{FFFD}".chars()) {
+                    break;
+                }
+
+                raw = &invalid[err.error_len().unwrap_or(invalid.len())..];
+            }
+        }
+    }
+
+    ssid
+}
 
-    let mut ssid = heapless::String::<32>::new();
-    unwrap!(ssid.push_str(ssid_ref));
+/// Rejects SSIDs containing null bytes, the driver would silently truncate them.
+fn validate_ssid(ssid: &str) -> Result<(), WifiError> {
+    if ssid.as_bytes().contains(&0) {
+        warn!("SSID contains a null byte");
+        return Err(WifiError::InternalError(InternalWifiError::EspErrWifiSsid));
+    }
+
+    Ok(())
+}
 
+fn convert_ap_info(record: &include::wifi_ap_record_t) -> AccessPointInfo {
     AccessPointInfo {
-        ssid,
+        ssid: ssid_from_raw(&record.ssid),
         bssid: record.bssid,
         channel: record.primary,
         secondary_channel: match record.second {
@@ -1268,18 +2432,483 @@ fn convert_ap_info(record: &include::wifi_ap_record_t) -> AccessPointInfo {
             _ => panic!(),
         },
         signal_strength: record.rssi,
-        protocols: EnumSet::empty(), // TODO
+        protocols: svc_protocols(ap_protocols(record)),
         auth_method: Some(AuthMethod::from_raw(record.authmode)),
     }
 }
 
+/// The protocols an access point supports according to its record.
+fn ap_protocols(record: &include::wifi_ap_record_t) -> ProtocolSet {
+    let mut protocols = ProtocolSet::empty();
+    for (supported, protocol) in [
+        (record.phy_11b(), WifiProtocol::P802D11B),
+        (record.phy_11g(), WifiProtocol::P802D11G),
+        (record.phy_11n(), WifiProtocol::P802D11N),
+        (record.phy_lr(), WifiProtocol::P802D11LR),
+        (record.phy_11ax(), WifiProtocol::P802D11AX),
+    ] {
+        if supported != 0 {
+            protocols |= protocol;
+        }
+    }
+
+    protocols
+}
+
+/// Maps `protocols` to the embedded-svc [`Protocol`]s, which are combinations of protocols.
+///
+/// A [`Protocol`] is included if all of its protocols are supported. 802.11ax has no
+/// [`Protocol`] and is left out.
+fn svc_protocols(protocols: ProtocolSet) -> EnumSet<Protocol> {
+    [
+        Protocol::P802D11B,
+        Protocol::P802D11BG,
+        Protocol::P802D11BGN,
+        Protocol::P802D11BGNLR,
+        Protocol::P802D11LR,
+    ]
+    .into_iter()
+    .filter(|protocol| protocols.is_superset(protocol.to_protocol_set()))
+    .collect()
+}
+
+fn convert_ap_info_extended(record: &include::wifi_ap_record_t) -> ExtendedAccessPointInfo {
+    ExtendedAccessPointInfo {
+        info: convert_ap_info(record),
+        country: CountryInfo::from_raw(&record.country),
+        protocols: ap_protocols(record),
+        bandwidth: if record.second == include::wifi_second_chan_t_WIFI_SECOND_CHAN_NONE {
+            Bandwidth::Ht20
+        } else {
+            Bandwidth::Ht40
+        },
+        wps: record.wps() != 0,
+        // HT and HE require QoS, the record has no flag for WMM itself
+        wmm: record.phy_11n() != 0 || record.phy_11ax() != 0,
+    }
+}
+
+/// The 2.4 GHz channels which may be used in a country.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelPlan {
+    pub first_channel: u8,
+    pub channel_count: u8,
+}
+
+impl ChannelPlan {
+    /// Channels 1 to 13, used by most countries.
+    const DEFAULT: Self = Self {
+        first_channel: 1,
+        channel_count: 13,
+    };
+
+    /// The channel plan of the country with the given ISO 3166-1 alpha-2 code.
+    ///
+    /// Countries without a plan of their own in this table get channels 1 to 13.
+    pub fn for_country(code: &str) -> Self {
+        match code {
+            // FCC and countries following its rules
+            "US" | "CA" | "TW" | "PR" | "GU" | "AS" | "VI" | "MP" | "UM" => Self {
+                first_channel: 1,
+                channel_count: 11,
+            },
+            // Channel 14 is only allowed for 802.11b
+            "JP" => Self {
+                first_channel: 1,
+                channel_count: 14,
+            },
+            _ => Self::DEFAULT,
+        }
+    }
+
+    pub fn contains(&self, channel: u8) -> bool {
+        channel >= self.first_channel && channel - self.first_channel < self.channel_count
+    }
+}
+
+/// The channel plan of the configured country, used to validate scan and access point channels.
+static ACTIVE_CHANNEL_PLAN: Mutex<RefCell<ChannelPlan>> =
+    Mutex::new(RefCell::new(ChannelPlan::DEFAULT));
+
+pub(crate) fn active_channel_plan() -> ChannelPlan {
+    blocking_checks::with(|cs| *ACTIVE_CHANNEL_PLAN.borrow_ref(cs))
+}
+
+pub(crate) fn set_active_channel_plan(plan: ChannelPlan) {
+    blocking_checks::with(|cs| *ACTIVE_CHANNEL_PLAN.borrow_ref_mut(cs) = plan);
+}
+
+/// Whether the driver follows the country of the access point the station is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CountryPolicy {
+    /// Use the country advertised by the connected access point, this country otherwise
+    Auto,
+    /// Always use this country
+    #[default]
+    Manual,
+}
+
+/// Regulatory domain, e.g. as advertised by an access point in its beacons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CountryInfo {
+    /// ISO 3166-1 alpha-2 country code
+    pub code: [u8; 2],
+    /// Third octet of the country string: `b' '` for any environment, `b'I'` indoor, `b'O'`
+    /// outdoor
+    pub environment: u8,
+    pub first_channel: u8,
+    pub channel_count: u8,
+    /// Maximum transmit power in dBm
+    pub max_tx_power: i8,
+    /// Whether the country of the connected access point replaces this one
+    pub policy: CountryPolicy,
+}
+
+impl CountryInfo {
+    /// The country configured with [`crate::EspWifiConfig::country_code`].
+    fn from_config(config: &crate::EspWifiConfig) -> Self {
+        let mut code = [0u8; 2];
+        code[..config.country_code.len()].copy_from_slice(config.country_code.as_bytes());
+        let plan = ChannelPlan::for_country(config.country_code);
+
+        Self {
+            code,
+            environment: match config.country_code_operating_class {
+                // Any environment
+                0 => b' ',
+                other => other,
+            },
+            first_channel: plan.first_channel,
+            channel_count: plan.channel_count,
+            max_tx_power: 20,
+            policy: CountryPolicy::Manual,
+        }
+    }
+
+    fn from_raw(country: &include::wifi_country_t) -> Option<Self> {
+        if country.cc[0] == 0 {
+            return None;
+        }
+
+        Some(Self {
+            code: [country.cc[0] as u8, country.cc[1] as u8],
+            environment: country.cc[2] as u8,
+            first_channel: country.schan,
+            channel_count: country.nchan,
+            max_tx_power: country.max_tx_power,
+            policy: if country.policy == wifi_country_policy_t_WIFI_COUNTRY_POLICY_AUTO {
+                CountryPolicy::Auto
+            } else {
+                CountryPolicy::Manual
+            },
+        })
+    }
+
+    /// The country code as a string, empty if it isn't valid ASCII.
+    pub fn code_str(&self) -> &str {
+        core::str::from_utf8(&self.code).unwrap_or_default()
+    }
+
+    /// The channels of the country.
+    pub fn channel_plan(&self) -> ChannelPlan {
+        ChannelPlan {
+            first_channel: self.first_channel,
+            channel_count: self.channel_count,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.code.iter().all(u8::is_ascii_uppercase)
+            && self.first_channel >= 1
+            && self.channel_count >= 1
+            && self.first_channel as u16 + self.channel_count as u16 - 1 <= 14
+    }
+
+    fn to_raw(self) -> wifi_country_t {
+        wifi_country_t {
+            // [u8] -> [i8] conversion
+            cc: [self.code[0] as _, self.code[1] as _, self.environment as _],
+            schan: self.first_channel,
+            nchan: self.channel_count,
+            max_tx_power: self.max_tx_power,
+            policy: match self.policy {
+                CountryPolicy::Auto => wifi_country_policy_t_WIFI_COUNTRY_POLICY_AUTO,
+                CountryPolicy::Manual => wifi_country_policy_t_WIFI_COUNTRY_POLICY_MANUAL,
+            },
+        }
+    }
+}
+
+/// The country set with [`WifiController::set_country`], replacing the configured one.
+static COUNTRY: Mutex<RefCell<Option<CountryInfo>>> = Mutex::new(RefCell::new(None));
+
+/// How [`WifiController::set_country`] treats a country which differs from the one advertised
+/// by the access point the station is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CountryCheck {
+    /// Log a warning and set the country anyway
+    #[default]
+    Warn,
+    /// Reject the country with [`WifiError::CountryMismatch`]
+    Strict,
+}
+
+static COUNTRY_CHECK: Mutex<RefCell<CountryCheck>> = Mutex::new(RefCell::new(CountryCheck::Warn));
+
+/// The transmit power set with [`WifiController::set_tx_power_dbm`], in units of 0.25 dBm.
+static TX_POWER: Mutex<RefCell<Option<i8>>> = Mutex::new(RefCell::new(None));
+
+fn apply_country(country: &CountryInfo) -> Result<(), WifiError> {
+    esp_wifi_result!(unsafe { esp_wifi_set_country(&country.to_raw()) })
+}
+
+/// A scan result with the details which don't fit into [`AccessPointInfo`], see
+/// [`WifiController::scan_extended_sync`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedAccessPointInfo {
+    pub info: AccessPointInfo,
+    /// The country advertised by the access point, if any
+    pub country: Option<CountryInfo>,
+    /// The protocols the access point supports, unlike [`AccessPointInfo::protocols`] including
+    /// 802.11ax
+    pub protocols: ProtocolSet,
+    /// The bandwidth the access point operates with, for
+    /// [`WifiController::sta_get_ap_info`] the bandwidth of the link to it
+    pub bandwidth: Bandwidth,
+    /// The access point supports WPS
+    pub wps: bool,
+    /// The access point supports WMM, e.g. for QoS decisions
+    ///
+    /// The driver doesn't report WMM directly, this is derived from 802.11n or 802.11ax support
+    /// which require it. Legacy access points with WMM are reported as not supporting it.
+    pub wmm: bool,
+}
+
+/// A scan result together with the vendor specific information elements of the access point, see
+/// [`WifiController::scan_results_raw`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawApRecord {
+    pub info: AccessPointInfo,
+    /// The vendor specific IEs (element ID 221) of the access point's beacons and probe responses,
+    /// each including its element ID and length. IEs which don't fit are left out.
+    pub ie_data: heapless::Vec<u8, 512>,
+    /// The data decoded by the parsers registered with
+    /// [`WifiController::register_ie_parser`], with the element ID of the decoded IE.
+    pub custom_ie_data: heapless::Vec<(u8, CustomIeData), MAX_IE_PARSERS>,
+}
+
+/// Data decoded from an IE by a parser registered with [`WifiController::register_ie_parser`].
+///
+/// The layout of the bytes is up to the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CustomIeData(pub [u8; 64]);
+
+pub use vendor_ie::{IeParser, MAX_IE_PARSERS};
+
+#[cfg(feature = "async")]
+pub use asynch::StationRssiMonitor;
+
+/// State of the station interface, see [`ConnectionStatus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StaStatus {
+    /// The station is not started
+    Stopped,
+    /// The station is started but not connected and no connection attempt is in progress
+    Idle,
+    /// A connection attempt is in progress
+    Connecting,
+    /// The station is connected to an access point
+    Connected {
+        /// BSSID of the access point
+        bssid: [u8; 6],
+        /// Primary channel of the access point
+        channel: u8,
+        /// Signal strength of the access point in dBm
+        rssi: i8,
+    },
+    /// The station lost or failed to establish its connection
+    Disconnected {
+        /// One of the `WIFI_REASON_*` codes, 0 if unknown
+        reason: u8,
+    },
+}
+
+/// State of the access point interface, see [`ConnectionStatus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ApStatus {
+    /// The access point is not started
+    Stopped,
+    /// The access point is started
+    Started {
+        /// Number of associated stations
+        stations: usize,
+    },
+}
+
+/// Combined state of both interfaces as returned by [`WifiController::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionStatus {
+    pub sta: StaStatus,
+    pub ap: ApStatus,
+}
+
+/// Reads the record of the access point the station is associated with.
+fn sta_ap_record() -> Result<include::wifi_ap_record_t, WifiError> {
+    let mut record = MaybeUninit::<include::wifi_ap_record_t>::uninit();
+    esp_wifi_result!(unsafe { include::esp_wifi_sta_get_ap_info(record.as_mut_ptr()) })?;
+
+    Ok(unsafe { record.assume_init() })
+}
+
+const DEEP_SLEEP_WIFI_STATE_MAGIC: u32 = 0x5754_4453;
+
+/// Connection details retained across deep sleep, used to reconnect quickly after waking up.
+///
+/// [`WifiController::deep_sleep_prepare`] keeps a copy in RTC memory which can be read back
+/// with [`DeepSleepWifiState::from_rtc_memory`] after waking up.
+///
+/// The PMKID negotiated with the access point is kept by the driver and is not exposed, so the
+/// 4-way handshake still takes place on reconnect. Skipping the scan is what saves the most time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeepSleepWifiState {
+    magic: u32,
+    /// BSSID of the access point the station was associated with.
+    pub bssid: [u8; 6],
+    /// Primary channel of the access point the station was associated with.
+    pub channel: u8,
+}
+
+impl DeepSleepWifiState {
+    #[cfg(not(esp32c2))]
+    const EMPTY: Self = Self {
+        magic: 0,
+        bssid: [0; 6],
+        channel: 0,
+    };
+
+    /// Returns the state saved by [`WifiController::deep_sleep_prepare`] before going to deep
+    /// sleep, or [`None`] if there is none (e.g. after a power-on reset).
+    #[cfg(not(esp32c2))]
+    pub fn from_rtc_memory() -> Option<Self> {
+        let state = unsafe { DEEP_SLEEP_WIFI_STATE };
+
+        if state.magic == DEEP_SLEEP_WIFI_STATE_MAGIC {
+            Some(state)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(esp32c2))]
+#[ram(rtc_fast, persistent)]
+static mut DEEP_SLEEP_WIFI_STATE: DeepSleepWifiState = DeepSleepWifiState::EMPTY;
+
+/// The access point the station was last connected to, see [`WifiController::fast_reconnect_sync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LastAccessPoint {
+    pub bssid: [u8; 6],
+    /// Primary channel of the access point
+    pub channel: u8,
+}
+
+impl From<DeepSleepWifiState> for LastAccessPoint {
+    fn from(state: DeepSleepWifiState) -> Self {
+        Self {
+            bssid: state.bssid,
+            channel: state.channel,
+        }
+    }
+}
+
+static LAST_ACCESS_POINT: Mutex<RefCell<Option<LastAccessPoint>>> = Mutex::new(RefCell::new(None));
+
+/// Remembers the access point the station connected to, called on `StaConnected`.
+pub(crate) fn set_last_access_point(ap: LastAccessPoint) {
+    blocking_checks::with(|cs| *LAST_ACCESS_POINT.borrow_ref_mut(cs) = Some(ap));
+}
+
+const WARM_START_DATA_MAGIC: u32 = 0x5741_524d;
+
+/// What [`crate::initialize_warm`] needs to bring up WiFi quickly after deep sleep: the RF
+/// calibration data and the access point to reconnect to.
+///
+/// Create it with [`WifiController::export_warm_start_data`] before going to sleep and keep it
+/// e.g. in RTC fast memory. It is specific to the chip and the esp-wifi version it was created
+/// with.
+#[derive(Clone, Copy)]
+pub struct WarmStartData {
+    magic: u32,
+    calibration: [u8; crate::common_adapter::PHY_CALIBRATION_DATA_SIZE],
+    /// The access point the station was connected to
+    pub access_point: Option<LastAccessPoint>,
+}
+
+impl WarmStartData {
+    /// Data which makes [`crate::initialize_warm`] behave like a cold start, e.g. to initialize
+    /// memory holding it.
+    pub const EMPTY: Self = Self {
+        magic: 0,
+        calibration: [0; crate::common_adapter::PHY_CALIBRATION_DATA_SIZE],
+        access_point: None,
+    };
+
+    /// Whether this was created by [`WifiController::export_warm_start_data`], as opposed to
+    /// e.g. uninitialized memory after a power-on reset.
+    pub fn is_valid(&self) -> bool {
+        self.magic == WARM_START_DATA_MAGIC
+    }
+
+    /// Restores the calibration data and the access point hint, called before initializing.
+    pub(crate) fn restore(&self) {
+        if !self.is_valid() {
+            debug!("No warm start data, doing a cold start");
+            return;
+        }
+
+        crate::common_adapter::set_phy_calibration_data(&self.calibration);
+        blocking_checks::with(|cs| *LAST_ACCESS_POINT.borrow_ref_mut(cs) = self.access_point);
+    }
+}
+
+impl core::fmt::Debug for WarmStartData {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WarmStartData")
+            .field("valid", &self.is_valid())
+            .field("access_point", &self.access_point)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A wifi controller implementing embedded_svc::Wifi traits
 pub struct WifiController<'d> {
     _device: PeripheralRef<'d, crate::hal::peripherals::WIFI>,
     config: Configuration,
 }
 
+/// The [`WifiDevice`]s created together with a [`WifiController`], a single device or an
+/// `(AP device, STA device)` tuple. See [`crate::deinit`].
+pub trait WifiDevices<'d>: sealed::SealedDevices {}
+
+impl<'d, MODE: WifiDeviceMode> WifiDevices<'d> for WifiDevice<'d, MODE> {}
+
+impl<'d> WifiDevices<'d> for (WifiDevice<'d, WifiApDevice>, WifiDevice<'d, WifiStaDevice>) {}
+
 impl<'d> WifiController<'d> {
+    pub(crate) fn into_peripheral(self) -> PeripheralRef<'d, crate::hal::peripherals::WIFI> {
+        self._device
+    }
+
     pub(crate) fn new_with_config(
         inited: &EspWifiInitialization,
         _device: PeripheralRef<'d, crate::hal::peripherals::WIFI>,
@@ -1289,65 +2918,1675 @@ impl<'d> WifiController<'d> {
             return Err(WifiError::NotInitialized);
         }
 
-        // We set up the controller with the default config because we need to call
-        // `set_configuration` to apply the actual configuration, and it will update the stored
-        // configuration anyway.
-        let mut this = Self {
-            _device,
-            config: Default::default(),
-        };
+        // `reconfigure` stores the configuration once it's applied
+        let mut this = Self {
+            _device,
+            config: Configuration::None,
+        };
+
+        this.reconfigure(&config)?;
+        Ok(this)
+    }
+
+    /// Creates the [`WifiDevice`] of the station interface, e.g. after the one created with the
+    /// controller was dropped or after switching to AP-STA mode with [`Self::reconfigure`].
+    ///
+    /// Returns [`WifiError::DeviceInUse`] while another station device exists.
+    pub fn sta_device(&mut self) -> Result<WifiDevice<'d, WifiStaDevice>, WifiError> {
+        self.device(WifiStaDevice)
+    }
+
+    /// Creates the [`WifiDevice`] of the access point interface, see [`Self::sta_device`].
+    pub fn ap_device(&mut self) -> Result<WifiDevice<'d, WifiApDevice>, WifiError> {
+        self.device(WifiApDevice)
+    }
+
+    fn device<MODE: WifiDeviceMode>(
+        &mut self,
+        mode: MODE,
+    ) -> Result<WifiDevice<'d, MODE>, WifiError> {
+        let actual = WifiMode::try_from(&self.config)?;
+        let available = match mode.mode() {
+            WifiMode::Ap => actual.is_ap(),
+            _ => actual.is_sta(),
+        };
+        if !available {
+            return Err(WifiError::WrongMode {
+                expected: mode.mode(),
+                actual,
+            });
+        }
+
+        // The flag makes sure there is only one device per interface, taking it has to be a
+        // single step so two callers can't both see it cleared
+        if mode
+            .device_taken()
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(WifiError::DeviceInUse);
+        }
+
+        Ok(WifiDevice::new(
+            unsafe { self._device.clone_unchecked() },
+            mode,
+        ))
+    }
+
+    /// Applies `conf` as a whole, switching the mode if the interfaces change, e.g. from
+    /// [`Configuration::Client`] to [`Configuration::Mixed`].
+    ///
+    /// Either everything is applied or nothing: if the mode or one of the interfaces is
+    /// rejected by the driver, the previous mode and configuration are restored and the stored
+    /// configuration stays unchanged. Unlike `set_configuration` this doesn't merge `conf` into
+    /// the current configuration, a [`Configuration::Client`] removes the access point.
+    ///
+    /// The devices of interfaces which are removed have to be dropped first, otherwise
+    /// [`WifiError::DeviceInUse`] is returned. Get the devices of new interfaces with
+    /// [`Self::sta_device`] and [`Self::ap_device`].
+    pub fn reconfigure(&mut self, conf: &Configuration) -> Result<(), WifiError> {
+        let mode = WifiMode::try_from(conf)?;
+        if (!mode.is_sta() && STA_DEVICE_TAKEN.load(Ordering::Relaxed))
+            || (!mode.is_ap() && AP_DEVICE_TAKEN.load(Ordering::Relaxed))
+        {
+            return Err(WifiError::DeviceInUse);
+        }
+
+        let previous_mode = WifiMode::try_from(&self.config).ok();
+        let mode_changed = previous_mode != Some(mode);
+        if mode_changed {
+            esp_wifi_result!(unsafe { esp_wifi_set_mode(mode.into()) })?;
+            debug!("Wifi mode {:?} set", mode);
+        }
+
+        if let Err(err) = apply_config(conf) {
+            warn!("Applying the configuration failed, restoring the previous one");
+            if self.restore_config(mode_changed, previous_mode).is_err() {
+                warn!("Restoring the previous configuration failed");
+            }
+            return Err(err);
+        }
+
+        self.config = conf.clone();
+        Ok(())
+    }
+
+    /// Brings the driver back to the stored configuration after a failed [`Self::reconfigure`],
+    /// switching back to `previous_mode` first if the mode was changed.
+    fn restore_config(
+        &self,
+        mode_changed: bool,
+        previous_mode: Option<WifiMode>,
+    ) -> Result<(), WifiError> {
+        if mode_changed {
+            let raw_mode = previous_mode.map_or(wifi_mode_t_WIFI_MODE_NULL, Into::into);
+            esp_wifi_result!(unsafe { esp_wifi_set_mode(raw_mode) })?;
+        }
+
+        match self.config {
+            Configuration::None => Ok(()),
+            ref config => apply_config(config),
+        }
+    }
+
+    /// Sets the mode and applies the configuration again, without any checks.
+    fn reapply_config(&self) -> Result<(), WifiError> {
+        let mode = WifiMode::try_from(&self.config)?;
+        esp_wifi_result!(unsafe { esp_wifi_set_mode(mode.into()) })?;
+        apply_config(&self.config)
+    }
+
+    /// Set the PHY protocol used on `interface`, e.g. to force the station to 802.11b/g only.
+    ///
+    /// The default for both interfaces is
+    /// `WIFI_PROTOCOL_11B|WIFI_PROTOCOL_11G|WIFI_PROTOCOL_11N`. See [`Self::set_protocol`] to
+    /// pass a [`ProtocolSet`] directly.
+    ///
+    /// # Arguments:
+    ///
+    /// * `interface` - The interface to configure
+    /// * `protocol` - The desired protocol
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use embedded_svc::wifi::Protocol;
+    /// use esp_wifi::wifi::WifiInterface;
+    /// controller.set_phy_protocol(WifiInterface::Sta, Protocol::P802D11BG)?;
+    /// ```
+    pub fn set_phy_protocol(
+        &mut self,
+        interface: WifiInterface,
+        protocol: Protocol,
+    ) -> Result<(), WifiError> {
+        self.set_protocol(interface, protocol.to_protocol_set())
+    }
+
+    /// Set the protocols enabled on `interface`.
+    ///
+    /// Fails with [`InternalWifiError::EspErrNotSupported`] if `protocols` contains a protocol
+    /// not available on `interface` of this chip, see [`chip_capabilities`].
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use esp_wifi::wifi::{WifiInterface, WifiProtocol};
+    /// controller.set_protocol(
+    ///     WifiInterface::Sta,
+    ///     WifiProtocol::P802D11B | WifiProtocol::P802D11G | WifiProtocol::P802D11N,
+    /// )?;
+    /// ```
+    pub fn set_protocol(
+        &mut self,
+        interface: WifiInterface,
+        protocols: impl Into<ProtocolSet>,
+    ) -> Result<(), WifiError> {
+        let protocols = protocols.into();
+
+        if protocols.is_empty() {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        if !chip_capabilities()
+            .protocols(interface)
+            .is_superset(protocols)
+        {
+            warn!(
+                "Protocols {:#x} are not supported on {:?} of this chip",
+                protocols.as_u8(),
+                interface
+            );
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrNotSupported,
+            ));
+        }
+
+        esp_wifi_result!(unsafe { esp_wifi_set_protocol(interface.to_raw(), protocols.as_u8()) })
+    }
+
+    /// Whether the chip can run the station and the access point at the same time, i.e. whether
+    /// [`new_ap_sta`] can be used.
+    ///
+    /// All supported chips can, but as there is only one radio both interfaces always use the same
+    /// channel: the access point follows the channel of the access point the station connects to.
+    pub fn can_do_apsta(&self) -> bool {
+        true
+    }
+
+    /// Moves the access point to `channel`.
+    ///
+    /// There is only one radio, so while the station is connected the access point has to use
+    /// the channel of the station's access point. In that case a different `channel` is
+    /// rejected with [`WifiError::ChannelDictatedBySta`] and nothing changes, instead of the
+    /// driver silently keeping the station's channel. Disconnect the station first to move the
+    /// access point. `channel` must be allowed by the configured country.
+    pub fn set_ap_channel(&mut self, channel: u8) -> Result<(), WifiError> {
+        self.ensure_ap_mode()?;
+
+        if get_sta_state() == WifiState::StaConnected {
+            let sta_channel = sta_ap_record()?.primary;
+            if sta_channel != channel {
+                warn!(
+                    "The access point has to stay on channel {} of the connected station",
+                    sta_channel
+                );
+                return Err(WifiError::ChannelDictatedBySta { sta_channel });
+            }
+        }
+
+        let mut config = self.config.clone();
+        if let Configuration::AccessPoint(ap) | Configuration::Mixed(_, ap) = &mut config {
+            ap.channel = channel;
+            apply_ap_config(ap)?;
+        }
+        self.config = config;
+
+        Ok(())
+    }
+
+    pub fn is_sta_enabled(&self) -> Result<bool, WifiError> {
+        WifiMode::try_from(&self.config).map(|m| m.is_sta())
+    }
+
+    pub fn is_ap_enabled(&self) -> Result<bool, WifiError> {
+        WifiMode::try_from(&self.config).map(|m| m.is_ap())
+    }
+
+    /// Whether the station interface is started, connected or not.
+    pub fn is_sta_started(&self) -> bool {
+        matches!(
+            get_sta_state(),
+            WifiState::StaStarted | WifiState::StaConnected | WifiState::StaDisconnected
+        )
+    }
+
+    /// Whether the access point interface is started.
+    pub fn is_ap_started(&self) -> bool {
+        matches!(get_ap_state(), WifiState::ApStarted)
+    }
+
+    /// Blocks until the driver signalled [`WifiEvent::WifiReady`].
+    ///
+    /// This is the point to apply configuration which has to be in place before the first
+    /// [`start`](embedded_svc::wifi::Wifi::start), e.g. the country, the protocols or the power
+    /// save mode. The driver is initialized synchronously by [`crate::initialize`], so once a
+    /// controller exists it's ready and this returns right away, consuming a pending
+    /// `WifiReady` event.
+    pub fn wait_for_wifi_ready_sync(&mut self) {
+        while !Self::take_wifi_ready() {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Whether the driver is ready, consuming a pending [`WifiEvent::WifiReady`].
+    fn take_wifi_ready() -> bool {
+        let event =
+            blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(WifiEvent::WifiReady));
+
+        event || WIFI_INITIALIZED.load(Ordering::Relaxed)
+    }
+
+    /// Sets the events which are posted, the others are dropped as soon as the driver reports
+    /// them: they aren't recorded, don't wake any waiting future and don't count for
+    /// diagnostics.
+    ///
+    /// All events are posted by default, except [`WifiEvent::ApProbereqrecved`] which the
+    /// driver itself doesn't report unless it is part of `events`. It's sent for every probe
+    /// request received, so only enable it when needed.
+    ///
+    /// [`WifiEvent::WifiReady`], [`WifiEvent::ScanDone`], [`WifiEvent::StaStart`],
+    /// [`WifiEvent::StaStop`], [`WifiEvent::StaConnected`], [`WifiEvent::StaDisconnected`],
+    /// [`WifiEvent::ApStart`] and [`WifiEvent::ApStop`] can't be masked, esp-wifi needs them to
+    /// track the state of the interfaces.
+    pub fn set_event_mask(&mut self, events: EnumSet<WifiEvent>) -> Result<(), WifiError> {
+        if !(!events & UNMASKABLE_EVENTS).is_empty() {
+            warn!("Some of the masked events are needed by esp-wifi and stay enabled");
+        }
+        let masked = !events - UNMASKABLE_EVENTS;
+
+        let driver_mask = if masked.contains(WifiEvent::ApProbereqrecved) {
+            WIFI_EVENT_MASK_AP_PROBEREQRECVED
+        } else {
+            include::WIFI_EVENT_MASK_NONE
+        };
+        esp_wifi_result!(unsafe { include::esp_wifi_set_event_mask(driver_mask) })?;
+
+        blocking_checks::with(|cs| *MASKED_EVENTS.borrow_ref_mut(cs) = masked);
+
+        Ok(())
+    }
+
+    /// Fails with [`WifiError::WrongMode`] unless the station is configured.
+    fn ensure_sta_mode(&self) -> Result<(), WifiError> {
+        let mode = WifiMode::try_from(&self.config)?;
+        if !mode.is_sta() {
+            return Err(WifiError::WrongMode {
+                expected: WifiMode::Sta,
+                actual: mode,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [`WifiError::WrongMode`] unless the access point is configured.
+    fn ensure_ap_mode(&self) -> Result<(), WifiError> {
+        let mode = WifiMode::try_from(&self.config)?;
+        if !mode.is_ap() {
+            return Err(WifiError::WrongMode {
+                expected: WifiMode::Ap,
+                actual: mode,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// A blocking wifi network scan with caller-provided scanning options.
+    ///
+    /// At most `N` access points are returned, the second element of the tuple is the total
+    /// number found. Use [`Self::scan_for_each_sync`] to visit every result without a fixed bound.
+    pub fn scan_with_config_sync<const N: usize>(
+        &mut self,
+        config: ScanConfig<'_>,
+    ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+        self.ensure_started()?;
+        esp_wifi_result!(crate::wifi::wifi_start_scan(true, config))?;
+
+        let count = self.scan_result_count()?;
+        let result = self.scan_results()?;
+
+        Ok((result, count))
+    }
+
+    /// Scans `active_channels` actively and `passive_channels` passively, e.g. for regions in
+    /// which some channels must only be scanned passively.
+    ///
+    /// The driver uses one scan type per scan, so every channel is scanned on its own and the
+    /// results are merged, keeping the strongest record per BSSID. `config.scan_type` sets the
+    /// timing of the channels of its type, the others use the defaults: 10 to 20 ms for active
+    /// and 360 ms for passive channels. `config.channel` must be `None` and a channel can't be
+    /// in both lists, otherwise [`InternalWifiError::EspErrInvalidArg`] is returned.
+    ///
+    /// Returns the merged results, strongest first, and the sum of the access points found by
+    /// the scans of all channels.
+    pub fn scan_mixed_sync<const N: usize>(
+        &mut self,
+        active_channels: &[u8],
+        passive_channels: &[u8],
+        config: ScanConfig<'_>,
+    ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+        let mut merged = heapless::Vec::new();
+        let mut total = 0;
+        for config in mixed_scan_configs(active_channels, passive_channels, config)? {
+            let (results, count) = self.scan_with_config_sync::<N>(config)?;
+            merge_scan_results(&mut merged, results);
+            total += count;
+        }
+        merged.sort_unstable_by(|a, b| b.signal_strength.cmp(&a.signal_strength));
+
+        Ok((merged, total))
+    }
+
+    /// A blocking scan which is safe to run while the station is connected.
+    ///
+    /// A normal scan visits every channel in one go, the station misses the beacons of its
+    /// access point in the meantime and may get disconnected. Instead, while the station is
+    /// connected, every channel is scanned on its own with a pause of a little more than a
+    /// beacon interval on the home channel in between. This makes the scan take several times
+    /// longer. Keep the scan times of `config` short, the defaults are fine.
+    ///
+    /// Without a connection this is the same as [`Self::scan_with_config_sync`]. Returns the
+    /// merged results, strongest first, and the sum of the access points found by the scans of
+    /// all channels.
+    ///
+    /// The pauses are busy waits: with 13 channels there are 12 of them, so the CPU spins for
+    /// about 1.3 s on top of the scans and other code only runs when the scheduler preempts it.
+    /// The async `background_scan` waits with a timer instead.
+    pub fn background_scan_sync<const N: usize>(
+        &mut self,
+        config: ScanConfig<'_>,
+    ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+        if get_sta_state() != WifiState::StaConnected {
+            return self.scan_with_config_sync(config);
+        }
+
+        let mut merged = heapless::Vec::new();
+        let mut total = 0;
+        for (i, config) in background_scan_configs(config).enumerate() {
+            if i > 0 {
+                let resume_at = crate::current_millis() + BACKGROUND_SCAN_PAUSE.as_millis() as u64;
+                while crate::current_millis() < resume_at {
+                    core::hint::spin_loop();
+                }
+            }
+
+            let (results, count) = self.scan_with_config_sync::<N>(config)?;
+            merge_scan_results(&mut merged, results);
+            total += count;
+        }
+        merged.sort_unstable_by(|a, b| b.signal_strength.cmp(&a.signal_strength));
+
+        Ok((merged, total))
+    }
+
+    /// Scans for the access points advertising `ssid` and returns their BSSID, RSSI and channel,
+    /// strongest first.
+    ///
+    /// This is meant to feed roaming decisions, i.e. to find out whether there is a better BSSID
+    /// than the one the station is currently associated with.
+    pub fn sta_get_bssid_rssi_map<const N: usize>(
+        &mut self,
+        ssid: &str,
+    ) -> Result<heapless::Vec<BssidRssi, N>, WifiError> {
+        let (aps, _) = self.scan_with_config_sync::<N>(ScanConfig {
+            ssid: Some(ssid),
+            ..Default::default()
+        })?;
+
+        let mut map: heapless::Vec<BssidRssi, N> = aps
+            .iter()
+            .filter(|ap| ap.ssid == ssid)
+            .map(|ap| BssidRssi {
+                bssid: ap.bssid,
+                rssi: ap.signal_strength,
+                channel: ap.channel,
+            })
+            .collect();
+        map.sort_unstable_by(|a, b| b.rssi.cmp(&a.rssi));
+
+        Ok(map)
+    }
+
+    /// Scans for the access points advertising `ssid` and returns how many there are.
+    ///
+    /// More than one means `ssid` is a network of several access points, in which roaming
+    /// between them can be worthwhile.
+    pub fn get_ap_record_count_for_ssid(&mut self, ssid: &str) -> Result<usize, WifiError> {
+        let mut count = 0;
+        let config = ScanConfig {
+            ssid: Some(ssid),
+            ..Default::default()
+        };
+        self.scan_for_each_sync(config, |ap| {
+            if ap.ssid == ssid {
+                count += 1;
+            }
+            ControlFlow::Continue(())
+        })?;
+
+        Ok(count)
+    }
+
+    /// Scans for the other access points advertising `ssid`, i.e. all but the one the station
+    /// is connected to, and returns their BSSID, RSSI and channel, strongest first.
+    ///
+    /// These are the candidates a roaming decision can choose from. If there are more than `N`,
+    /// the strongest are kept. If the station isn't connected every access point of `ssid` is
+    /// returned, like [`Self::sta_get_bssid_rssi_map`].
+    pub fn scan_roaming_candidates<const N: usize>(
+        &mut self,
+        ssid: &str,
+    ) -> Result<heapless::Vec<BssidRssi, N>, WifiError> {
+        let connected_bssid = if get_sta_state() == WifiState::StaConnected {
+            Some(sta_ap_record()?.bssid)
+        } else {
+            None
+        };
+
+        let mut candidates = heapless::Vec::<BssidRssi, N>::new();
+        let config = ScanConfig {
+            ssid: Some(ssid),
+            ..Default::default()
+        };
+        self.scan_for_each_sync(config, |ap| {
+            if ap.ssid != ssid || Some(ap.bssid) == connected_bssid {
+                return ControlFlow::Continue(());
+            }
+
+            let candidate = BssidRssi {
+                bssid: ap.bssid,
+                rssi: ap.signal_strength,
+                channel: ap.channel,
+            };
+            if let Err(candidate) = candidates.push(candidate) {
+                // Full, replace the weakest if this one is stronger
+                if let Some(weakest) = candidates.iter_mut().min_by_key(|known| known.rssi) {
+                    if weakest.rssi < candidate.rssi {
+                        *weakest = candidate;
+                    }
+                }
+            }
+            ControlFlow::Continue(())
+        })?;
+        candidates.sort_unstable_by(|a, b| b.rssi.cmp(&a.rssi));
+
+        Ok(candidates)
+    }
+
+    /// Transmits the Ethernet frame `frame` on `interface` without copying it into the static
+    /// buffer the [`WifiDevice`]s use first.
+    ///
+    /// `esp_wifi_internal_tx` copies the frame into a buffer of the driver before it returns, so
+    /// `frame` can be reused as soon as this returns and there are no alignment requirements.
+    /// The frame is only taken as `&mut` because the driver needs a mutable pointer, it isn't
+    /// modified. The frame counts against the same limit of frames in flight as the devices'
+    /// frames, when it's reached this fails with [`InternalWifiError::EspErrWifiWouldBlock`] and
+    /// the frame should be sent again later.
+    ///
+    /// Frames longer than the MTU plus the Ethernet header are rejected with
+    /// [`InternalWifiError::EspErrInvalidArg`].
+    pub fn transmit_buffer(
+        &mut self,
+        interface: WifiInterface,
+        frame: &mut [u8],
+    ) -> Result<(), WifiError> {
+        match interface {
+            WifiInterface::Sta => self.ensure_sta_mode()?,
+            WifiInterface::Ap => self.ensure_ap_mode()?,
+        }
+
+        if frame.is_empty() || frame.len() > DATA_FRAME_SIZE {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        if !try_reserve_tx_slot() {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrWifiWouldBlock,
+            ));
+        }
+
+        esp_wifi_result!(esp_wifi_send_data(interface.to_raw(), frame))
+    }
+
+    /// Broadcasts a gratuitous ARP request announcing `ip` on the STA interface.
+    ///
+    /// Sending this after acquiring an address (e.g. from DHCP) updates the ARP caches of the
+    /// neighboring devices. The STA MAC address is used as the sender hardware address.
+    ///
+    /// The frame is sent with [`Self::transmit_buffer`], so this fails with
+    /// [`InternalWifiError::EspErrWifiWouldBlock`] when too many frames are in flight.
+    pub fn send_gratuitous_arp(&mut self, ip: [u8; 4]) -> Result<(), WifiError> {
+        if get_sta_state() != WifiState::StaConnected {
+            return Err(WifiError::Disconnected);
+        }
+
+        let mut mac = [0u8; 6];
+        get_sta_mac(&mut mac);
+
+        let mut frame = [0u8; 42];
+        // Ethernet header: broadcast destination, ARP ethertype
+        frame[0..6].copy_from_slice(&[0xff; 6]);
+        frame[6..12].copy_from_slice(&mac);
+        frame[12..14].copy_from_slice(&[0x08, 0x06]);
+        // ARP request for Ethernet / IPv4 where sender and target protocol address are both `ip`
+        frame[14..16].copy_from_slice(&[0x00, 0x01]);
+        frame[16..18].copy_from_slice(&[0x08, 0x00]);
+        frame[18] = 6;
+        frame[19] = 4;
+        frame[20..22].copy_from_slice(&[0x00, 0x01]);
+        frame[22..28].copy_from_slice(&mac);
+        frame[28..32].copy_from_slice(&ip);
+        frame[38..42].copy_from_slice(&ip);
+
+        self.transmit_buffer(WifiInterface::Sta, &mut frame)
+    }
+
+    /// A blocking wifi network scan calling `f` for every access point found.
+    ///
+    /// The records are fetched from the driver one at a time, so unlike
+    /// [`Self::scan_with_config_sync`] no access point is dropped because of a fixed bound.
+    /// Returning [`ControlFlow::Break`] from `f` stops the iteration and discards the remaining
+    /// records.
+    ///
+    /// Returns the total number of access points found.
+    pub fn scan_for_each_sync<F>(
+        &mut self,
+        config: ScanConfig<'_>,
+        f: F,
+    ) -> Result<usize, WifiError>
+    where
+        F: FnMut(AccessPointInfo) -> ControlFlow<()>,
+    {
+        self.ensure_started()?;
+        esp_wifi_result!(crate::wifi::wifi_start_scan(true, config))?;
+
+        self.scan_results_for_each(f)
+    }
+
+    /// Estimates how busy `channel` is, in percent, by observing it passively for about
+    /// `duration_ms` milliseconds.
+    ///
+    /// The observation is split into periods of a little more than the usual beacon interval,
+    /// each a passive scan of the channel. The result is the share of periods in which beacons
+    /// were received. The driver doesn't report the time the channel is busy with other
+    /// traffic, so this is an estimate of how crowded the channel is with access points rather
+    /// than a measurement of its airtime.
+    ///
+    /// # Warning
+    ///
+    /// Each period the radio leaves the current channel, just like during a scan, and returns to
+    /// it afterwards. A connected station or a running access point doesn't receive or send
+    /// anything in the meantime, long durations can lead to missed beacons and disconnects.
+    pub fn measure_channel_occupancy(
+        &mut self,
+        channel: u8,
+        duration_ms: u32,
+    ) -> Result<u8, WifiError> {
+        if duration_ms == 0 {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        if get_sta_state() == WifiState::StaConnected || self.is_ap_started() {
+            warn!(
+                "Measuring the occupancy of channel {} leaves the current channel",
+                channel
+            );
+        }
+
+        let periods = u32::max(duration_ms / OCCUPANCY_PERIOD.as_millis() as u32, 1);
+        let config = ScanConfig {
+            channel: Some(channel),
+            show_hidden: true,
+            scan_type: ScanTypeConfig::Passive(OCCUPANCY_PERIOD),
+            ..Default::default()
+        };
+
+        let mut busy = 0;
+        for _ in 0..periods {
+            if self.scan_for_each_sync(config, |_| ControlFlow::Break(()))? > 0 {
+                busy += 1;
+            }
+        }
+
+        Ok((busy * 100 / periods) as u8)
+    }
+
+    /// A blocking wifi network scan returning the results together with the details which
+    /// don't fit into [`AccessPointInfo`].
+    ///
+    /// At most `N` access points are returned, the second element of the tuple is the total
+    /// number found.
+    pub fn scan_extended_sync<const N: usize>(
+        &mut self,
+        config: ScanConfig<'_>,
+    ) -> Result<(heapless::Vec<ExtendedAccessPointInfo, N>, usize), WifiError> {
+        self.ensure_started()?;
+        esp_wifi_result!(crate::wifi::wifi_start_scan(true, config))?;
+
+        let mut result = heapless::Vec::new();
+        let count = self.scan_records_for_each(|record| {
+            match result.push(convert_ap_info_extended(record)) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        })?;
+
+        Ok((result, count))
+    }
+
+    /// Starts a wifi network scan without waiting for it to finish.
+    ///
+    /// Use [`Self::scan_results_available`] (or `await_scan_results` with the `async` feature)
+    /// to find out when it's done and [`Self::take_scan_results`] to collect the results.
+    pub fn scan_start_async(&mut self, config: ScanConfig<'_>) -> Result<(), WifiError> {
+        self.ensure_started()?;
+        blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(WifiEvent::ScanDone));
+
+        esp_wifi_result!(crate::wifi::wifi_start_scan(false, config))
+    }
+
+    /// Starts a wifi network scan with `config`, letting `f` change the configuration passed to
+    /// the driver (`esp_wifi_sys::include::wifi_scan_config_t`) first.
+    ///
+    /// This is an escape hatch for the fields [`ScanConfig`] doesn't cover. Pointers set by `f`
+    /// must stay valid until the scan started. With `block` this returns once the scan finished,
+    /// otherwise it behaves like [`Self::scan_start_async`]. Either way the results are collected
+    /// with [`Self::take_scan_results`] or [`Self::scan_results_raw`].
+    pub fn scan_with_raw_config(
+        &mut self,
+        config: ScanConfig<'_>,
+        f: impl FnOnce(&mut wifi_scan_config_t),
+        block: bool,
+    ) -> Result<(), WifiError> {
+        self.ensure_started()?;
+        blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(WifiEvent::ScanDone));
+
+        esp_wifi_result!(wifi_start_scan_with_raw_config(block, config, f))
+    }
+
+    /// Whether the scan started with [`Self::scan_start_async`] finished.
+    pub fn scan_results_available(&self) -> bool {
+        blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref(cs).contains(WifiEvent::ScanDone))
+    }
+
+    /// Collects the results of a scan started with [`Self::scan_start_async`].
+    ///
+    /// At most `N` access points are returned, the second element of the tuple is the total
+    /// number found. The results are removed from the driver, a second call returns none.
+    pub fn take_scan_results<const N: usize>(
+        &mut self,
+    ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+        blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(WifiEvent::ScanDone));
+
+        let mut result = heapless::Vec::new();
+        let count = self.scan_results_for_each(|info| match result.push(info) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        })?;
+
+        Ok((result, count))
+    }
+
+    /// Collects the results of a scan started with [`Self::scan_start_async`] together with the
+    /// vendor specific IEs of each access point.
+    ///
+    /// The driver doesn't keep the raw IEs of scanned access points, only the vendor specific
+    /// ones it reports while scanning are available. These are enough e.g. to detect WPS or WMM
+    /// support or proprietary extensions. At most `N` access points are returned.
+    pub fn scan_results_raw<const N: usize>(
+        &mut self,
+    ) -> Result<heapless::Vec<RawApRecord, N>, WifiError> {
+        blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(WifiEvent::ScanDone));
+        vendor_ie::stop_capture();
+
+        let mut result = heapless::Vec::new();
+        self.scan_records_for_each(|record| {
+            let mut ie_data = heapless::Vec::new();
+            vendor_ie::copy_ies(&record.bssid, &mut ie_data);
+            let mut custom_ie_data = heapless::Vec::new();
+            vendor_ie::parse_ies(&record.bssid, &mut custom_ie_data);
+
+            let raw = RawApRecord {
+                info: convert_ap_info(record),
+                ie_data,
+                custom_ie_data,
+            };
+            match result.push(raw) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        })?;
+
+        Ok(result)
+    }
+
+    /// Registers a parser which decodes the IEs with the given element ID of the access points
+    /// found by a scan. Its results are returned in [`RawApRecord::custom_ie_data`] by
+    /// [`Self::scan_results_raw`].
+    ///
+    /// The parser is called with the body of the IE, without element ID and length. A parser
+    /// registered before for the same element ID is replaced. At most
+    /// [`MAX_IE_PARSERS`] parsers can be registered.
+    ///
+    /// Only vendor specific IEs (element ID 221) are currently kept by the driver, parsers for
+    /// other element IDs are never called.
+    pub fn register_ie_parser(
+        &mut self,
+        element_id: u8,
+        parser: IeParser,
+    ) -> Result<(), WifiError> {
+        if !vendor_ie::register_parser(element_id, parser) {
+            return Err(WifiError::InternalError(InternalWifiError::EspErrNoMem));
+        }
+
+        Ok(())
+    }
+
+    /// Removes the parser registered for `element_id`, if any.
+    pub fn unregister_ie_parser(&mut self, element_id: u8) {
+        vendor_ie::unregister_parser(element_id);
+    }
+
+    /// Scanning fails with an opaque driver error when WiFi isn't started, catch that early.
+    fn ensure_started(&self) -> Result<(), WifiError> {
+        if !embedded_svc::wifi::Wifi::is_started(self)? {
+            warn!("WiFi must be started before scanning, call `WifiController::start` first");
+            return Err(WifiError::NotStarted);
+        }
+
+        Ok(())
+    }
+
+    fn scan_results_for_each<F>(&mut self, mut f: F) -> Result<usize, WifiError>
+    where
+        F: FnMut(AccessPointInfo) -> ControlFlow<()>,
+    {
+        self.scan_records_for_each(|record| f(convert_ap_info(record)))
+    }
+
+    fn scan_records_for_each<F>(&mut self, mut f: F) -> Result<usize, WifiError>
+    where
+        F: FnMut(&include::wifi_ap_record_t) -> ControlFlow<()>,
+    {
+        let count = self.scan_result_count()?;
+
+        // Frees the records we didn't fetch, both on error and when `f` stops early
+        let _guard = FreeApListOnDrop;
+
+        for _ in 0..count {
+            let mut record = MaybeUninit::<include::wifi_ap_record_t>::uninit();
+
+            // Each call removes the returned record from the driver's list and frees it
+            unsafe { esp_wifi_result!(include::esp_wifi_scan_get_ap_record(record.as_mut_ptr()))? };
+
+            let record = unsafe { record.assume_init_ref() };
+            if f(record).is_break() {
+                break;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Stops the WiFi driver before entering deep sleep and returns the details needed to
+    /// reconnect quickly afterwards.
+    ///
+    /// The station must be connected. On chips with RTC fast memory the state is also kept there,
+    /// see [`DeepSleepWifiState::from_rtc_memory`].
+    pub fn deep_sleep_prepare(&mut self) -> Result<DeepSleepWifiState, WifiError> {
+        if get_sta_state() != WifiState::StaConnected {
+            return Err(WifiError::Disconnected);
+        }
+
+        let record = sta_ap_record()?;
+        let state = DeepSleepWifiState {
+            magic: DEEP_SLEEP_WIFI_STATE_MAGIC,
+            bssid: record.bssid,
+            channel: record.primary,
+        };
+
+        esp_wifi_result!(unsafe { esp_wifi_stop() })?;
+
+        #[cfg(not(esp32c2))]
+        unsafe {
+            DEEP_SLEEP_WIFI_STATE = state;
+        }
+
+        Ok(state)
+    }
+
+    /// Pins the station configuration to the access point saved by
+    /// [`Self::deep_sleep_prepare`], so the next connect skips the scan for it.
+    ///
+    /// Call this before `start` and `connect`.
+    pub fn deep_sleep_restore(&mut self, state: &DeepSleepWifiState) -> Result<(), WifiError> {
+        self.ensure_sta_mode()?;
+
+        let mut config = self.config.clone();
+        if let Configuration::Client(client) | Configuration::Mixed(client, _) = &mut config {
+            client.bssid = Some(state.bssid);
+            client.channel = Some(state.channel);
+        }
+
+        self.set_configuration(&config)
+    }
+
+    /// The channels which may be used in the configured country.
+    ///
+    /// Scans and the access point are restricted to these channels.
+    pub fn channel_plan(&self) -> ChannelPlan {
+        active_channel_plan()
+    }
+
+    /// Sets the country, replacing the one configured with
+    /// [`crate::EspWifiConfig::country_code`] until WiFi is deinitialized.
+    ///
+    /// While the station is connected, `country` is compared to the country the access point
+    /// advertises in its beacons, if any. A different country code most likely means the
+    /// device would operate outside the local regulatory domain: by default this is logged,
+    /// with [`CountryCheck::Strict`] set via [`Self::set_country_check`] the country is
+    /// rejected with [`WifiError::CountryMismatch`].
+    ///
+    /// The country can be changed while connected, as long as it allows the channels in use:
+    /// the channel of the connected access point and the channel configured for the own access
+    /// point. Otherwise [`WifiError::ChannelNotInCountry`] is returned and nothing changes;
+    /// disconnect or reconfigure the access point first.
+    ///
+    /// `country` must have an upper case country code and channels within 1 to 14, otherwise
+    /// [`InternalWifiError::EspErrInvalidArg`] is returned.
+    pub fn set_country(&mut self, country: CountryInfo) -> Result<(), WifiError> {
+        if !country.is_valid() {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        let plan = country.channel_plan();
+        if let Configuration::AccessPoint(ap) | Configuration::Mixed(_, ap) = &self.config {
+            if !plan.contains(ap.channel) {
+                warn!(
+                    "The access point's channel {} is not allowed in {}",
+                    ap.channel,
+                    country.code_str()
+                );
+                return Err(WifiError::ChannelNotInCountry {
+                    channel: ap.channel,
+                });
+            }
+        }
+
+        if get_sta_state() == WifiState::StaConnected {
+            let record = sta_ap_record()?;
+            if !plan.contains(record.primary) {
+                warn!(
+                    "The station's channel {} is not allowed in {}",
+                    record.primary,
+                    country.code_str()
+                );
+                return Err(WifiError::ChannelNotInCountry {
+                    channel: record.primary,
+                });
+            }
+
+            let advertised = CountryInfo::from_raw(&record.country);
+            if let Some(advertised) = advertised.filter(|ap| ap.code != country.code) {
+                warn!(
+                    "Country {} differs from {} advertised by the access point",
+                    country.code_str(),
+                    advertised.code_str()
+                );
+
+                let check = blocking_checks::with(|cs| *COUNTRY_CHECK.borrow_ref(cs));
+                if check == CountryCheck::Strict {
+                    return Err(WifiError::CountryMismatch {
+                        advertised: advertised.code,
+                    });
+                }
+            }
+        }
+
+        apply_country(&country)?;
+        set_active_channel_plan(plan);
+        blocking_checks::with(|cs| *COUNTRY.borrow_ref_mut(cs) = Some(country));
+
+        Ok(())
+    }
+
+    /// Returns the country the driver uses.
+    ///
+    /// With [`CountryPolicy::Auto`] this is the country of the connected access point, if it
+    /// advertises one.
+    pub fn get_country(&self) -> Result<CountryInfo, WifiError> {
+        let mut country = MaybeUninit::<wifi_country_t>::uninit();
+        esp_wifi_result!(unsafe { include::esp_wifi_get_country(country.as_mut_ptr()) })?;
+
+        CountryInfo::from_raw(unsafe { country.assume_init_ref() }).ok_or(WifiError::InternalError(
+            InternalWifiError::EspErrInvalidArg,
+        ))
+    }
+
+    /// Sets how [`Self::set_country`] treats a country which differs from the one the connected
+    /// access point advertises, [`CountryCheck::Warn`] by default.
+    pub fn set_country_check(&mut self, check: CountryCheck) {
+        blocking_checks::with(|cs| *COUNTRY_CHECK.borrow_ref_mut(cs) = check);
+    }
+
+    /// Returns the details of the access point the station is connected to.
+    ///
+    /// The bandwidth is the one of the link, i.e. HT40 only if both the access point and the
+    /// station use it. Returns [`WifiError::Disconnected`] if the station isn't connected.
+    pub fn sta_get_ap_info(&self) -> Result<ExtendedAccessPointInfo, WifiError> {
+        if get_sta_state() != WifiState::StaConnected {
+            return Err(WifiError::Disconnected);
+        }
+
+        let mut info = convert_ap_info_extended(&sta_ap_record()?);
+
+        let mut sta_bandwidth = include::wifi_bandwidth_t_WIFI_BW_HT20;
+        esp_wifi_result!(unsafe {
+            include::esp_wifi_get_bandwidth(wifi_interface_t_WIFI_IF_STA, &mut sta_bandwidth)
+        })?;
+        if sta_bandwidth == include::wifi_bandwidth_t_WIFI_BW_HT20 {
+            info.bandwidth = Bandwidth::Ht20;
+        }
+
+        Ok(info)
+    }
+
+    /// Returns the access point the station was last connected to.
+    ///
+    /// It is kept after disconnecting, so it can be used by [`Self::fast_reconnect_sync`].
+    pub fn last_access_point(&self) -> Option<LastAccessPoint> {
+        blocking_checks::with(|cs| *LAST_ACCESS_POINT.borrow_ref(cs))
+    }
+
+    /// Sets the access point [`Self::fast_reconnect_sync`] connects to, e.g. one saved before a
+    /// reset. `None` makes the next fast reconnect a normal connect.
+    pub fn set_last_access_point(&mut self, ap: Option<LastAccessPoint>) {
+        blocking_checks::with(|cs| *LAST_ACCESS_POINT.borrow_ref_mut(cs) = ap);
+    }
+
+    /// Returns what [`crate::initialize_warm`] needs to skip the RF calibration and the scan for
+    /// the access point after deep sleep.
+    ///
+    /// Call this before going to sleep, ideally while connected. Without a connection the data
+    /// only allows skipping the calibration.
+    pub fn export_warm_start_data(&self) -> Result<WarmStartData, WifiError> {
+        let Some(calibration) = crate::common_adapter::phy_calibration_data() else {
+            return Err(WifiError::NotInitialized);
+        };
+
+        Ok(WarmStartData {
+            magic: WARM_START_DATA_MAGIC,
+            calibration,
+            access_point: self.last_access_point(),
+        })
+    }
+
+    /// Connects to the access point the station was last connected to, without scanning for it.
+    ///
+    /// The station configuration is pinned to the BSSID and channel of that access point and
+    /// this blocks until the connection attempt finished. If it failed, or there is no access
+    /// point to reconnect to, the previous configuration is restored and a normal, scan based
+    /// connect is started instead. Like `connect` this doesn't wait for the fallback to finish.
+    ///
+    /// If the driver doesn't report the outcome of the attempt within 10 seconds, the attempt
+    /// is aborted, the previous configuration is restored and [`WifiError::Timeout`] is returned.
+    pub fn fast_reconnect_sync(&mut self) -> Result<(), WifiError> {
+        let Some(fast_config) = self.fast_reconnect_config()? else {
+            return Wifi::connect(self);
+        };
+
+        let config = self.config.clone();
+        self.set_configuration(&fast_config)?;
+
+        if Wifi::connect(self).is_ok() {
+            let deadline = crate::current_millis() + FAST_RECONNECT_TIMEOUT.as_millis() as u64;
+            while STA_CONNECTING.load(Ordering::Relaxed) {
+                if crate::current_millis() >= deadline {
+                    warn!("Fast reconnect timed out");
+                    self.abort_connect()?;
+                    self.set_configuration(&config)?;
+                    return Err(WifiError::Timeout);
+                }
+                core::hint::spin_loop();
+            }
+
+            if get_sta_state() == WifiState::StaConnected {
+                return Ok(());
+            }
+        }
+
+        debug!("Fast reconnect failed, falling back to a scan");
+        self.set_configuration(&config)?;
+        Wifi::connect(self)
+    }
+
+    /// Cancels a connection attempt of the station which is still in progress, e.g. to try a
+    /// different access point instead.
+    ///
+    /// Does nothing if the station isn't connecting, an established connection is kept, see
+    /// [`Wifi::disconnect`] for that. Pending [`WifiEvent::StaConnected`] and
+    /// [`WifiEvent::StaDisconnected`] events are cleared.
+    ///
+    /// The async `connect` borrows the controller, so it has to be dropped before this can be
+    /// called, e.g. after a timeout. An async `connect` which is still awaited when the attempt
+    /// is aborted some other way resolves with [`WifiError::Disconnected`], as the driver
+    /// reports the aborted attempt with [`WifiEvent::StaDisconnected`].
+    pub fn abort_connect(&mut self) -> Result<(), WifiError> {
+        self.ensure_sta_mode()?;
+
+        if !STA_CONNECTING.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        debug!("Aborting the connection attempt");
+        STA_CONNECTING.store(false, Ordering::Relaxed);
+        match esp_wifi_result!(unsafe { esp_wifi_disconnect() }) {
+            Err(WifiError::InternalError(
+                InternalWifiError::EspErrWifiNotStarted | InternalWifiError::EspErrWifiNotConnect,
+            ))
+            | Ok(()) => {}
+            Err(err) => return Err(err),
+        }
+
+        blocking_checks::with(|cs| {
+            WIFI_EVENTS
+                .borrow_ref_mut(cs)
+                .remove_all(WifiEvent::StaConnected | WifiEvent::StaDisconnected)
+        });
+
+        Ok(())
+    }
+
+    /// The station configuration pinned to the last access point, `None` if there is none.
+    fn fast_reconnect_config(&self) -> Result<Option<Configuration>, WifiError> {
+        self.ensure_sta_mode()?;
+
+        let Some(ap) = self.last_access_point() else {
+            return Ok(None);
+        };
+
+        let mut config = self.config.clone();
+        if let Configuration::Client(client) | Configuration::Mixed(client, _) = &mut config {
+            client.bssid = Some(ap.bssid);
+            client.channel = Some(ap.channel);
+        }
+
+        Ok(Some(config))
+    }
+
+    /// Pins the PHY rate used to transmit on `interface`.
+    ///
+    /// The rate has to be allowed by the protocols enabled on the interface, e.g. an MCS rate
+    /// requires 802.11n and the long range rates require LR. This is mostly useful for range
+    /// tests, deterministic point-to-point links and RF characterization.
+    pub fn set_fixed_rate(
+        &mut self,
+        interface: WifiInterface,
+        rate: WifiPhyRate,
+    ) -> Result<(), WifiError> {
+        let mut protocols = 0u8;
+        esp_wifi_result!(unsafe {
+            include::esp_wifi_get_protocol(interface.to_raw(), &mut protocols)
+        })?;
+
+        if protocols & rate.required_protocol() == 0 {
+            warn!(
+                "{:?} is not allowed by the protocols enabled on {:?}",
+                rate, interface
+            );
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        esp_wifi_result!(unsafe {
+            include::esp_wifi_config_80211_tx_rate(interface.to_raw(), rate as u32)
+        })
+    }
+
+    /// Returns the maximum transmit power in dBm.
+    ///
+    /// The driver has a single limit shared by the station and the access point, so in AP-STA
+    /// mode this is the effective limit of both interfaces. It's the lower of the configured
+    /// limit and the one of the current country. Fails unless WiFi is started.
+    pub fn get_max_tx_power(&self) -> Result<f32, WifiError> {
+        let mut power = 0i8;
+        esp_wifi_result!(unsafe { include::esp_wifi_get_max_tx_power(&mut power) })?;
+
+        // The driver reports the power in units of 0.25 dBm
+        Ok(power as f32 / 4.0)
+    }
+
+    /// Sets the maximum transmit power in dBm, rounded to 0.25 dBm, e.g. to save power.
+    ///
+    /// The chips support 2 to 20 dBm, other values are rejected with
+    /// [`InternalWifiError::EspErrInvalidArg`]. The power of the country still applies, the
+    /// lower limit wins. If WiFi isn't started yet, the power is applied when it starts. It's
+    /// kept until WiFi is deinitialized.
+    pub fn set_tx_power_dbm(&mut self, dbm: f32) -> Result<(), WifiError> {
+        if !(2.0..=20.0).contains(&dbm) {
+            warn!("The transmit power must be within 2 to 20 dBm");
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        // The driver takes the power in units of 0.25 dBm
+        let power = (dbm * 4.0 + 0.5) as i8;
+        if embedded_svc::wifi::Wifi::is_started(self)? {
+            esp_wifi_result!(unsafe { include::esp_wifi_set_max_tx_power(power) })?;
+        }
+        blocking_checks::with(|cs| *TX_POWER.borrow_ref_mut(cs) = Some(power));
+
+        Ok(())
+    }
+
+    /// Returns the maximum transmit power in dBm.
+    ///
+    /// While WiFi is started this is [`Self::get_max_tx_power`]. Before, it's the power set with
+    /// [`Self::set_tx_power_dbm`], or [`WifiError::NotStarted`] if none was set.
+    pub fn get_tx_power_dbm(&self) -> Result<f32, WifiError> {
+        if embedded_svc::wifi::Wifi::is_started(self)? {
+            return self.get_max_tx_power();
+        }
+
+        blocking_checks::with(|cs| *TX_POWER.borrow_ref(cs))
+            .map(|power| power as f32 / 4.0)
+            .ok_or(WifiError::NotStarted)
+    }
+
+    /// Returns the state of the station and the access point interfaces.
+    pub fn status(&self) -> ConnectionStatus {
+        let disconnected = || StaStatus::Disconnected {
+            reason: STA_DISCONNECT_REASON.load(Ordering::Relaxed),
+        };
+
+        let sta = match get_sta_state() {
+            _ if STA_CONNECTING.load(Ordering::Relaxed) => StaStatus::Connecting,
+            WifiState::StaConnected => match sta_ap_record() {
+                Ok(record) => StaStatus::Connected {
+                    bssid: record.bssid,
+                    channel: record.primary,
+                    rssi: record.rssi,
+                },
+                // The connection dropped in the meantime
+                Err(_) => disconnected(),
+            },
+            WifiState::StaDisconnected => disconnected(),
+            WifiState::StaStarted => StaStatus::Idle,
+            _ => StaStatus::Stopped,
+        };
+
+        let ap = match get_ap_state() {
+            WifiState::ApStarted => {
+                let mut list = MaybeUninit::<include::wifi_sta_list_t>::uninit();
+                let stations = match esp_wifi_result!(unsafe {
+                    include::esp_wifi_ap_get_sta_list(list.as_mut_ptr())
+                }) {
+                    Ok(()) => unsafe { list.assume_init_ref() }.num as usize,
+                    Err(_) => 0,
+                };
+
+                ApStatus::Started { stations }
+            }
+            _ => ApStatus::Stopped,
+        };
+
+        ConnectionStatus { sta, ap }
+    }
+
+    /// Configures periodic OBSS (overlapping BSS) scans of the station.
+    ///
+    /// The WiFi blobs shipped with this crate don't expose the OBSS scan parameters, so this
+    /// always fails with [`InternalWifiError::EspErrNotSupported`] for now. The 802.11 minimum
+    /// scan interval of 10 seconds is still validated so callers get consistent errors once
+    /// support lands.
+    pub fn set_obss_scan(&mut self, enable: bool, interval_sec: u16) -> Result<(), WifiError> {
+        if enable && interval_sec < 10 {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        Err(WifiError::InternalError(
+            InternalWifiError::EspErrNotSupported,
+        ))
+    }
+
+    /// Returns the traffic counters of the station since it last connected.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        blocking_checks::with(|cs| *STA_CONNECTION_STATS.borrow_ref(cs))
+    }
+
+    /// Returns the signal strength of the access point the station is connected to, in dBm.
+    ///
+    /// Returns [`WifiError::Disconnected`] if the station isn't connected.
+    pub fn rssi(&self) -> Result<i32, WifiError> {
+        if get_sta_state() != WifiState::StaConnected {
+            return Err(WifiError::Disconnected);
+        }
+
+        let mut rssi = 0;
+        esp_wifi_result!(unsafe { include::esp_wifi_sta_get_rssi(&mut rssi) })?;
+
+        Ok(rssi)
+    }
+
+    /// Returns how long the current association of the station has lasted, `None` if it isn't
+    /// connected.
+    ///
+    /// Counts from the last [`WifiEvent::StaConnected`], so a reassociation, e.g. when roaming,
+    /// starts from zero again.
+    pub fn connected_duration(&self) -> Option<Duration> {
+        if get_sta_state() != WifiState::StaConnected {
+            return None;
+        }
+
+        let connected_at = self.connection_stats().connect_time_us;
+        let now = crate::timer::ticks_to_micros(crate::timer::get_systimer_count());
+        Some(Duration::from_micros(now.saturating_sub(connected_at)))
+    }
+
+    /// Sets how long the station's link is reported down to embassy-net after connecting,
+    /// zero by default.
+    ///
+    /// This is a workaround for access points which drop the first frames sent right after
+    /// the association, before their tables are updated. A few milliseconds are usually
+    /// enough; the delay only postpones the link going up, frames are still sent as soon as
+    /// embassy-net sees the link. It takes effect with the next connection.
+    #[cfg(feature = "embassy-net")]
+    pub fn set_link_settle_delay(&mut self, delay: Duration) {
+        blocking_checks::with(|cs| *embassy::STA_LINK_SETTLE_DELAY.borrow_ref_mut(cs) = delay);
+    }
+
+    /// Returns the stations connected to the access point.
+    pub fn ap_sta_list(&self) -> Result<heapless::Vec<ApStationInfo, MAX_AP_STATIONS>, WifiError> {
+        self.ensure_ap_mode()?;
+
+        ap_station_list()
+    }
+
+    /// Returns the traffic counters of the access point, summed over all stations, since it was
+    /// started.
+    pub fn ap_traffic_stats(&self) -> Result<ApTrafficStats, WifiError> {
+        self.ensure_ap_mode()?;
+
+        Ok(blocking_checks::with(|cs| AP_TRAFFIC.borrow_ref(cs).total))
+    }
+
+    /// Returns the traffic counters of the station with the given MAC address since it was
+    /// first seen.
+    ///
+    /// Counters are kept for up to 16 stations at a time and are dropped when a station
+    /// disconnects, `None` is returned for any other MAC address.
+    pub fn ap_station_stats(&self, mac: [u8; 6]) -> Result<Option<StationStats>, WifiError> {
+        self.ensure_ap_mode()?;
+
+        Ok(blocking_checks::with(|cs| {
+            AP_TRAFFIC.borrow_ref(cs).stations.get(&mac).copied()
+        }))
+    }
+
+    /// Changes the Protected Management Frame policy of `interface` without touching the rest
+    /// of its configuration.
+    ///
+    /// The policy is kept when the configuration is changed later on. By default PMF is
+    /// `capable` but not `required`.
+    pub fn set_pmf(
+        &mut self,
+        interface: WifiInterface,
+        capable: bool,
+        required: bool,
+    ) -> Result<(), WifiError> {
+        match interface {
+            WifiInterface::Sta => self.ensure_sta_mode()?,
+            WifiInterface::Ap => self.ensure_ap_mode()?,
+        }
+
+        if required && !capable {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        let pmf_cfg = wifi_pmf_config_t { capable, required };
+
+        unsafe {
+            let mut cfg = MaybeUninit::<wifi_config_t>::uninit();
+            esp_wifi_result!(include::esp_wifi_get_config(
+                interface.to_raw(),
+                cfg.as_mut_ptr()
+            ))?;
+
+            let mut cfg = cfg.assume_init();
+            match interface {
+                WifiInterface::Sta => cfg.sta.pmf_cfg = pmf_cfg,
+                WifiInterface::Ap => cfg.ap.pmf_cfg = pmf_cfg,
+            }
+
+            esp_wifi_result!(esp_wifi_set_config(interface.to_raw(), &mut cfg))?;
+        }
+
+        blocking_checks::with(|cs| *interface.pmf_config().borrow_ref_mut(cs) = pmf_cfg);
+
+        Ok(())
+    }
+
+    /// Sets the WPA3 SAE-PK policy of the station without touching the rest of its
+    /// configuration.
+    ///
+    /// The policy is kept when the configuration is changed later on and takes effect with the
+    /// next connection. By default SAE-PK is used when the access point supports it.
+    pub fn set_sae_pk_mode(&mut self, mode: SaePkMode) -> Result<(), WifiError> {
+        self.ensure_sta_mode()?;
+
+        unsafe {
+            let mut cfg = MaybeUninit::<wifi_config_t>::uninit();
+            esp_wifi_result!(include::esp_wifi_get_config(
+                wifi_interface_t_WIFI_IF_STA,
+                cfg.as_mut_ptr()
+            ))?;
+
+            let mut cfg = cfg.assume_init();
+            cfg.sta.sae_pk_mode = mode.to_raw();
+
+            esp_wifi_result!(esp_wifi_set_config(wifi_interface_t_WIFI_IF_STA, &mut cfg))?;
+        }
+
+        blocking_checks::with(|cs| *STA_SAE_PK_MODE.borrow_ref_mut(cs) = mode);
+
+        Ok(())
+    }
+
+    /// Sets the power save mode of the station.
+    ///
+    /// The mode is kept when WiFi is restarted. When WiFi and BLE are used together (`coex`), the
+    /// radio is shared in the modem sleep windows, so [`PowerSaveMode::None`] is rejected with
+    /// [`WifiError::PowerSaveRequiredByCoex`].
+    pub fn set_ps_type(&mut self, mode: PowerSaveMode) -> Result<(), WifiError> {
+        if cfg!(coex) && mode == PowerSaveMode::None {
+            warn!("Power save can't be disabled when using coex");
+            return Err(WifiError::PowerSaveRequiredByCoex);
+        }
+
+        esp_wifi_result!(unsafe { esp_wifi_set_ps(mode.to_raw()) })?;
+        crate::update_esp_wifi_config(|config| config.power_save_mode = mode);
+
+        Ok(())
+    }
+
+    /// Changes the power save mode of the running station, e.g. to disable power save for the
+    /// duration of an OTA download.
+    ///
+    /// Like [`Self::set_ps_type`], but returns [`WifiError::NotStarted`] unless WiFi is
+    /// started, so the mode takes effect right away. With `coex`, [`PowerSaveMode::None`] is
+    /// rejected with [`WifiError::PowerSaveRequiredByCoex`].
+    pub fn set_power_saving(&mut self, mode: PowerSaveMode) -> Result<(), WifiError> {
+        if !embedded_svc::wifi::Wifi::is_started(self)? {
+            return Err(WifiError::NotStarted);
+        }
+
+        self.set_ps_type(mode)
+    }
+
+    /// Returns the power save mode of the running station.
+    ///
+    /// Returns [`WifiError::NotStarted`] unless WiFi is started.
+    pub fn get_power_saving(&self) -> Result<PowerSaveMode, WifiError> {
+        if !embedded_svc::wifi::Wifi::is_started(self)? {
+            return Err(WifiError::NotStarted);
+        }
+
+        let mut mode = include::wifi_ps_type_t_WIFI_PS_NONE;
+        esp_wifi_result!(unsafe { include::esp_wifi_get_ps(&mut mode) })?;
+
+        Ok(PowerSaveMode::from_raw(mode))
+    }
+
+    /// Calls `cb` every `threshold` received packets an interface drops because its RX queue is
+    /// full, i.e. because they aren't read fast enough. A `threshold` of 0 disables the callback.
+    ///
+    /// The callback runs in the driver's receive callback, it must return quickly and must not
+    /// block.
+    pub fn set_drop_threshold_callback(&mut self, threshold: usize, cb: fn(DroppedPacketReport)) {
+        blocking_checks::with(|cs| *DROP_CALLBACK.borrow_ref_mut(cs) = Some(cb));
+        DROP_THRESHOLD.store(threshold, Ordering::Relaxed);
+    }
+
+    /// Switches the station to [`PowerSaveMode::Maximum`], waking up every `beacons` beacon
+    /// intervals to receive a beacon.
+    ///
+    /// The interval is counted in beacons, not DTIM periods: the driver doesn't report the DTIM
+    /// period of the access point, so multicast frames buffered by it are only received if the
+    /// interval lines up with its DTIM beacons. The interval replaces
+    /// [`crate::EspWifiConfig::listen_interval`] until WiFi is initialized again.
+    ///
+    /// Like [`Self::set_power_saving`] this returns [`WifiError::NotStarted`] unless WiFi is
+    /// started. The listen interval is announced when associating, so a connected station
+    /// disconnects and reassociates to apply it. The reassociation is started like
+    /// [`Wifi::connect`], wait for [`WifiEvent::StaConnected`] to know it finished.
+    pub fn set_ps_listen_interval(&mut self, beacons: u8) -> Result<(), WifiError> {
+        if !embedded_svc::wifi::Wifi::is_started(self)? {
+            return Err(WifiError::NotStarted);
+        }
+
+        if beacons == 0 {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        crate::update_esp_wifi_config(|config| config.listen_interval = beacons as u16);
+        if let Configuration::Client(config) | Configuration::Mixed(config, _) = &self.config {
+            apply_sta_config(config)?;
+        }
 
-        let mode = WifiMode::try_from(&config)?;
-        esp_wifi_result!(unsafe { esp_wifi_set_mode(mode.into()) })?;
-        debug!("Wifi mode {:?} set", mode);
+        self.set_ps_type(PowerSaveMode::Maximum)?;
 
-        this.set_configuration(&config)?;
-        Ok(this)
+        if get_sta_state() == WifiState::StaConnected {
+            debug!("Reassociating with listen interval {}", beacons);
+            self.reassociate_sync()?;
+        }
+
+        Ok(())
     }
 
-    /// Set the wifi mode.
-    ///
-    /// This will set the wifi protocol to the desired protocol, the default for this is:
-    /// `WIFI_PROTOCOL_11B|WIFI_PROTOCOL_11G|WIFI_PROTOCOL_11N`
-    ///
-    /// # Arguments:
+    /// Disconnects the station and starts connecting again once the driver reported the
+    /// disconnect, so the late [`WifiEvent::StaDisconnected`] can't be taken for the outcome of
+    /// the new attempt.
+    fn reassociate_sync(&mut self) -> Result<(), WifiError> {
+        blocking_checks::with(|cs| {
+            WIFI_EVENTS
+                .borrow_ref_mut(cs)
+                .remove_all(WifiEvent::StaConnected | WifiEvent::StaDisconnected)
+        });
+        Wifi::disconnect(self)?;
+
+        let deadline = crate::current_millis() + DISCONNECT_TIMEOUT.as_millis() as u64;
+        while !blocking_checks::with(|cs| {
+            WIFI_EVENTS
+                .borrow_ref_mut(cs)
+                .remove(WifiEvent::StaDisconnected)
+        }) {
+            if crate::current_millis() >= deadline {
+                return Err(WifiError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+
+        Wifi::connect(self)
+    }
+
+    /// Scans for the given networks and connects to the one with the strongest signal.
     ///
-    /// * `protocol` - The desired protocol
+    /// The client configuration is replaced by the chosen network. Like [`Wifi::connect`] this
+    /// only starts connecting, wait for [`WifiEvent::StaConnected`] to know it succeeded.
     ///
-    /// # Example:
+    /// Returns [`WifiError::ApNotFound`] if none of the networks was seen.
+    pub fn scan_and_connect(&mut self, networks: &[NetworkCredential]) -> Result<(), WifiError> {
+        self.ensure_sta_mode()?;
+
+        let mut best: Option<(&NetworkCredential, i8)> = None;
+        self.scan_for_each_sync(Default::default(), |ap| {
+            let known = networks.iter().find(|network| network.ssid == ap.ssid);
+            if let Some(network) = known {
+                if best.map_or(true, |(_, rssi)| ap.signal_strength > rssi) {
+                    best = Some((network, ap.signal_strength));
+                }
+            }
+            ControlFlow::Continue(())
+        })?;
+
+        let Some((network, _)) = best else {
+            return Err(WifiError::ApNotFound);
+        };
+
+        self.set_configuration(&Configuration::Client(ClientConfiguration {
+            ssid: network.ssid.clone(),
+            password: network.password.clone(),
+            auth_method: network.auth,
+            ..Default::default()
+        }))?;
+
+        Wifi::connect(self)
+    }
+
+    /// Sets the HT capabilities the station advertises when associating.
     ///
-    /// ```
-    /// use embedded_svc::wifi::Protocol;
-    /// use esp_wifi::wifi::WifiController;
-    /// let mut wifi = WifiController::new();
-    /// wifi.set_mode(Protocol::P802D11BGNLR);
-    /// ```
-    pub fn set_mode(&mut self, protocol: Protocol) -> Result<(), WifiError> {
-        let mut mode = wifi_mode_t_WIFI_MODE_NULL;
-        esp_wifi_result!(unsafe { esp_wifi_get_mode(&mut mode) })?;
-        esp_wifi_result!(unsafe { esp_wifi_set_protocol(mode, protocol as u8) })?;
-        Ok(())
+    /// The driver only allows changing the channel width, the other fields have to match
+    /// [`HtCapabilities::default`], otherwise `EspErrNotSupported` is returned. Takes effect on the
+    /// next connection.
+    pub fn set_ht_capabilities(&mut self, config: HtCapabilities) -> Result<(), WifiError> {
+        let supported = HtCapabilities {
+            ht40: config.ht40,
+            ..Default::default()
+        };
+        if config != supported {
+            warn!("Only the channel width of the HT capabilities can be changed");
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrNotSupported,
+            ));
+        }
+
+        let bandwidth = if config.ht40 {
+            include::wifi_bandwidth_t_WIFI_BW_HT40
+        } else {
+            include::wifi_bandwidth_t_WIFI_BW_HT20
+        };
+
+        esp_wifi_result!(unsafe {
+            include::esp_wifi_set_bandwidth(wifi_interface_t_WIFI_IF_STA, bandwidth)
+        })
     }
 
-    pub fn is_sta_enabled(&self) -> Result<bool, WifiError> {
-        WifiMode::try_from(&self.config).map(|m| m.is_sta())
+    /// Makes the driver log its RX/TX statistics, including the rates used for transmitting.
+    ///
+    /// The driver doesn't expose its per-rate counters, they can only be read from this dump. The
+    /// output needs the `wifi-logs` feature.
+    pub fn dump_rxtx_statistics(&self) -> Result<(), WifiError> {
+        esp_wifi_result!(unsafe { include::esp_wifi_statis_dump(include::WIFI_STATIS_RXTX) })
     }
 
-    pub fn is_ap_enabled(&self) -> Result<bool, WifiError> {
-        WifiMode::try_from(&self.config).map(|m| m.is_ap())
+    /// Selects the antennas used for receiving and transmitting.
+    ///
+    /// This needs an external antenna switch set up with [`Self::set_antenna_gpio`].
+    pub fn set_antenna(&mut self, config: AntennaConfig) -> Result<(), WifiError> {
+        if config.tx_mode == AntennaMode::Auto && config.rx_mode != AntennaMode::Auto {
+            warn!("The TX antenna can only be selected automatically if the RX antenna is too");
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        // Only four GPIOs can be configured for the antenna switch
+        if config.enabled_ant0 > 0xf || config.enabled_ant1 > 0xf {
+            return Err(WifiError::InternalError(
+                InternalWifiError::EspErrInvalidArg,
+            ));
+        }
+
+        let raw = include::wifi_ant_config_t {
+            rx_ant_mode: config.rx_mode.to_raw(),
+            rx_ant_default: config.rx_default.to_raw(),
+            tx_ant_mode: config.tx_mode.to_raw(),
+            _bitfield_align_1: [],
+            _bitfield_1: include::wifi_ant_config_t::new_bitfield_1(
+                config.enabled_ant0,
+                config.enabled_ant1,
+            ),
+            __bindgen_padding_0: [0; 3],
+        };
+
+        esp_wifi_result!(unsafe { include::esp_wifi_set_ant(&raw) })
     }
 
-    /// A blocking wifi network scan with caller-provided scanning options.
-    pub fn scan_with_config_sync<const N: usize>(
-        &mut self,
-        config: ScanConfig<'_>,
-    ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
-        esp_wifi_result!(crate::wifi::wifi_start_scan(true, config))?;
+    /// Configures the GPIOs connected to an external antenna switch.
+    ///
+    /// Up to four GPIOs can be used, `None` leaves that index unused. The values driven onto them
+    /// are set with [`AntennaConfig::enabled_ant0`] and [`AntennaConfig::enabled_ant1`].
+    /// Returns [`WifiError::InvalidGpio`] if a GPIO doesn't exist or isn't output capable on this
+    /// chip.
+    pub fn set_antenna_gpio(&mut self, gpios: [Option<u8>; 4]) -> Result<(), WifiError> {
+        let mut config = include::wifi_ant_gpio_config_t {
+            gpio_cfg: [include::wifi_ant_gpio_t {
+                _bitfield_align_1: [],
+                _bitfield_1: include::wifi_ant_gpio_t::new_bitfield_1(0, 0),
+            }; 4],
+        };
 
-        let count = self.scan_result_count()?;
-        let result = self.scan_results()?;
+        for (cfg, gpio) in config.gpio_cfg.iter_mut().zip(gpios) {
+            if let Some(gpio) = gpio {
+                if gpio >= 64 || ANTENNA_GPIO_MASK & (1 << gpio) == 0 {
+                    return Err(WifiError::InvalidGpio(gpio));
+                }
 
-        Ok((result, count))
+                cfg.set_gpio_select(1);
+                cfg.set_gpio_num(gpio);
+            }
+        }
+
+        esp_wifi_result!(unsafe { include::esp_wifi_set_ant_gpio(&config) })
+    }
+
+    /// Sends a TCP RST for the connections seen before the station reconnects.
+    ///
+    /// After a reconnect the remote side of a TCP connection isn't told that the connection is
+    /// gone, so it appears to hang. When enabled, connections are tracked by snooping the frames
+    /// received by the station and a TCP RST is sent for each of them after
+    /// [`WifiEvent::StaConnected`], as soon as there are free TX slots. Only connections which
+    /// received data while this was enabled are tracked, up to eight at a time.
+    ///
+    /// This is a workaround pending proper integration with the socket layer.
+    pub fn set_tcp_rst_on_reconnect(&mut self, enable: bool) {
+        tcp_rst::set_enabled(enable);
+    }
+
+    /// Joins or forms an ESP-MESH network.
+    ///
+    /// Work in progress: mesh networking isn't supported yet, this always returns
+    /// [`WifiError::NotInitialized`].
+    pub fn enable_mesh(&mut self, config: MeshConfig) -> Result<(), WifiError> {
+        warn!("ESP-MESH is not supported yet, ignoring {:?}", config);
+        Err(WifiError::NotInitialized)
     }
 
     fn scan_result_count(&mut self) -> Result<usize, WifiError> {
@@ -1434,7 +4673,7 @@ impl<MODE: Sealed> WifiRxToken<MODE> {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let mut data = critical_section::with(|cs| {
+        let mut data = blocking_checks::with(|cs| {
             let mut queue = self.mode.data_queue_rx(cs);
 
             unwrap!(
@@ -1505,7 +4744,9 @@ impl<MODE: Sealed> TxToken for WifiTxToken<MODE> {
 // FIXME data here has to be &mut because of `esp_wifi_internal_tx` signature, requiring a *mut ptr to the buffer
 // Casting const to mut is instant UB, even though in reality `esp_wifi_internal_tx` copies the buffer into its own memory and
 // does not modify
-pub(crate) fn esp_wifi_send_data(interface: wifi_interface_t, data: &mut [u8]) {
+//
+// Returns the result of `esp_wifi_internal_tx`.
+pub(crate) fn esp_wifi_send_data(interface: wifi_interface_t, data: &mut [u8]) -> i32 {
     trace!("sending... {} bytes", data.len());
     dump_packet_info(data);
 
@@ -1519,10 +4760,51 @@ pub(crate) fn esp_wifi_send_data(interface: wifi_interface_t, data: &mut [u8]) {
         decrement_inflight_counter();
     } else {
         trace!("esp_wifi_internal_tx ok");
+
+        if interface == wifi_interface_t_WIFI_IF_STA {
+            blocking_checks::with(|cs| {
+                STA_CONNECTION_STATS.borrow_ref_mut(cs).tx_bytes += len as u64
+            });
+        } else {
+            blocking_checks::with(|cs| {
+                AP_TRAFFIC
+                    .borrow_ref_mut(cs)
+                    .record_tx(data.get(0..6), len as usize)
+            });
+        }
+    }
+
+    res
+}
+
+/// Applies the configuration of the interfaces in `conf` to the driver.
+fn apply_config(conf: &Configuration) -> Result<(), WifiError> {
+    match conf {
+        Configuration::None => Err(WifiError::InternalError(
+            InternalWifiError::EspErrInvalidArg,
+        )),
+        Configuration::Client(config) => apply_sta_config(config),
+        Configuration::AccessPoint(config) => apply_ap_config(config),
+        Configuration::Mixed(sta_config, ap_config) => {
+            apply_ap_config(ap_config)?;
+            apply_sta_config(sta_config)
+        }
     }
 }
 
 fn apply_ap_config(config: &AccessPointConfiguration) -> Result<(), WifiError> {
+    validate_ssid(&config.ssid)?;
+
+    if !active_channel_plan().contains(config.channel) {
+        warn!(
+            "Channel {} is not allowed in the configured country",
+            config.channel
+        );
+        return Err(WifiError::InternalError(
+            InternalWifiError::EspErrInvalidArg,
+        ));
+    }
+
     let mut cfg = wifi_config_t {
         ap: wifi_ap_config_t {
             ssid: [0; 32],
@@ -1535,10 +4817,7 @@ fn apply_ap_config(config: &AccessPointConfiguration) -> Result<(), WifiError> {
             beacon_interval: 100,
             pairwise_cipher: wifi_cipher_type_t_WIFI_CIPHER_TYPE_CCMP,
             ftm_responder: false,
-            pmf_cfg: wifi_pmf_config_t {
-                capable: true,
-                required: false,
-            },
+            pmf_cfg: blocking_checks::with(|cs| *AP_PMF_CONFIG.borrow_ref(cs)),
             sae_pwe_h2e: 0,
         },
     };
@@ -1552,35 +4831,54 @@ fn apply_ap_config(config: &AccessPointConfiguration) -> Result<(), WifiError> {
     }
 }
 
+/// Sets how often the driver retries connecting on its own before it reports the failure,
+/// without touching the rest of the station configuration.
+#[cfg(feature = "async")]
+fn set_sta_failure_retry_cnt(count: u8) -> Result<(), WifiError> {
+    unsafe {
+        let mut cfg = MaybeUninit::<wifi_config_t>::uninit();
+        esp_wifi_result!(include::esp_wifi_get_config(
+            wifi_interface_t_WIFI_IF_STA,
+            cfg.as_mut_ptr()
+        ))?;
+
+        let mut cfg = cfg.assume_init();
+        cfg.sta.failure_retry_cnt = count;
+
+        esp_wifi_result!(esp_wifi_set_config(wifi_interface_t_WIFI_IF_STA, &mut cfg))
+    }
+}
+
 fn apply_sta_config(config: &ClientConfiguration) -> Result<(), WifiError> {
+    validate_ssid(&config.ssid)?;
+
+    let esp_wifi_config = crate::esp_wifi_config();
+
     let mut cfg = wifi_config_t {
         sta: wifi_sta_config_t {
             ssid: [0; 32],
             password: [0; 64],
-            scan_method: crate::CONFIG.scan_method,
+            scan_method: esp_wifi_config.scan_method,
             bssid_set: config.bssid.is_some(),
             bssid: match config.bssid {
                 Some(bssid_ref) => bssid_ref,
                 None => [0; 6],
             },
             channel: config.channel.unwrap_or(0),
-            listen_interval: crate::CONFIG.listen_interval,
+            listen_interval: esp_wifi_config.listen_interval,
             sort_method: wifi_sort_method_t_WIFI_CONNECT_AP_BY_SIGNAL,
             threshold: wifi_scan_threshold_t {
                 rssi: -99,
                 authmode: config.auth_method.to_raw(),
             },
-            pmf_cfg: wifi_pmf_config_t {
-                capable: true,
-                required: false,
-            },
+            pmf_cfg: blocking_checks::with(|cs| *STA_PMF_CONFIG.borrow_ref(cs)),
             sae_pwe_h2e: 3,
             _bitfield_align_1: [0; 0],
             _bitfield_1: __BindgenBitfieldUnit::new([0; 4]),
-            failure_retry_cnt: crate::CONFIG.failure_retry_cnt,
+            failure_retry_cnt: esp_wifi_config.failure_retry_cnt,
             _bitfield_align_2: [0; 0],
             _bitfield_2: __BindgenBitfieldUnit::new([0; 4]),
-            sae_pk_mode: 0, // ??
+            sae_pk_mode: blocking_checks::with(|cs| *STA_SAE_PK_MODE.borrow_ref(cs)).to_raw(),
             sae_h2e_identifier: [0; 32],
         },
     };
@@ -1601,7 +4899,7 @@ impl Wifi for WifiController<'_> {
         use embedded_svc::wifi::Capability;
 
         let caps = match self.config {
-            Configuration::None => unreachable!(),
+            Configuration::None => EnumSet::empty(),
             Configuration::Client(_) => enumset::enum_set! { Capability::Client },
             Configuration::AccessPoint(_) => enumset::enum_set! { Capability::AccessPoint },
             Configuration::Mixed(_, _) => {
@@ -1659,62 +4957,112 @@ impl Wifi for WifiController<'_> {
             },
         }
 
-        match conf {
-            Configuration::None => {
-                return Err(WifiError::InternalError(
-                    InternalWifiError::EspErrInvalidArg,
-                ));
-            }
-            Configuration::Client(config) => apply_sta_config(config)?,
-            Configuration::AccessPoint(config) => apply_ap_config(config)?,
-            Configuration::Mixed(sta_config, ap_config) => {
-                apply_ap_config(ap_config)?;
-                apply_sta_config(sta_config)?;
-            }
-        };
-
-        Ok(())
+        apply_config(conf)
     }
 
+    /// Starts the driver, doing nothing if it is already started.
     fn start(&mut self) -> Result<(), Self::Error> {
+        if self.is_started()? {
+            return Ok(());
+        }
+
         crate::wifi::wifi_start()
     }
 
+    /// Stops the driver, doing nothing if it is already stopped.
     fn stop(&mut self) -> Result<(), Self::Error> {
+        if !self.is_started()? {
+            return Ok(());
+        }
+
         esp_wifi_result!(unsafe { esp_wifi_stop() })
     }
 
+    /// Starts connecting the station, see [`WifiEvent::StaConnected`] for when it's done.
+    ///
+    /// If the driver reports [`InternalWifiError::EspErrWifiConn`], its control block of the
+    /// station is in a bad state, which happens e.g. after stopping and starting. The mode and
+    /// the configuration are then applied again and connecting is retried once before the error
+    /// is returned.
     fn connect(&mut self) -> Result<(), Self::Error> {
-        esp_wifi_result!(unsafe { esp_wifi_connect() })
+        self.ensure_sta_mode()?;
+
+        STA_CONNECTING.store(true, Ordering::Relaxed);
+
+        let mut result = esp_wifi_result!(unsafe { esp_wifi_connect() });
+        if let Err(WifiError::InternalError(InternalWifiError::EspErrWifiConn)) = result {
+            warn!("Connecting failed with a bad driver state, applying the configuration again");
+            result = self
+                .reapply_config()
+                .and_then(|_| esp_wifi_result!(unsafe { esp_wifi_connect() }));
+        }
+        if result.is_err() {
+            STA_CONNECTING.store(false, Ordering::Relaxed);
+        }
+
+        result
     }
 
+    /// Disconnects the station, doing nothing if it is neither connected nor connecting.
     fn disconnect(&mut self) -> Result<(), Self::Error> {
-        esp_wifi_result!(unsafe { esp_wifi_disconnect() })
-    }
+        self.ensure_sta_mode()?;
 
-    fn is_started(&self) -> Result<bool, Self::Error> {
-        if matches!(
-            crate::wifi::get_sta_state(),
-            WifiState::StaStarted | WifiState::StaConnected | WifiState::StaDisconnected
-        ) {
-            return Ok(true);
+        if !sta_is_connected_or_connecting() {
+            return Ok(());
         }
-        if matches!(crate::wifi::get_ap_state(), WifiState::ApStarted) {
-            return Ok(true);
+
+        match esp_wifi_result!(unsafe { esp_wifi_disconnect() }) {
+            Err(WifiError::InternalError(
+                InternalWifiError::EspErrWifiNotStarted | InternalWifiError::EspErrWifiNotConnect,
+            )) => Ok(()),
+            result => result,
         }
-        Ok(false)
+    }
+
+    fn is_started(&self) -> Result<bool, Self::Error> {
+        Ok(self.is_sta_started() || self.is_ap_started())
     }
 
     fn is_connected(&self) -> Result<bool, Self::Error> {
         match crate::wifi::get_sta_state() {
             crate::wifi::WifiState::StaConnected => Ok(true),
-            crate::wifi::WifiState::StaDisconnected => Err(WifiError::Disconnected),
-            //FIXME: Should any other enum value trigger an error instead of returning false?
             _ => Ok(false),
         }
     }
 }
 
+fn sta_is_connected_or_connecting() -> bool {
+    get_sta_state() == WifiState::StaConnected || STA_CONNECTING.load(Ordering::Relaxed)
+}
+
+/// Logs the current mode, interface states, last event and last error code of the driver.
+///
+/// Only reads plain state so it can't panic itself while the crate is already panicking.
+#[cfg(feature = "dump-state-on-panic")]
+pub(crate) fn dump_state() {
+    // Don't recurse if something in here panics
+    static DUMPING: AtomicBool = AtomicBool::new(false);
+    if DUMPING.load(Ordering::Relaxed) {
+        return;
+    }
+    DUMPING.store(true, Ordering::Relaxed);
+
+    let mut mode = wifi_mode_t_WIFI_MODE_NULL;
+    let mode = match unsafe { esp_wifi_get_mode(&mut mode) } {
+        0 => WifiMode::try_from(mode).ok(),
+        _ => None,
+    };
+
+    error!(
+        "WiFi state: mode {:?}, STA {:?}, AP {:?}, last event {:?}, last error {}",
+        mode,
+        get_sta_state(),
+        get_ap_state(),
+        WifiEvent::from_i32(state::LAST_EVENT.load(Ordering::Relaxed)),
+        state::LAST_ERROR.load(Ordering::Relaxed)
+    );
+}
+
 fn dump_packet_info(_buffer: &[u8]) {
     #[cfg(feature = "dump-packets")]
     {
@@ -1729,6 +5077,8 @@ macro_rules! esp_wifi_result {
         let result = $value;
         if result != include::ESP_OK as i32 {
             warn!("{} returned an error: {}", stringify!($value), result);
+            #[cfg(feature = "dump-state-on-panic")]
+            $crate::wifi::state::LAST_ERROR.store(result, core::sync::atomic::Ordering::Relaxed);
             Err(WifiError::InternalError(unwrap!(FromPrimitive::from_i32(
                 result
             ))))
@@ -1754,6 +5104,77 @@ pub(crate) mod embassy {
     pub(crate) static STA_RECEIVE_WAKER: AtomicWaker = AtomicWaker::new();
     pub(crate) static STA_LINK_STATE_WAKER: AtomicWaker = AtomicWaker::new();
 
+    /// See [`WifiController::set_link_settle_delay`].
+    pub(crate) static STA_LINK_SETTLE_DELAY: Mutex<RefCell<Duration>> =
+        Mutex::new(RefCell::new(Duration::ZERO));
+
+    /// Runs while the station's link is held down after connecting, `None` once it's up.
+    static STA_LINK_SETTLE_TIMER: Mutex<RefCell<Option<embassy_time::Timer>>> =
+        Mutex::new(RefCell::new(None));
+
+    /// A waker waking [`STA_LINK_STATE_WAKER`], the settle timer is polled with it so
+    /// embassy-net polls the link state again once the timer expired.
+    fn sta_link_state_waker() -> core::task::Waker {
+        use core::task::{RawWaker, RawWakerVTable, Waker};
+
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+
+        unsafe fn clone_waker(_: *const ()) -> RawWaker {
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+
+        unsafe fn wake(_: *const ()) {
+            STA_LINK_STATE_WAKER.wake();
+        }
+
+        unsafe fn drop_waker(_: *const ()) {}
+
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    /// Polls the settle timer, returns whether it's still running.
+    fn poll_sta_link_settle_timer(timer: &mut embassy_time::Timer) -> bool {
+        let waker = sta_link_state_waker();
+        let mut cx = core::task::Context::from_waker(&waker);
+        core::future::Future::poll(core::pin::Pin::new(timer), &mut cx).is_pending()
+    }
+
+    /// Starts holding the station's link down for the settle delay, called when it connected.
+    pub(crate) fn start_sta_link_settle() {
+        blocking_checks::with(|cs| {
+            let delay = *STA_LINK_SETTLE_DELAY.borrow_ref(cs);
+            let mut timer = STA_LINK_SETTLE_TIMER.borrow_ref_mut(cs);
+            *timer = None;
+            if delay.is_zero() {
+                return;
+            }
+
+            let mut settle = embassy_time::Timer::after(embassy_time::Duration::from_micros(
+                delay.as_micros() as u64,
+            ));
+            if poll_sta_link_settle_timer(&mut settle) {
+                *timer = Some(settle);
+            }
+        });
+    }
+
+    /// Stops holding the station's link down, e.g. when it disconnected.
+    pub(crate) fn stop_sta_link_settle(cs: CriticalSection<'_>) {
+        *STA_LINK_SETTLE_TIMER.borrow_ref_mut(cs) = None;
+    }
+
+    /// Whether the station's link is still held down after connecting.
+    pub(crate) fn sta_link_settling() -> bool {
+        blocking_checks::with(|cs| {
+            let mut timer = STA_LINK_SETTLE_TIMER.borrow_ref_mut(cs);
+            let settling = timer.as_mut().map_or(false, poll_sta_link_settle_timer);
+            if !settling {
+                *timer = None;
+            }
+            settling
+        })
+    }
+
     impl<MODE: WifiDeviceMode> RxToken for WifiRxToken<MODE> {
         fn consume<R, F>(self, f: F) -> R
         where
@@ -1792,7 +5213,7 @@ pub(crate) mod embassy {
 
         fn link_state(&mut self, cx: &mut core::task::Context) -> embassy_net_driver::LinkState {
             self.mode.register_link_state_waker(cx);
-            self.mode.link_state()
+            self.mode.link_state(cx)
         }
 
         fn capabilities(&self) -> Capabilities {
@@ -1817,6 +5238,8 @@ mod asynch {
     use core::task::Poll;
 
     use embassy_sync::waitqueue::AtomicWaker;
+    use embassy_time::Timer;
+    use futures_util::Stream;
     use num_traits::FromPrimitive;
 
     use super::*;
@@ -1834,6 +5257,7 @@ mod asynch {
             &mut self,
             config: ScanConfig<'_>,
         ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+            self.ensure_started()?;
             Self::clear_events(WifiEvent::ScanDone);
             esp_wifi_result!(wifi_start_scan(false, config))?;
 
@@ -1849,10 +5273,102 @@ mod asynch {
             Ok((result, count))
         }
 
+        /// Async version of [`WifiController::scan_mixed_sync`].
+        pub async fn scan_mixed<const N: usize>(
+            &mut self,
+            active_channels: &[u8],
+            passive_channels: &[u8],
+            config: ScanConfig<'_>,
+        ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+            let mut merged = heapless::Vec::new();
+            let mut total = 0;
+            for config in mixed_scan_configs(active_channels, passive_channels, config)? {
+                let (results, count) = self.scan_with_config::<N>(config).await?;
+                merge_scan_results(&mut merged, results);
+                total += count;
+            }
+            merged.sort_unstable_by(|a, b| b.signal_strength.cmp(&a.signal_strength));
+
+            Ok((merged, total))
+        }
+
+        /// Async version of [`WifiController::background_scan_sync`].
+        pub async fn background_scan<const N: usize>(
+            &mut self,
+            config: ScanConfig<'_>,
+        ) -> Result<(heapless::Vec<AccessPointInfo, N>, usize), WifiError> {
+            if get_sta_state() != WifiState::StaConnected {
+                return self.scan_with_config(config).await;
+            }
+
+            let mut merged = heapless::Vec::new();
+            let mut total = 0;
+            for (i, config) in background_scan_configs(config).enumerate() {
+                if i > 0 {
+                    Timer::after(embassy_time::Duration::from_micros(
+                        BACKGROUND_SCAN_PAUSE.as_micros() as u64,
+                    ))
+                    .await;
+                }
+
+                let (results, count) = self.scan_with_config::<N>(config).await?;
+                merge_scan_results(&mut merged, results);
+                total += count;
+            }
+            merged.sort_unstable_by(|a, b| b.signal_strength.cmp(&a.signal_strength));
+
+            Ok((merged, total))
+        }
+
+        /// Async version of [`WifiController::scan_for_each_sync`].
+        pub async fn scan_for_each<F>(
+            &mut self,
+            config: ScanConfig<'_>,
+            f: F,
+        ) -> Result<usize, WifiError>
+        where
+            F: FnMut(AccessPointInfo) -> ControlFlow<()>,
+        {
+            self.ensure_started()?;
+            Self::clear_events(WifiEvent::ScanDone);
+            esp_wifi_result!(wifi_start_scan(false, config))?;
+
+            // Prevents memory leak if `scan_for_each`'s future is dropped.
+            let guard = FreeApListOnDrop;
+            WifiEventFuture::new(WifiEvent::ScanDone).await;
+
+            guard.defuse();
+
+            self.scan_results_for_each(f)
+        }
+
+        /// Waits for the scan started with [`WifiController::scan_start_async`] to finish,
+        /// without starting a scan.
+        ///
+        /// [`WifiController::scan_results_available`] keeps returning `true` afterwards until
+        /// the results are collected with [`WifiController::take_scan_results`].
+        pub async fn await_scan_results(&mut self) {
+            if self.scan_results_available() {
+                return;
+            }
+
+            WifiEventFuture::new(WifiEvent::ScanDone).await;
+
+            // The future consumed the event
+            blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).insert(WifiEvent::ScanDone));
+        }
+
         /// Async version of [`embedded_svc::wifi::Wifi`]'s `start` method
-        pub async fn start(&mut self) -> Result<(), WifiError> {
+        ///
+        /// Returns the state of both interfaces once the driver reported the configured ones as
+        /// started.
+        pub async fn start(&mut self) -> Result<ConnectionStatus, WifiError> {
             let mode = WifiMode::try_from(&self.config)?;
 
+            if embedded_svc::wifi::Wifi::is_started(self)? {
+                return Ok(self.status());
+            }
+
             let mut events = enumset::enum_set! {};
             if mode.is_ap() {
                 events |= WifiEvent::ApStart;
@@ -1867,13 +5383,46 @@ mod asynch {
 
             self.wait_for_all_events(events, false).await;
 
-            Ok(())
+            Ok(self.status())
+        }
+
+        /// Waits until all interfaces of the current configuration are up: the station is
+        /// connected and/or the access point is started.
+        ///
+        /// This returns right away for interfaces which are already up and doesn't start or
+        /// connect anything itself. It doesn't wait for an IP configuration, e.g. DHCP.
+        pub async fn wait_for_all_interfaces_up(&mut self) -> Result<(), WifiError> {
+            let mode = WifiMode::try_from(&self.config)?;
+
+            loop {
+                let mut missing = enumset::enum_set! {};
+                if mode.is_sta() && get_sta_state() != WifiState::StaConnected {
+                    missing |= WifiEvent::StaConnected;
+                }
+                if mode.is_ap() && get_ap_state() != WifiState::ApStarted {
+                    missing |= WifiEvent::ApStart;
+                }
+
+                if missing.is_empty() {
+                    return Ok(());
+                }
+
+                // Also wakes up for events which were pending already, the state is checked again
+                MultiWifiEventFuture::new(missing).await;
+            }
         }
 
         /// Async version of [`embedded_svc::wifi::Wifi`]'s `stop` method
-        pub async fn stop(&mut self) -> Result<(), WifiError> {
+        ///
+        /// Returns the state of both interfaces once the driver reported the configured ones as
+        /// stopped, or right away if the driver wasn't started.
+        pub async fn stop(&mut self) -> Result<ConnectionStatus, WifiError> {
             let mode = WifiMode::try_from(&self.config)?;
 
+            if !embedded_svc::wifi::Wifi::is_started(self)? {
+                return Ok(self.status());
+            }
+
             let mut events = enumset::enum_set! {};
             if mode.is_ap() {
                 events |= WifiEvent::ApStop;
@@ -1891,11 +5440,13 @@ mod asynch {
             reset_ap_state();
             reset_sta_state();
 
-            Ok(())
+            Ok(self.status())
         }
 
         /// Async version of [`embedded_svc::wifi::Wifi`]'s `connect` method
         pub async fn connect(&mut self) -> Result<(), WifiError> {
+            self.ensure_sta_mode()?;
+
             Self::clear_events(WifiEvent::StaConnected | WifiEvent::StaDisconnected);
 
             let err = embedded_svc::wifi::Wifi::connect(self).err();
@@ -1910,8 +5461,83 @@ mod asynch {
             }
         }
 
+        /// Async version of [`WifiController::fast_reconnect_sync`], which also waits for the
+        /// fallback connect to finish.
+        pub async fn fast_reconnect(&mut self) -> Result<(), WifiError> {
+            let Some(fast_config) = self.fast_reconnect_config()? else {
+                return self.connect().await;
+            };
+
+            let config = self.config.clone();
+            self.set_configuration(&fast_config)?;
+
+            if self.connect().await.is_ok() {
+                return Ok(());
+            }
+
+            debug!("Fast reconnect failed, falling back to a scan");
+            self.set_configuration(&config)?;
+            self.connect().await
+        }
+
+        /// Like [`Self::connect`], but retries failed attempts up to `failure_retry_cnt` times
+        /// and reports how long connecting took and how many attempts were needed.
+        ///
+        /// The retries are done here instead of by the driver: its own `failure_retry_cnt` is
+        /// set to 0 while this runs, so every attempt is seen and counted, and restored
+        /// afterwards. This helps to tune `failure_retry_cnt` and to diagnose access points
+        /// which are slow to join.
+        ///
+        /// If the future is dropped before it completes, the driver doesn't retry on its own
+        /// until the station configuration is set again.
+        pub async fn connect_with_report(&mut self) -> Result<ConnectReport, WifiError> {
+            let retries = crate::esp_wifi_config().failure_retry_cnt;
+            set_sta_failure_retry_cnt(0)?;
+            let result = self.connect_counting_attempts(retries as u32).await;
+            set_sta_failure_retry_cnt(retries)?;
+
+            result
+        }
+
+        async fn connect_counting_attempts(
+            &mut self,
+            retries: u32,
+        ) -> Result<ConnectReport, WifiError> {
+            let start = crate::timer::get_systimer_count();
+            let mut attempts = 0;
+            let mut last_disconnect_reason = None;
+
+            loop {
+                attempts += 1;
+
+                match self.connect().await {
+                    Ok(()) => {
+                        let elapsed = crate::timer::elapsed_time_since(start);
+                        return Ok(ConnectReport {
+                            elapsed: Duration::from_micros(crate::timer::ticks_to_micros(elapsed)),
+                            attempts,
+                            last_disconnect_reason,
+                        });
+                    }
+                    Err(WifiError::Disconnected) if attempts <= retries => {
+                        let reason = STA_DISCONNECT_REASON.load(Ordering::Relaxed);
+                        debug!("Connection attempt {} failed, reason {}", attempts, reason);
+                        last_disconnect_reason = Some(reason);
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
         /// Async version of [`embedded_svc::wifi::Wifi`]'s `Disconnect` method
         pub async fn disconnect(&mut self) -> Result<(), WifiError> {
+            self.ensure_sta_mode()?;
+
+            // There won't be a `StaDisconnected` event to wait for
+            if !sta_is_connected_or_connecting() {
+                return Ok(());
+            }
+
             Self::clear_events(WifiEvent::StaDisconnected);
             embedded_svc::wifi::Wifi::disconnect(self)?;
             WifiEventFuture::new(WifiEvent::StaDisconnected).await;
@@ -1920,7 +5546,32 @@ mod asynch {
         }
 
         fn clear_events(events: impl Into<EnumSet<WifiEvent>>) {
-            critical_section::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove_all(events.into()));
+            blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove_all(events.into()));
+        }
+
+        /// Monitors the RSSI of the station with the MAC address `mac` connected to the access
+        /// point, polling the list of stations every `interval`.
+        ///
+        /// The first value is yielded right away.
+        pub fn station_rssi_monitor(&self, mac: [u8; 6], interval: Duration) -> StationRssiMonitor {
+            StationRssiMonitor {
+                mac,
+                interval,
+                timer: None,
+            }
+        }
+
+        /// Waits until the driver signalled [`WifiEvent::WifiReady`].
+        ///
+        /// The async version of
+        /// [`wait_for_wifi_ready_sync`](WifiController::wait_for_wifi_ready_sync), the point to
+        /// apply configuration which has to be in place before the first [`start`](Self::start).
+        pub async fn wait_for_wifi_ready(&mut self) {
+            if Self::take_wifi_ready() {
+                return;
+            }
+
+            WifiEventFuture::new(WifiEvent::WifiReady).await
         }
 
         /// Wait for one [`WifiEvent`].
@@ -1941,6 +5592,21 @@ mod asynch {
             MultiWifiEventFuture::new(events).await
         }
 
+        /// Wait for any [`WifiEvent`] and return it, without clearing pending events first.
+        ///
+        /// If several events occurred, the others stay pending and are returned by the next
+        /// calls. This is meant for a central task dispatching on the event type.
+        pub async fn wait_for_any_event(&mut self) -> WifiEvent {
+            let mut fired = MultiWifiEventFuture::new(EnumSet::all()).await;
+            let event = unwrap!(fired.iter().next());
+            fired.remove(event);
+
+            // The future consumed all of them
+            blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).insert_all(fired));
+
+            event
+        }
+
         /// Wait for multiple [`WifiEvent`]s.
         pub async fn wait_for_all_events(
             &mut self,
@@ -2053,6 +5719,43 @@ mod asynch {
         }
     }
 
+    /// Stream of the RSSI of a station connected to the access point, see
+    /// [`WifiController::station_rssi_monitor`].
+    ///
+    /// Yields `None` while the station isn't connected. The stream never ends.
+    pub struct StationRssiMonitor {
+        mac: [u8; 6],
+        interval: Duration,
+        timer: Option<Timer>,
+    }
+
+    impl Stream for StationRssiMonitor {
+        type Item = Option<i8>;
+
+        fn poll_next(
+            mut self: core::pin::Pin<&mut Self>,
+            cx: &mut core::task::Context<'_>,
+        ) -> Poll<Option<Self::Item>> {
+            if let Some(timer) = self.timer.as_mut() {
+                if core::future::Future::poll(core::pin::Pin::new(timer), cx).is_pending() {
+                    return Poll::Pending;
+                }
+            }
+            self.timer = Some(Timer::after(embassy_time::Duration::from_micros(
+                self.interval.as_micros() as u64,
+            )));
+
+            let rssi = ap_station_list().ok().and_then(|stations| {
+                stations
+                    .iter()
+                    .find(|sta| sta.mac == self.mac)
+                    .map(|sta| sta.rssi)
+            });
+
+            Poll::Ready(Some(rssi))
+        }
+    }
+
     pub(crate) struct WifiEventFuture {
         event: WifiEvent,
     }
@@ -2071,7 +5774,7 @@ mod asynch {
             cx: &mut core::task::Context<'_>,
         ) -> Poll<Self::Output> {
             self.event.waker().register(cx.waker());
-            if critical_section::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(self.event)) {
+            if blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).remove(self.event)) {
                 Poll::Ready(())
             } else {
                 Poll::Pending
@@ -2096,7 +5799,7 @@ mod asynch {
             self: core::pin::Pin<&mut Self>,
             cx: &mut core::task::Context<'_>,
         ) -> Poll<Self::Output> {
-            let output = critical_section::with(|cs| {
+            let output = blocking_checks::with(|cs| {
                 let mut events = WIFI_EVENTS.borrow_ref_mut(cs);
                 let active = events.intersection(self.event);
                 events.remove_all(active);
@@ -0,0 +1,335 @@
+//! Persistent storage for the driver's NVS (non-volatile storage).
+//!
+//! The WiFi driver keeps its own state, e.g. the configuration set by the application, in NVS
+//! namespaces when [`crate::EspWifiConfig::nvs_enable`] is set. There is no NVS partition in
+//! esp-wifi, the application provides the storage by implementing [`NvsStorage`] and registering
+//! it with [`set_storage`] before initializing.
+//!
+//! With the `nvs-flash` feature [`FlashNvsStorage`] implements it on top of a flash region, e.g.
+//! using `esp_storage::FlashStorage`.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+
+use crate::binary::c_types;
+use crate::compat::blocking_checks;
+use crate::compat::common::str_from_c;
+
+#[cfg(feature = "nvs-flash")]
+mod flash;
+#[cfg(feature = "nvs-flash")]
+pub use flash::FlashNvsStorage;
+
+/// Longest namespace name accepted by the driver's NVS calls.
+pub const MAX_NAMESPACE_LEN: usize = 15;
+
+/// Number of namespaces which can be open at the same time.
+const MAX_OPEN_NAMESPACES: usize = 4;
+
+// Error codes of ESP-IDF's NVS API, the driver checks for some of them
+const ESP_ERR_NVS_NOT_INITIALIZED: c_types::c_int = 0x1101;
+const ESP_ERR_NVS_NOT_FOUND: c_types::c_int = 0x1102;
+const ESP_ERR_NVS_NOT_ENOUGH_SPACE: c_types::c_int = 0x1105;
+const ESP_ERR_NVS_INVALID_NAME: c_types::c_int = 0x1106;
+const ESP_ERR_NVS_INVALID_HANDLE: c_types::c_int = 0x1107;
+const ESP_ERR_NVS_INVALID_LENGTH: c_types::c_int = 0x110c;
+
+/// Error returned by a [`NvsStorage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NvsError {
+    /// There is no value for the key
+    NotFound,
+    /// The stored value doesn't fit into the buffer, it has the given length
+    BufferTooSmall(usize),
+    /// The stored value doesn't have the length of the requested type, or a name or value is
+    /// too long for the storage
+    InvalidLength,
+    /// There is no space left for the value
+    NotEnoughSpace,
+    /// The underlying storage failed
+    Storage,
+}
+
+impl NvsError {
+    fn to_esp_err(self) -> c_types::c_int {
+        match self {
+            NvsError::NotFound => ESP_ERR_NVS_NOT_FOUND,
+            NvsError::BufferTooSmall(_) | NvsError::InvalidLength => ESP_ERR_NVS_INVALID_LENGTH,
+            NvsError::NotEnoughSpace => ESP_ERR_NVS_NOT_ENOUGH_SPACE,
+            NvsError::Storage => crate::binary::include::ESP_FAIL,
+        }
+    }
+}
+
+/// Key-value storage backing the driver's NVS calls.
+///
+/// Values are blobs stored by namespace and key. The integer accessors mirror the typed NVS
+/// calls of the driver and by default store their value as a little endian blob.
+pub trait NvsStorage: Send {
+    /// Reads the value of `key` into `buf` and returns its length.
+    ///
+    /// Returns [`NvsError::BufferTooSmall`] with the length of the value if it doesn't fit.
+    fn get_blob(&mut self, namespace: &str, key: &str, buf: &mut [u8]) -> Result<usize, NvsError>;
+
+    /// Stores `value` for `key`, replacing the previous value.
+    fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<(), NvsError>;
+
+    /// Removes `key`.
+    fn erase_key(&mut self, namespace: &str, key: &str) -> Result<(), NvsError>;
+
+    /// Makes the changes to `namespace` persistent, for storages which buffer them.
+    fn commit(&mut self, _namespace: &str) -> Result<(), NvsError> {
+        Ok(())
+    }
+
+    fn get_u8(&mut self, namespace: &str, key: &str) -> Result<u8, NvsError> {
+        get_bytes(self, namespace, key).map(u8::from_le_bytes)
+    }
+
+    fn set_u8(&mut self, namespace: &str, key: &str, value: u8) -> Result<(), NvsError> {
+        self.set_blob(namespace, key, &value.to_le_bytes())
+    }
+
+    fn get_i8(&mut self, namespace: &str, key: &str) -> Result<i8, NvsError> {
+        get_bytes(self, namespace, key).map(i8::from_le_bytes)
+    }
+
+    fn set_i8(&mut self, namespace: &str, key: &str, value: i8) -> Result<(), NvsError> {
+        self.set_blob(namespace, key, &value.to_le_bytes())
+    }
+
+    fn get_u16(&mut self, namespace: &str, key: &str) -> Result<u16, NvsError> {
+        get_bytes(self, namespace, key).map(u16::from_le_bytes)
+    }
+
+    fn set_u16(&mut self, namespace: &str, key: &str, value: u16) -> Result<(), NvsError> {
+        self.set_blob(namespace, key, &value.to_le_bytes())
+    }
+}
+
+/// Reads a value which has to be exactly `N` bytes long.
+fn get_bytes<const N: usize, S: NvsStorage + ?Sized>(
+    storage: &mut S,
+    namespace: &str,
+    key: &str,
+) -> Result<[u8; N], NvsError> {
+    let mut bytes = [0u8; N];
+    match storage.get_blob(namespace, key, &mut bytes) {
+        Ok(len) if len == N => Ok(bytes),
+        Ok(_) | Err(NvsError::BufferTooSmall(_)) => Err(NvsError::InvalidLength),
+        Err(err) => Err(err),
+    }
+}
+
+static STORAGE: Mutex<RefCell<Option<&'static mut dyn NvsStorage>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Namespaces opened by the driver, the handle of a namespace is its index plus one.
+static OPEN_NAMESPACES: Mutex<
+    RefCell<[Option<heapless::String<MAX_NAMESPACE_LEN>>; MAX_OPEN_NAMESPACES]>,
+> = Mutex::new(RefCell::new([None, None, None, None]));
+
+/// Registers the storage used for the driver's NVS.
+///
+/// This has to be done before initializing to take effect, the previously registered storage
+/// is returned.
+pub fn set_storage(storage: &'static mut dyn NvsStorage) -> Option<&'static mut dyn NvsStorage> {
+    blocking_checks::with(|cs| STORAGE.borrow_ref_mut(cs).replace(storage))
+}
+
+pub(crate) fn has_storage() -> bool {
+    blocking_checks::with(|cs| STORAGE.borrow_ref(cs).is_some())
+}
+
+/// Runs `f` with the storage and the namespace of `handle`, converting the result to an
+/// ESP-IDF error code.
+///
+/// Flash storages erase and write in their calls, so the storage is taken out of [`STORAGE`]
+/// and `f` runs outside of the critical section. The driver serializes its NVS calls, there is
+/// no other user of the storage while it's taken.
+fn with_namespace(
+    handle: u32,
+    f: impl FnOnce(&mut dyn NvsStorage, &str) -> Result<(), NvsError>,
+) -> c_types::c_int {
+    let taken = blocking_checks::with(|cs| {
+        let namespaces = OPEN_NAMESPACES.borrow_ref(cs);
+        let Some(Some(namespace)) = (handle as usize)
+            .checked_sub(1)
+            .and_then(|index| namespaces.get(index))
+        else {
+            return Err(ESP_ERR_NVS_INVALID_HANDLE);
+        };
+
+        let Some(storage) = STORAGE.borrow_ref_mut(cs).take() else {
+            return Err(ESP_ERR_NVS_NOT_INITIALIZED);
+        };
+
+        Ok((storage, namespace.clone()))
+    });
+
+    let (storage, namespace) = match taken {
+        Ok(taken) => taken,
+        Err(err) => return err,
+    };
+
+    let res = f(storage, &namespace);
+
+    blocking_checks::with(|cs| {
+        // keep a storage registered by `set_storage` in the meantime
+        STORAGE.borrow_ref_mut(cs).get_or_insert(storage);
+    });
+
+    match res {
+        Ok(()) => 0,
+        Err(err) => err.to_esp_err(),
+    }
+}
+
+pub(crate) unsafe fn open(name: *const c_types::c_char, out_handle: *mut u32) -> c_types::c_int {
+    let name = str_from_c(name.cast());
+    let Ok(name) = heapless::String::try_from(name) else {
+        return ESP_ERR_NVS_INVALID_NAME;
+    };
+
+    if !has_storage() {
+        return ESP_ERR_NVS_NOT_INITIALIZED;
+    }
+
+    blocking_checks::with(|cs| {
+        let mut namespaces = OPEN_NAMESPACES.borrow_ref_mut(cs);
+        let Some(index) = namespaces.iter().position(Option::is_none) else {
+            warn!("Too many open NVS namespaces");
+            return ESP_ERR_NVS_NOT_ENOUGH_SPACE;
+        };
+
+        namespaces[index] = Some(name);
+        *out_handle = index as u32 + 1;
+        0
+    })
+}
+
+pub(crate) fn close(handle: u32) {
+    blocking_checks::with(|cs| {
+        let mut namespaces = OPEN_NAMESPACES.borrow_ref_mut(cs);
+        if let Some(namespace) = (handle as usize)
+            .checked_sub(1)
+            .and_then(|index| namespaces.get_mut(index))
+        {
+            *namespace = None;
+        }
+    });
+}
+
+/// Forgets the namespaces the driver didn't close, see [`crate::deinit`].
+pub(crate) fn close_all() {
+    blocking_checks::with(|cs| OPEN_NAMESPACES.borrow_ref_mut(cs).fill(None));
+}
+
+pub(crate) fn commit(handle: u32) -> c_types::c_int {
+    with_namespace(handle, |storage, namespace| storage.commit(namespace))
+}
+
+pub(crate) unsafe fn set_blob(
+    handle: u32,
+    key: *const c_types::c_char,
+    value: &[u8],
+) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        storage.set_blob(namespace, key, value)
+    })
+}
+
+/// Reads a blob, with a null `out_value` only the length is returned through `length`.
+pub(crate) unsafe fn get_blob(
+    handle: u32,
+    key: *const c_types::c_char,
+    out_value: *mut u8,
+    length: *mut usize,
+) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    let buf: &mut [u8] = if out_value.is_null() {
+        &mut []
+    } else {
+        core::slice::from_raw_parts_mut(out_value, *length)
+    };
+
+    with_namespace(handle, |storage, namespace| {
+        match storage.get_blob(namespace, key, buf) {
+            Ok(len) => *length = len,
+            Err(NvsError::BufferTooSmall(len)) if out_value.is_null() => *length = len,
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    })
+}
+
+pub(crate) unsafe fn get_u8(
+    handle: u32,
+    key: *const c_types::c_char,
+    out_value: *mut u8,
+) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        *out_value = storage.get_u8(namespace, key)?;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe fn set_u8(handle: u32, key: *const c_types::c_char, value: u8) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        storage.set_u8(namespace, key, value)
+    })
+}
+
+pub(crate) unsafe fn get_i8(
+    handle: u32,
+    key: *const c_types::c_char,
+    out_value: *mut i8,
+) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        *out_value = storage.get_i8(namespace, key)?;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe fn set_i8(handle: u32, key: *const c_types::c_char, value: i8) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        storage.set_i8(namespace, key, value)
+    })
+}
+
+pub(crate) unsafe fn get_u16(
+    handle: u32,
+    key: *const c_types::c_char,
+    out_value: *mut u16,
+) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        *out_value = storage.get_u16(namespace, key)?;
+        Ok(())
+    })
+}
+
+pub(crate) unsafe fn set_u16(
+    handle: u32,
+    key: *const c_types::c_char,
+    value: u16,
+) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        storage.set_u16(namespace, key, value)
+    })
+}
+
+pub(crate) unsafe fn erase_key(handle: u32, key: *const c_types::c_char) -> c_types::c_int {
+    let key = str_from_c(key.cast());
+    with_namespace(handle, |storage, namespace| {
+        storage.erase_key(namespace, key)
+    })
+}
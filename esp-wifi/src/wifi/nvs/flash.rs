@@ -0,0 +1,192 @@
+//! [`NvsStorage`] on top of a flash region.
+
+use embedded_storage::Storage;
+
+use super::{NvsError, NvsStorage, MAX_NAMESPACE_LEN};
+
+/// Marks a region holding entries, anything else is treated as an empty region.
+const MAGIC: [u8; 4] = *b"EWNV";
+
+/// Size of the header in front of namespace, key and value: their lengths as `u8`, `u8` and
+/// little endian `u16`.
+const ENTRY_HEADER_LEN: usize = 4;
+
+/// Keeps all entries in a region of `N` bytes at `offset` of `S`, e.g. `esp_storage::FlashStorage`.
+///
+/// The region is read into RAM on first use. Changes are only written back by
+/// [`NvsStorage::commit`], which the driver calls after changing its state. Every commit
+/// rewrites the whole region, so it should be a multiple of the flash sector size and must not
+/// overlap anything else, e.g. the application or the `nvs` partition of an ESP-IDF partition
+/// table.
+pub struct FlashNvsStorage<S, const N: usize = 4096> {
+    storage: S,
+    offset: u32,
+    buffer: [u8; N],
+    loaded: bool,
+    dirty: bool,
+}
+
+impl<S, const N: usize> FlashNvsStorage<S, N>
+where
+    S: Storage + Send,
+{
+    pub const fn new(storage: S, offset: u32) -> Self {
+        Self {
+            storage,
+            offset,
+            buffer: [0xff; N],
+            loaded: false,
+            dirty: false,
+        }
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) -> Result<(), NvsError> {
+        self.buffer.fill(0xff);
+        self.buffer[..MAGIC.len()].copy_from_slice(&MAGIC);
+        self.loaded = true;
+        self.dirty = true;
+        self.commit("")
+    }
+
+    fn load(&mut self) -> Result<(), NvsError> {
+        if self.loaded {
+            return Ok(());
+        }
+
+        self.storage
+            .read(self.offset, &mut self.buffer)
+            .map_err(|_| NvsError::Storage)?;
+
+        if self.buffer[..MAGIC.len()] != MAGIC {
+            debug!("No NVS entries in flash");
+            self.buffer.fill(0xff);
+            self.buffer[..MAGIC.len()].copy_from_slice(&MAGIC);
+        }
+
+        self.loaded = true;
+        Ok(())
+    }
+
+    /// Start of the entry after the entry at `pos`, `None` at the end of the entries.
+    fn next_entry(&self, pos: usize) -> Option<usize> {
+        let header = self.buffer.get(pos..pos + ENTRY_HEADER_LEN)?;
+        if header[0] == 0xff {
+            return None;
+        }
+
+        let len = ENTRY_HEADER_LEN
+            + header[0] as usize
+            + header[1] as usize
+            + u16::from_le_bytes([header[2], header[3]]) as usize;
+        Some(pos + len).filter(|end| *end <= N)
+    }
+
+    /// Position and length of the entry for `key`, and the end of all entries.
+    fn find(&self, namespace: &str, key: &str) -> (Option<(usize, usize)>, usize) {
+        let mut found = None;
+        let mut pos = MAGIC.len();
+        while let Some(next) = self.next_entry(pos) {
+            let ns_len = self.buffer[pos] as usize;
+            let key_len = self.buffer[pos + 1] as usize;
+            let name = &self.buffer[pos + ENTRY_HEADER_LEN..];
+            if &name[..ns_len] == namespace.as_bytes()
+                && &name[ns_len..ns_len + key_len] == key.as_bytes()
+            {
+                found = Some((pos, next - pos));
+            }
+            pos = next;
+        }
+
+        (found, pos)
+    }
+
+    fn remove(&mut self, pos: usize, len: usize, end: usize) -> usize {
+        self.buffer.copy_within(pos + len..end, pos);
+        self.buffer[end - len..end].fill(0xff);
+        self.dirty = true;
+        end - len
+    }
+}
+
+impl<S, const N: usize> NvsStorage for FlashNvsStorage<S, N>
+where
+    S: Storage + Send,
+{
+    fn get_blob(&mut self, namespace: &str, key: &str, buf: &mut [u8]) -> Result<usize, NvsError> {
+        self.load()?;
+
+        let (Some((pos, len)), _) = self.find(namespace, key) else {
+            return Err(NvsError::NotFound);
+        };
+
+        let value_start = pos + ENTRY_HEADER_LEN + namespace.len() + key.len();
+        let value = &self.buffer[value_start..pos + len];
+        let Some(buf) = buf.get_mut(..value.len()) else {
+            return Err(NvsError::BufferTooSmall(value.len()));
+        };
+        buf.copy_from_slice(value);
+
+        Ok(value.len())
+    }
+
+    fn set_blob(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<(), NvsError> {
+        self.load()?;
+
+        // A namespace length of 0xff would mark the end of the entries
+        if namespace.len() > MAX_NAMESPACE_LEN {
+            return Err(NvsError::InvalidLength);
+        }
+        let ns_len = namespace.len() as u8;
+        let key_len = u8::try_from(key.len()).map_err(|_| NvsError::InvalidLength)?;
+        let value_len = u16::try_from(value.len()).map_err(|_| NvsError::InvalidLength)?;
+
+        let (existing, mut end) = self.find(namespace, key);
+        let entry_len = ENTRY_HEADER_LEN + namespace.len() + key.len() + value.len();
+        let freed = existing.map_or(0, |(_, len)| len);
+        if end - freed + entry_len > N {
+            return Err(NvsError::NotEnoughSpace);
+        }
+
+        if let Some((pos, len)) = existing {
+            end = self.remove(pos, len, end);
+        }
+
+        let entry = &mut self.buffer[end..end + entry_len];
+        entry[0] = ns_len;
+        entry[1] = key_len;
+        entry[2..4].copy_from_slice(&value_len.to_le_bytes());
+        let (name, data) = entry[ENTRY_HEADER_LEN..].split_at_mut(namespace.len() + key.len());
+        name[..namespace.len()].copy_from_slice(namespace.as_bytes());
+        name[namespace.len()..].copy_from_slice(key.as_bytes());
+        data.copy_from_slice(value);
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    fn erase_key(&mut self, namespace: &str, key: &str) -> Result<(), NvsError> {
+        self.load()?;
+
+        match self.find(namespace, key) {
+            (Some((pos, len)), end) => {
+                self.remove(pos, len, end);
+                Ok(())
+            }
+            (None, _) => Err(NvsError::NotFound),
+        }
+    }
+
+    fn commit(&mut self, _namespace: &str) -> Result<(), NvsError> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        self.storage
+            .write(self.offset, &self.buffer)
+            .map_err(|_| NvsError::Storage)?;
+        self.dirty = false;
+
+        Ok(())
+    }
+}
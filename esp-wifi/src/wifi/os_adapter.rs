@@ -11,14 +11,16 @@ use core::cell::RefCell;
 
 use critical_section::Mutex;
 use enumset::EnumSet;
+use portable_atomic::{AtomicBool, Ordering};
 
 use crate::{
     binary::include::*,
     common_adapter::RADIO_CLOCKS,
     compat::{
+        blocking_checks,
         common::{
-            create_recursive_mutex, create_wifi_queue, lock_mutex, receive_queued, send_queued,
-            str_from_c, thread_sem_get, unlock_mutex,
+            create_recursive_mutex, create_wifi_queue, delete_mutex, lock_mutex, receive_queued,
+            send_queued, str_from_c, thread_sem_get, unlock_mutex,
         },
         malloc::calloc,
         task_runner::spawn_task,
@@ -37,6 +39,19 @@ use super::WifiEvent;
 pub(crate) static WIFI_EVENTS: Mutex<RefCell<EnumSet<WifiEvent>>> =
     Mutex::new(RefCell::new(enumset::enum_set!()));
 
+/// Whether the driver holds a reference to the PHY, which is shared with BLE.
+static WIFI_PHY_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Drops the PHY reference of the driver if it still holds one and gates the WiFi clock, once
+/// the driver is deinitialized.
+pub(crate) unsafe fn release_phy_and_clock() {
+    if WIFI_PHY_ENABLED.swap(false, Ordering::SeqCst) {
+        crate::common_adapter::chip_specific::phy_disable();
+    }
+
+    wifi_clock_disable();
+}
+
 /****************************************************************************
  * Name: wifi_env_is_chip
  *
@@ -212,7 +227,9 @@ pub unsafe extern "C" fn wifi_int_disable(
     wifi_int_mux: *mut crate::binary::c_types::c_void,
 ) -> u32 {
     trace!("wifi_int_disable");
-    crate::wifi::os_adapter::os_adapter_chip_specific::wifi_int_disable(wifi_int_mux)
+    let state = crate::wifi::os_adapter::os_adapter_chip_specific::wifi_int_disable(wifi_int_mux);
+    blocking_checks::critical_section_entered();
+    state
 }
 
 /****************************************************************************
@@ -235,6 +252,7 @@ pub unsafe extern "C" fn wifi_int_restore(
     tmp: u32,
 ) {
     trace!("wifi_int_restore");
+    blocking_checks::critical_section_left();
     crate::wifi::os_adapter::os_adapter_chip_specific::wifi_int_restore(wifi_int_mux, tmp)
 }
 
@@ -321,8 +339,8 @@ pub unsafe extern "C" fn recursive_mutex_create() -> *mut crate::binary::c_types
  *   None
  *
  ****************************************************************************/
-pub unsafe extern "C" fn mutex_delete(_mutex: *mut crate::binary::c_types::c_void) {
-    todo!("mutex_delete")
+pub unsafe extern "C" fn mutex_delete(mutex: *mut crate::binary::c_types::c_void) {
+    delete_mutex(mutex);
 }
 
 /****************************************************************************
@@ -339,6 +357,7 @@ pub unsafe extern "C" fn mutex_delete(_mutex: *mut crate::binary::c_types::c_voi
  *
  ****************************************************************************/
 pub unsafe extern "C" fn mutex_lock(mutex: *mut crate::binary::c_types::c_void) -> i32 {
+    blocking_checks::assert_may_block("mutex_lock");
     lock_mutex(mutex)
 }
 
@@ -417,6 +436,9 @@ pub unsafe extern "C" fn queue_send(
     item: *mut crate::binary::c_types::c_void,
     block_time_tick: u32,
 ) -> i32 {
+    if block_time_tick != 0 {
+        blocking_checks::assert_may_block("queue_send");
+    }
     send_queued(queue, item, block_time_tick)
 }
 
@@ -443,7 +465,7 @@ pub unsafe extern "C" fn queue_send_from_isr(
 ) -> i32 {
     trace!("queue_send_from_isr");
     *(_hptw as *mut u32) = 1;
-    queue_send(queue, item, 1000)
+    send_queued(queue, item, 1000)
 }
 
 /****************************************************************************
@@ -512,6 +534,9 @@ pub unsafe extern "C" fn queue_recv(
     item: *mut crate::binary::c_types::c_void,
     block_time_tick: u32,
 ) -> i32 {
+    if block_time_tick != 0 {
+        blocking_checks::assert_may_block("queue_recv");
+    }
     receive_queued(queue, item, block_time_tick)
 }
 
@@ -639,7 +664,8 @@ pub unsafe extern "C" fn task_create_pinned_to_core(
         core_id
     );
 
-    *(task_handle as *mut usize) = 0; // we will run it in task 0
+    // the task function identifies the task in `task_delete`
+    *(task_handle as *mut usize) = task_func as usize;
 
     if spawn_task(
         task_func,
@@ -700,8 +726,17 @@ pub unsafe extern "C" fn task_create(
  *   None
  *
  ****************************************************************************/
-pub unsafe extern "C" fn task_delete(_task_handle: *mut crate::binary::c_types::c_void) {
-    todo!("task_delete")
+pub unsafe extern "C" fn task_delete(task_handle: *mut crate::binary::c_types::c_void) {
+    trace!("task_delete {:?}", task_handle);
+
+    // A task deleting itself must not return into the driver. C tasks never return to
+    // `run_c_task`, so start the task runner over to have it pick up tasks spawned by a later
+    // initialization.
+    if task_handle.is_null() {
+        crate::preempt::restart_current_task();
+    }
+
+    crate::compat::task_runner::delete_task(task_handle as usize);
 }
 
 /****************************************************************************
@@ -719,6 +754,7 @@ pub unsafe extern "C" fn task_delete(_task_handle: *mut crate::binary::c_types::
  ****************************************************************************/
 pub unsafe extern "C" fn task_delay(tick: u32) {
     trace!("task_delay tick {}", tick);
+    blocking_checks::assert_may_block("task_delay");
     let start_time = crate::timer::get_systimer_count();
     while crate::timer::elapsed_time_since(start_time) < tick as u64 {
         yield_task();
@@ -853,16 +889,65 @@ pub unsafe extern "C" fn event_post(
 
     let event = unwrap!(WifiEvent::from_i32(event_id));
     trace!("EVENT: {:?}", event);
-    critical_section::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).insert(event));
+
+    // Done even if the event is masked, the table would fill up otherwise
+    if event == WifiEvent::ApStadisconnected && !event_data.is_null() {
+        let data = &*(event_data as *const wifi_event_ap_stadisconnected_t);
+        super::remove_ap_station_traffic_stats(&data.mac);
+    }
+
+    if super::is_event_masked(event) {
+        return 0;
+    }
+
+    blocking_checks::with(|cs| WIFI_EVENTS.borrow_ref_mut(cs).insert(event));
+    #[cfg(feature = "diagnostics")]
+    crate::diagnostics::record_event();
+
+    if event == WifiEvent::StaConnected {
+        super::reset_sta_connection_stats();
+
+        if !event_data.is_null() {
+            let data = &*(event_data as *const wifi_event_sta_connected_t);
+            super::set_last_access_point(super::LastAccessPoint {
+                bssid: data.bssid,
+                channel: data.channel,
+            });
+        }
+    }
+
+    if event == WifiEvent::ApStart {
+        super::reset_ap_traffic_stats();
+    }
+
+    if event == WifiEvent::ScanDone {
+        super::vendor_ie::stop_capture();
+    }
+
+    if event == WifiEvent::StaDisconnected && !event_data.is_null() {
+        let data = &*(event_data as *const wifi_event_sta_disconnected_t);
+        super::state::STA_DISCONNECT_REASON
+            .store(data.reason, core::sync::atomic::Ordering::Relaxed);
+    }
 
     super::state::update_state(event);
 
+    if event == WifiEvent::StaConnected {
+        super::tcp_rst::on_sta_connected();
+    }
+
     #[cfg(feature = "async")]
     event.waker().wake();
 
     #[cfg(feature = "embassy-net")]
     match event {
-        WifiEvent::StaConnected | WifiEvent::StaDisconnected => {
+        WifiEvent::StaConnected => {
+            crate::wifi::embassy::start_sta_link_settle();
+            crate::wifi::embassy::STA_LINK_STATE_WAKER.wake();
+        }
+
+        WifiEvent::StaDisconnected => {
+            blocking_checks::with(crate::wifi::embassy::stop_sta_link_settle);
             crate::wifi::embassy::STA_LINK_STATE_WAKER.wake();
         }
 
@@ -892,7 +977,7 @@ pub unsafe extern "C" fn event_post(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn get_free_heap_size() -> u32 {
-    critical_section::with(|cs| crate::HEAP.borrow_ref(cs).free() as u32)
+    blocking_checks::with(|cs| crate::HEAP.borrow_ref(cs).free() as u32)
 }
 
 /****************************************************************************
@@ -970,7 +1055,9 @@ pub unsafe extern "C" fn wifi_apb80m_release() {
 pub unsafe extern "C" fn phy_disable() {
     trace!("phy_disable");
 
-    crate::common_adapter::chip_specific::phy_disable();
+    if WIFI_PHY_ENABLED.swap(false, Ordering::SeqCst) {
+        crate::common_adapter::chip_specific::phy_disable();
+    }
 }
 
 /****************************************************************************
@@ -990,7 +1077,9 @@ pub unsafe extern "C" fn phy_enable() {
     // quite some code needed here
     trace!("phy_enable");
 
-    crate::common_adapter::chip_specific::phy_enable();
+    if !WIFI_PHY_ENABLED.swap(true, Ordering::SeqCst) {
+        crate::common_adapter::chip_specific::phy_enable();
+    }
 }
 
 /****************************************************************************
@@ -1116,12 +1205,12 @@ pub unsafe extern "C" fn esp_timer_get_time() -> i64 {
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_set_i8(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _value: i8,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    value: i8,
 ) -> crate::binary::c_types::c_int {
-    debug!("nvs_set_i8");
-    -1
+    trace!("nvs_set_i8");
+    crate::wifi::nvs::set_i8(handle, key, value)
 }
 
 /****************************************************************************
@@ -1140,11 +1229,12 @@ pub unsafe extern "C" fn nvs_set_i8(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_get_i8(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _out_value: *mut i8,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    out_value: *mut i8,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_get_i8")
+    trace!("nvs_get_i8");
+    crate::wifi::nvs::get_i8(handle, key, out_value)
 }
 
 /****************************************************************************
@@ -1163,11 +1253,12 @@ pub unsafe extern "C" fn nvs_get_i8(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_set_u8(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _value: u8,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    value: u8,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_set_u8")
+    trace!("nvs_set_u8");
+    crate::wifi::nvs::set_u8(handle, key, value)
 }
 
 /****************************************************************************
@@ -1186,11 +1277,12 @@ pub unsafe extern "C" fn nvs_set_u8(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_get_u8(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _out_value: *mut u8,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    out_value: *mut u8,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_get_u8")
+    trace!("nvs_get_u8");
+    crate::wifi::nvs::get_u8(handle, key, out_value)
 }
 
 /****************************************************************************
@@ -1209,11 +1301,12 @@ pub unsafe extern "C" fn nvs_get_u8(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_set_u16(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _value: u16,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    value: u16,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_set_u16")
+    trace!("nvs_set_u16");
+    crate::wifi::nvs::set_u16(handle, key, value)
 }
 
 /****************************************************************************
@@ -1232,11 +1325,12 @@ pub unsafe extern "C" fn nvs_set_u16(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_get_u16(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _out_value: *mut u16,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    out_value: *mut u16,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_get_u16")
+    trace!("nvs_get_u16");
+    crate::wifi::nvs::get_u16(handle, key, out_value)
 }
 
 /****************************************************************************
@@ -1255,11 +1349,12 @@ pub unsafe extern "C" fn nvs_get_u16(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_open(
-    _name: *const crate::binary::c_types::c_char,
+    name: *const crate::binary::c_types::c_char,
     _open_mode: u32,
-    _out_handle: *mut u32,
+    out_handle: *mut u32,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_open")
+    trace!("nvs_open");
+    crate::wifi::nvs::open(name, out_handle)
 }
 
 /****************************************************************************
@@ -1275,19 +1370,27 @@ pub unsafe extern "C" fn nvs_open(
  *   0 if success or -1 if fail
  *
  ****************************************************************************/
-pub unsafe extern "C" fn nvs_close(_handle: u32) {
-    todo!("nvs_close")
+pub unsafe extern "C" fn nvs_close(handle: u32) {
+    trace!("nvs_close");
+    crate::wifi::nvs::close(handle)
 }
 
 /****************************************************************************
  * Name: esp_nvs_commit
  *
  * Description:
- *   This function has no practical effect
+ *   Write the pending changes of the storage data object
+ *
+ * Input Parameters:
+ *   handle - NVS handle
+ *
+ * Returned Value:
+ *   0 if success or -1 if fail
  *
  ****************************************************************************/
-pub unsafe extern "C" fn nvs_commit(_handle: u32) -> crate::binary::c_types::c_int {
-    todo!("nvs_commit")
+pub unsafe extern "C" fn nvs_commit(handle: u32) -> crate::binary::c_types::c_int {
+    trace!("nvs_commit");
+    crate::wifi::nvs::commit(handle)
 }
 
 /****************************************************************************
@@ -1307,12 +1410,14 @@ pub unsafe extern "C" fn nvs_commit(_handle: u32) -> crate::binary::c_types::c_i
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_set_blob(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _value: *const crate::binary::c_types::c_void,
-    _length: usize,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    value: *const crate::binary::c_types::c_void,
+    length: usize,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_set_blob")
+    trace!("nvs_set_blob {}", length);
+    let value = core::slice::from_raw_parts(value as *const u8, length);
+    crate::wifi::nvs::set_blob(handle, key, value)
 }
 
 /****************************************************************************
@@ -1332,19 +1437,20 @@ pub unsafe extern "C" fn nvs_set_blob(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_get_blob(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
-    _out_value: *mut crate::binary::c_types::c_void,
-    _length: *mut usize,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
+    out_value: *mut crate::binary::c_types::c_void,
+    length: *mut usize,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_get_blob")
+    trace!("nvs_get_blob");
+    crate::wifi::nvs::get_blob(handle, key, out_value as *mut u8, length)
 }
 
 /****************************************************************************
  * Name: esp_nvs_erase_key
  *
  * Description:
- *   Read a block of data from file system
+ *   Remove a data index from file system
  *
  * Input Parameters:
  *   handle    - NVS handle
@@ -1355,10 +1461,11 @@ pub unsafe extern "C" fn nvs_get_blob(
  *
  ****************************************************************************/
 pub unsafe extern "C" fn nvs_erase_key(
-    _handle: u32,
-    _key: *const crate::binary::c_types::c_char,
+    handle: u32,
+    key: *const crate::binary::c_types::c_char,
 ) -> crate::binary::c_types::c_int {
-    todo!("nvs_erase_key")
+    trace!("nvs_erase_key");
+    crate::wifi::nvs::erase_key(handle, key)
 }
 
 /****************************************************************************
@@ -1377,14 +1484,9 @@ pub unsafe extern "C" fn nvs_erase_key(
  ****************************************************************************/
 pub unsafe extern "C" fn get_random(buf: *mut u8, len: usize) -> crate::binary::c_types::c_int {
     trace!("get_random");
-    if let Some(ref mut rng) = crate::common_adapter::RANDOM_GENERATOR {
-        let buffer = unsafe { core::slice::from_raw_parts_mut(buf, len) };
-
-        for chunk in buffer.chunks_mut(4) {
-            let bytes = rng.random().to_le_bytes();
-            chunk.copy_from_slice(&bytes[..chunk.len()]);
-        }
+    let buffer = unsafe { core::slice::from_raw_parts_mut(buf, len) };
 
+    if crate::common_adapter::fill_random(buffer) {
         0
     } else {
         -1
@@ -1745,7 +1847,11 @@ pub unsafe extern "C" fn coex_wifi_request(
     trace!("coex_wifi_request");
 
     #[cfg(coex)]
-    return crate::binary::include::coex_wifi_request(event, latency, duration);
+    {
+        let res = crate::binary::include::coex_wifi_request(event, latency, duration);
+        crate::coex::record_wifi_request(res);
+        return res;
+    }
 
     #[cfg(not(coex))]
     0
@@ -1763,7 +1869,10 @@ pub unsafe extern "C" fn coex_wifi_release(event: u32) -> crate::binary::c_types
     trace!("coex_wifi_release");
 
     #[cfg(coex)]
-    return crate::binary::include::coex_wifi_release(event);
+    {
+        crate::coex::record_wifi_release();
+        return crate::binary::include::coex_wifi_release(event);
+    }
 
     #[cfg(not(coex))]
     0
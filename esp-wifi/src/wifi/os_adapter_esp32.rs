@@ -49,8 +49,8 @@ pub(crate) unsafe extern "C" fn set_intr(
     extern "C" {
         fn intr_matrix_set(cpu_no: u32, model_num: u32, intr_num: u32);
     }
-    // Force to bind WiFi interrupt to CPU0
-    intr_matrix_set(0, intr_source, intr_num);
+    // Bind the WiFi interrupt to the core esp-wifi runs on
+    intr_matrix_set(crate::hal::get_core() as u32, intr_source, intr_num);
 }
 
 pub(crate) unsafe extern "C" fn wifi_clock_enable() {
@@ -100,7 +100,7 @@ pub unsafe extern "C" fn set_isr(
     {
         unwrap!(interrupt::enable(
             peripherals::Interrupt::WIFI_MAC,
-            interrupt::Priority::Priority1,
+            unwrap!(crate::wifi::wifi_interrupt_priority()),
         ));
     }
 }
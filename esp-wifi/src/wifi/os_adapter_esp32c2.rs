@@ -104,11 +104,11 @@ pub unsafe extern "C" fn set_isr(
     {
         unwrap!(interrupt::enable(
             Interrupt::WIFI_MAC,
-            interrupt::Priority::Priority1
+            unwrap!(crate::wifi::wifi_interrupt_priority())
         ));
         unwrap!(interrupt::enable(
             Interrupt::WIFI_PWR,
-            interrupt::Priority::Priority1
+            unwrap!(crate::wifi::wifi_interrupt_priority())
         ));
     }
 }
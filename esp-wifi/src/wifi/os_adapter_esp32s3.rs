@@ -34,8 +34,8 @@ pub(crate) unsafe extern "C" fn set_intr(
     extern "C" {
         fn intr_matrix_set(cpu_no: u32, model_num: u32, intr_num: u32);
     }
-    // Force to bind WiFi interrupt to CPU0
-    intr_matrix_set(0, intr_source, intr_num);
+    // Bind the WiFi interrupt to the core esp-wifi runs on
+    intr_matrix_set(crate::hal::get_core() as u32, intr_source, intr_num);
 }
 
 /****************************************************************************
@@ -73,11 +73,11 @@ pub unsafe extern "C" fn set_isr(
     {
         unwrap!(interrupt::enable(
             peripherals::Interrupt::WIFI_MAC,
-            interrupt::Priority::Priority1,
+            unwrap!(crate::wifi::wifi_interrupt_priority()),
         ));
         unwrap!(interrupt::enable(
             peripherals::Interrupt::WIFI_PWR,
-            interrupt::Priority::Priority1,
+            unwrap!(crate::wifi::wifi_interrupt_priority()),
         ));
     }
 }
@@ -1,8 +1,12 @@
 use super::WifiEvent;
 
 use core::sync::atomic::Ordering;
+use portable_atomic::{AtomicBool, AtomicU8};
 use portable_atomic_enum::atomic_enum;
 
+#[cfg(feature = "dump-state-on-panic")]
+use portable_atomic::AtomicI32;
+
 /// Wifi interface state
 #[atomic_enum]
 #[derive(PartialEq, Debug)]
@@ -36,6 +40,20 @@ impl From<WifiEvent> for WifiState {
 pub(crate) static STA_STATE: AtomicWifiState = AtomicWifiState::new(WifiState::Invalid);
 pub(crate) static AP_STATE: AtomicWifiState = AtomicWifiState::new(WifiState::Invalid);
 
+/// Set while a connection attempt of the station is in progress.
+pub(crate) static STA_CONNECTING: AtomicBool = AtomicBool::new(false);
+
+/// The `WIFI_REASON_*` code of the last station disconnect, 0 if there was none.
+pub(crate) static STA_DISCONNECT_REASON: AtomicU8 = AtomicU8::new(0);
+
+/// The last event posted by the driver, or -1 if none was posted yet.
+#[cfg(feature = "dump-state-on-panic")]
+pub(crate) static LAST_EVENT: AtomicI32 = AtomicI32::new(-1);
+
+/// The last error code returned by a driver function.
+#[cfg(feature = "dump-state-on-panic")]
+pub(crate) static LAST_ERROR: AtomicI32 = AtomicI32::new(0);
+
 /// Get the current state of the AP
 pub fn get_ap_state() -> WifiState {
     AP_STATE.load(Ordering::Relaxed)
@@ -47,11 +65,17 @@ pub fn get_sta_state() -> WifiState {
 }
 
 pub(crate) fn update_state(event: WifiEvent) {
+    #[cfg(feature = "dump-state-on-panic")]
+    LAST_EVENT.store(event as i32, Ordering::Relaxed);
+
     match event {
         WifiEvent::StaConnected
         | WifiEvent::StaDisconnected
         | WifiEvent::StaStart
-        | WifiEvent::StaStop => STA_STATE.store(WifiState::from(event), Ordering::Relaxed),
+        | WifiEvent::StaStop => {
+            STA_CONNECTING.store(false, Ordering::Relaxed);
+            STA_STATE.store(WifiState::from(event), Ordering::Relaxed)
+        }
 
         WifiEvent::ApStart | WifiEvent::ApStop => {
             AP_STATE.store(WifiState::from(event), Ordering::Relaxed)
@@ -61,13 +85,12 @@ pub(crate) fn update_state(event: WifiEvent) {
     }
 }
 
-#[cfg(feature = "async")]
 pub(crate) fn reset_ap_state() {
     AP_STATE.store(WifiState::Invalid, Ordering::Relaxed)
 }
 
-#[cfg(feature = "async")]
 pub(crate) fn reset_sta_state() {
+    STA_CONNECTING.store(false, Ordering::Relaxed);
     STA_STATE.store(WifiState::Invalid, Ordering::Relaxed)
 }
 
@@ -0,0 +1,248 @@
+//! Workaround for TCP connections appearing to hang after the station reconnects.
+//!
+//! The driver doesn't tell the remote side of a TCP connection that the connection is gone
+//! when the station reconnects. To get the remote side to drop it, connections are tracked by
+//! snooping received frames and a TCP RST is injected for each of them on `StaConnected`.
+//! The RSTs are sent from the timer task rather than the driver's event callback, as long as
+//! there are free TX slots.
+//!
+//! This will go away once there is proper integration with the socket layer.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use portable_atomic::{AtomicBool, Ordering};
+
+use crate::binary::include::wifi_interface_t_WIFI_IF_STA;
+use crate::compat::blocking_checks;
+
+use super::{esp_wifi_send_data, get_sta_mac, try_reserve_tx_slot};
+
+const MAX_TRACKED_CONNECTIONS: usize = 8;
+
+const ETHERTYPE_IPV4: [u8; 2] = [0x08, 0x00];
+const IP_PROTOCOL_TCP: u8 = 6;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+#[derive(Clone, Copy, PartialEq)]
+struct TcpConnection {
+    peer_mac: [u8; 6],
+    local_ip: [u8; 4],
+    local_port: u16,
+    remote_ip: [u8; 4],
+    remote_port: u16,
+    /// The sequence number the remote side expects next, i.e. the last acknowledgment it sent.
+    seq: u32,
+}
+
+impl TcpConnection {
+    fn same_connection(&self, other: &TcpConnection) -> bool {
+        self.local_ip == other.local_ip
+            && self.local_port == other.local_port
+            && self.remote_ip == other.remote_ip
+            && self.remote_port == other.remote_port
+    }
+}
+
+pub(crate) static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static CONNECTIONS: Mutex<RefCell<heapless::Vec<TcpConnection, MAX_TRACKED_CONNECTIONS>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Connections seen before the last reconnect which still need a TCP RST.
+static PENDING_RESETS: Mutex<RefCell<heapless::Vec<TcpConnection, MAX_TRACKED_CONNECTIONS>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+pub(crate) fn set_enabled(enable: bool) {
+    clear();
+    ENABLED.store(enable, Ordering::Relaxed);
+}
+
+pub(crate) fn clear() {
+    blocking_checks::with(|cs| {
+        CONNECTIONS.borrow_ref_mut(cs).clear();
+        PENDING_RESETS.borrow_ref_mut(cs).clear();
+    });
+}
+
+/// Updates the connection table from a frame received by the station.
+pub(crate) fn snoop_rx(frame: &[u8]) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let Some((connection, flags)) = parse_tcp_frame(frame) else {
+        return;
+    };
+
+    blocking_checks::with(|cs| {
+        let mut connections = CONNECTIONS.borrow_ref_mut(cs);
+        let existing = connections
+            .iter()
+            .position(|c| c.same_connection(&connection));
+
+        if flags & (TCP_FIN | TCP_RST) != 0 {
+            if let Some(index) = existing {
+                connections.swap_remove(index);
+            }
+        } else if flags & TCP_ACK != 0 {
+            match existing {
+                Some(index) => connections[index] = connection,
+                None => {
+                    // Forget about the oldest connection if the table is full
+                    if connections.is_full() {
+                        connections.remove(0);
+                    }
+                    unwrap!(connections.push(connection).ok());
+                }
+            }
+        }
+    });
+}
+
+/// Schedules a TCP RST for all tracked connections, called when the station (re)connected.
+pub(crate) fn on_sta_connected() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    blocking_checks::with(|cs| {
+        let connections = core::mem::take(&mut *CONNECTIONS.borrow_ref_mut(cs));
+        let mut pending = PENDING_RESETS.borrow_ref_mut(cs);
+        for connection in connections {
+            if pending.push(connection).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Sends the TCP RSTs scheduled by [`on_sta_connected`], called from the timer task.
+///
+/// Stops when all TX slots are in use, the remaining RSTs are sent on the next call.
+pub(crate) fn send_pending_resets() {
+    let mut mac = None;
+
+    loop {
+        let Some(connection) = blocking_checks::with(|cs| PENDING_RESETS.borrow_ref_mut(cs).pop())
+        else {
+            return;
+        };
+
+        if !try_reserve_tx_slot() {
+            debug!("No TX slot for the TCP RST, trying again later");
+            blocking_checks::with(|cs| {
+                PENDING_RESETS.borrow_ref_mut(cs).push(connection).ok();
+            });
+            return;
+        }
+
+        let mac = mac.get_or_insert_with(|| {
+            let mut mac = [0u8; 6];
+            get_sta_mac(&mut mac);
+            mac
+        });
+
+        debug!(
+            "Sending TCP RST to {:?}:{}",
+            connection.remote_ip, connection.remote_port
+        );
+
+        let mut frame = rst_frame(&connection, mac);
+        let res = esp_wifi_send_data(wifi_interface_t_WIFI_IF_STA, &mut frame);
+        if res != 0 {
+            warn!(
+                "Sending the TCP RST to {:?}:{} failed: {}",
+                connection.remote_ip, connection.remote_port, res
+            );
+        }
+    }
+}
+
+/// Extracts the connection and the TCP flags from an Ethernet frame sent by the remote side.
+fn parse_tcp_frame(frame: &[u8]) -> Option<(TcpConnection, u8)> {
+    if frame.get(12..14)? != ETHERTYPE_IPV4 {
+        return None;
+    }
+
+    let ip = frame.get(14..)?;
+    let ihl = (*ip.first()? & 0x0f) as usize * 4;
+    if *ip.get(9)? != IP_PROTOCOL_TCP || ihl < 20 {
+        return None;
+    }
+
+    let tcp = ip.get(ihl..)?;
+    if tcp.len() < 20 {
+        return None;
+    }
+
+    let connection = TcpConnection {
+        peer_mac: unwrap!(frame[6..12].try_into()),
+        local_ip: unwrap!(ip[16..20].try_into()),
+        local_port: u16::from_be_bytes([tcp[2], tcp[3]]),
+        remote_ip: unwrap!(ip[12..16].try_into()),
+        remote_port: u16::from_be_bytes([tcp[0], tcp[1]]),
+        seq: u32::from_be_bytes(unwrap!(tcp[8..12].try_into())),
+    };
+
+    Some((connection, tcp[13]))
+}
+
+/// Builds an Ethernet frame containing a TCP RST for the given connection.
+fn rst_frame(connection: &TcpConnection, mac: &[u8; 6]) -> [u8; 54] {
+    let mut frame = [0u8; 54];
+
+    // Ethernet header
+    frame[0..6].copy_from_slice(&connection.peer_mac);
+    frame[6..12].copy_from_slice(mac);
+    frame[12..14].copy_from_slice(&ETHERTYPE_IPV4);
+
+    // IPv4 header without options, don't fragment
+    let ip = &mut frame[14..34];
+    ip[0] = 0x45;
+    ip[2..4].copy_from_slice(&40u16.to_be_bytes());
+    ip[6] = 0x40;
+    ip[8] = 64;
+    ip[9] = IP_PROTOCOL_TCP;
+    ip[12..16].copy_from_slice(&connection.local_ip);
+    ip[16..20].copy_from_slice(&connection.remote_ip);
+    let checksum = checksum(0, ip);
+    ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    // TCP header without options
+    let tcp = &mut frame[34..54];
+    tcp[0..2].copy_from_slice(&connection.local_port.to_be_bytes());
+    tcp[2..4].copy_from_slice(&connection.remote_port.to_be_bytes());
+    tcp[4..8].copy_from_slice(&connection.seq.to_be_bytes());
+    tcp[12] = 5 << 4;
+    tcp[13] = TCP_RST;
+
+    let mut pseudo_header = [0u8; 12];
+    pseudo_header[0..4].copy_from_slice(&connection.local_ip);
+    pseudo_header[4..8].copy_from_slice(&connection.remote_ip);
+    pseudo_header[9] = IP_PROTOCOL_TCP;
+    pseudo_header[10..12].copy_from_slice(&20u16.to_be_bytes());
+    let checksum = checksum(sum_words(0, &pseudo_header), tcp);
+    tcp[16..18].copy_from_slice(&checksum.to_be_bytes());
+
+    frame
+}
+
+fn sum_words(mut sum: u32, data: &[u8]) -> u32 {
+    for word in data.chunks(2) {
+        sum += u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32;
+    }
+    sum
+}
+
+/// The internet checksum of `data`, `sum` being the partial sum of a pseudo header.
+fn checksum(sum: u32, data: &[u8]) -> u16 {
+    let mut sum = sum_words(sum, data);
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -8,6 +8,8 @@ use smoltcp::{
     wire::{EthernetAddress, HardwareAddress},
 };
 
+use embedded_svc::wifi::AccessPointInfo;
+
 use crate::current_millis;
 use crate::EspWifiInitialization;
 
@@ -95,3 +97,40 @@ pub fn create_ap_sta_network_interface<'a, 'd>(
         sta_socket_set,
     })
 }
+
+/// Formats a MAC address or BSSID as `aa:bb:cc:dd:ee:ff`, e.g. for logging.
+pub fn format_mac(mac: &[u8; 6]) -> heapless::String<17> {
+    use core::fmt::Write;
+
+    let mut formatted = heapless::String::new();
+    unwrap!(write!(
+        formatted,
+        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    )
+    .ok());
+
+    formatted
+}
+
+/// Sorts scan results alphabetically by SSID, ignoring ASCII case, e.g. for a network picker.
+///
+/// Access points with the same SSID are ordered by signal strength, strongest first, and then
+/// by BSSID, so the order doesn't change between scans of the same networks.
+pub fn sort_by_ssid(access_points: &mut [AccessPointInfo]) {
+    access_points.sort_unstable_by(|a, b| {
+        let ssid = |ap: &AccessPointInfo| {
+            ap.ssid
+                .as_bytes()
+                .iter()
+                .map(u8::to_ascii_lowercase)
+                .collect::<heapless::Vec<u8, 32>>()
+        };
+
+        ssid(a)
+            .cmp(&ssid(b))
+            .then_with(|| a.ssid.cmp(&b.ssid))
+            .then_with(|| b.signal_strength.cmp(&a.signal_strength))
+            .then_with(|| a.bssid.cmp(&b.bssid))
+    });
+}
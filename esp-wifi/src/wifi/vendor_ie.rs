@@ -0,0 +1,172 @@
+//! Vendor specific information elements of the access points found by a scan.
+//!
+//! The driver doesn't keep the IEs of scanned access points, but it reports the vendor specific
+//! IEs of received beacons and probe responses through a callback. They are collected while a
+//! scan is running and matched to the scan results by BSSID.
+//!
+//! Parsers registered for an element ID are run over the captured IEs when the results are
+//! fetched.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use portable_atomic::{AtomicBool, Ordering};
+
+use super::CustomIeData;
+use crate::binary::{
+    c_types,
+    include::{
+        esp_wifi_set_vendor_ie_cb, vendor_ie_data_t, wifi_vendor_ie_type_t,
+        wifi_vendor_ie_type_t_WIFI_VND_IE_TYPE_BEACON,
+        wifi_vendor_ie_type_t_WIFI_VND_IE_TYPE_PROBE_RESP,
+    },
+};
+use crate::compat::blocking_checks;
+
+/// Size of the buffer holding the captured IEs, each prefixed by the BSSID it was received from.
+const CAPTURE_BUFFER_SIZE: usize = 2048;
+
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+static CAPTURED: Mutex<RefCell<heapless::Vec<u8, CAPTURE_BUFFER_SIZE>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Forgets the IEs of the previous scan and starts collecting new ones.
+pub(crate) fn start_capture() {
+    blocking_checks::with(|cs| CAPTURED.borrow_ref_mut(cs).clear());
+    CAPTURING.store(true, Ordering::Relaxed);
+
+    let res = unsafe { esp_wifi_set_vendor_ie_cb(Some(vendor_ie_cb), core::ptr::null_mut()) };
+    if res != 0 {
+        warn!("esp_wifi_set_vendor_ie_cb {}", res);
+    }
+}
+
+pub(crate) fn stop_capture() {
+    CAPTURING.store(false, Ordering::Relaxed);
+}
+
+unsafe extern "C" fn vendor_ie_cb(
+    _ctx: *mut c_types::c_void,
+    type_: wifi_vendor_ie_type_t,
+    sa: *const u8,
+    vnd_ie: *const vendor_ie_data_t,
+    _rssi: c_types::c_int,
+) {
+    if !CAPTURING.load(Ordering::Relaxed)
+        || (type_ != wifi_vendor_ie_type_t_WIFI_VND_IE_TYPE_BEACON
+            && type_ != wifi_vendor_ie_type_t_WIFI_VND_IE_TYPE_PROBE_RESP)
+        || sa.is_null()
+        || vnd_ie.is_null()
+    {
+        return;
+    }
+
+    let bssid = core::slice::from_raw_parts(sa, 6);
+    let ie = core::slice::from_raw_parts(vnd_ie as *const u8, 2 + (*vnd_ie).length as usize);
+
+    blocking_checks::with(|cs| {
+        let mut captured = CAPTURED.borrow_ref_mut(cs);
+
+        // Every beacon repeats the same IEs
+        if entries(&captured).any(|(b, i)| b == bssid && i == ie) {
+            return;
+        }
+
+        if captured.capacity() - captured.len() < bssid.len() + ie.len() {
+            trace!("No space left for vendor IE");
+            return;
+        }
+
+        unwrap!(captured.extend_from_slice(bssid).ok());
+        unwrap!(captured.extend_from_slice(ie).ok());
+    });
+}
+
+/// Iterates over the `(BSSID, IE)` pairs in the capture buffer.
+fn entries(buffer: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut rest = buffer;
+    core::iter::from_fn(move || {
+        let len = 6 + 2 + *rest.get(7)? as usize;
+        let (entry, tail) = rest.split_at(len);
+        rest = tail;
+        Some(entry.split_at(6))
+    })
+}
+
+/// Appends the IEs captured for `bssid` to `out`, as many as fit.
+pub(crate) fn copy_ies<const N: usize>(bssid: &[u8; 6], out: &mut heapless::Vec<u8, N>) {
+    blocking_checks::with(|cs| {
+        let captured = CAPTURED.borrow_ref(cs);
+        for (_, ie) in entries(&captured).filter(|(b, _)| *b == bssid) {
+            if out.extend_from_slice(ie).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Decodes the body of an IE, see [`super::WifiController::register_ie_parser`].
+pub type IeParser = fn(&[u8]) -> Option<CustomIeData>;
+
+/// Maximum number of IE parsers which can be registered at the same time.
+pub const MAX_IE_PARSERS: usize = 4;
+
+static PARSERS: Mutex<RefCell<heapless::Vec<(u8, IeParser), MAX_IE_PARSERS>>> =
+    Mutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Registers `parser` for `element_id`, replacing the parser previously registered for it.
+///
+/// Returns `false` if all slots are taken by other element IDs.
+pub(crate) fn register_parser(element_id: u8, parser: IeParser) -> bool {
+    blocking_checks::with(|cs| {
+        let mut parsers = PARSERS.borrow_ref_mut(cs);
+        match parsers.iter_mut().find(|(id, _)| *id == element_id) {
+            Some(entry) => {
+                entry.1 = parser;
+                true
+            }
+            None => parsers.push((element_id, parser)).is_ok(),
+        }
+    })
+}
+
+pub(crate) fn unregister_parser(element_id: u8) {
+    blocking_checks::with(|cs| {
+        PARSERS
+            .borrow_ref_mut(cs)
+            .retain(|(id, _)| *id != element_id)
+    });
+}
+
+/// Runs the registered parsers over the IEs captured for `bssid` and appends the decoded data
+/// to `out`, as many as fit.
+pub(crate) fn parse_ies<const N: usize>(
+    bssid: &[u8; 6],
+    out: &mut heapless::Vec<(u8, CustomIeData), N>,
+) {
+    // The parsers are user code, don't run them inside the critical section
+    let parsers = blocking_checks::with(|cs| PARSERS.borrow_ref(cs).clone());
+    if parsers.is_empty() {
+        return;
+    }
+
+    let mut ies = heapless::Vec::<u8, 512>::new();
+    copy_ies(bssid, &mut ies);
+
+    let mut rest = &ies[..];
+    while let [element_id, len, tail @ ..] = rest {
+        let Some(body) = tail.get(..*len as usize) else {
+            break;
+        };
+        rest = &tail[*len as usize..];
+
+        for (_, parser) in parsers.iter().filter(|(id, _)| id == element_id) {
+            if let Some(data) = parser(body) {
+                if out.push((*element_id, data)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
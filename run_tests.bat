@@ -13,64 +13,122 @@ cargo +esp build --release --example test_esp_now --target xtensa-esp32-none-elf
 cargo +esp build --release --example open_access_point --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi,esp-now,utils,smoltcp,tcp
 cargo +esp build --release --example test_connect --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi,esp-now,utils,smoltcp,tcp
 cargo +esp build --release --example test_ble --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,ble
+cargo +esp build --release --example test_reinit --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example non_utf8_access_point --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example test_non_utf8_ssid --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example test_wrong_mode --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi,async
+cargo +esp build --release --example test_remove_ble --target xtensa-esp32-none-elf --features esp32,esp32-hal/default,esp32-hal/embassy-time-timg0,esp32-hal/embassy-executor-thread,wifi,ble,coex
 copy ..\target\xtensa-esp32-none-elf\release\examples\esp_now_broadcaster ..\tmp\esp32
 copy ..\target\xtensa-esp32-none-elf\release\examples\test_esp_now ..\tmp\esp32
 copy ..\target\xtensa-esp32-none-elf\release\examples\open_access_point ..\tmp\esp32
 copy ..\target\xtensa-esp32-none-elf\release\examples\test_connect ..\tmp\esp32
 copy ..\target\xtensa-esp32-none-elf\release\examples\test_ble ..\tmp\esp32
+copy ..\target\xtensa-esp32-none-elf\release\examples\test_reinit ..\tmp\esp32
+copy ..\target\xtensa-esp32-none-elf\release\examples\non_utf8_access_point ..\tmp\esp32
+copy ..\target\xtensa-esp32-none-elf\release\examples\test_non_utf8_ssid ..\tmp\esp32
+copy ..\target\xtensa-esp32-none-elf\release\examples\test_wrong_mode ..\tmp\esp32
+copy ..\target\xtensa-esp32-none-elf\release\examples\test_remove_ble ..\tmp\esp32
 
 cargo +esp build --release --example esp_now_broadcaster --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi,esp-now
 cargo +esp build --release --example test_esp_now --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi,esp-now
 cargo +esp build --release --example open_access_point --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi,esp-now,utils,smoltcp,tcp
 cargo +esp build --release --example test_connect --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi,esp-now,utils,smoltcp,tcp
+cargo +esp build --release --example test_reinit --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example non_utf8_access_point --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example test_non_utf8_ssid --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example test_wrong_mode --target xtensa-esp32s2-none-elf --features esp32s2,esp32s2-hal/default,esp32s2-hal/embassy-time-timg0,esp32s2-hal/embassy-executor-thread,wifi,async
 copy ..\target\xtensa-esp32s2-none-elf\release\examples\esp_now_broadcaster ..\tmp\esp32s2
 copy ..\target\xtensa-esp32s2-none-elf\release\examples\test_esp_now ..\tmp\esp32s2
 copy ..\target\xtensa-esp32s2-none-elf\release\examples\open_access_point ..\tmp\esp32s2
 copy ..\target\xtensa-esp32s2-none-elf\release\examples\test_connect ..\tmp\esp32s2
+copy ..\target\xtensa-esp32s2-none-elf\release\examples\test_reinit ..\tmp\esp32s2
+copy ..\target\xtensa-esp32s2-none-elf\release\examples\non_utf8_access_point ..\tmp\esp32s2
+copy ..\target\xtensa-esp32s2-none-elf\release\examples\test_non_utf8_ssid ..\tmp\esp32s2
+copy ..\target\xtensa-esp32s2-none-elf\release\examples\test_wrong_mode ..\tmp\esp32s2
 
 cargo +esp build --release --example esp_now_broadcaster --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi,esp-now
 cargo +esp build --release --example test_esp_now --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi,esp-now
 cargo +esp build --release --example open_access_point --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi,esp-now,utils,smoltcp,tcp
 cargo +esp build --release --example test_connect --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi,esp-now,utils,smoltcp,tcp
 cargo +esp build --release --example test_ble --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,ble
+cargo +esp build --release --example test_reinit --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example non_utf8_access_point --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example test_non_utf8_ssid --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi
+cargo +esp build --release --example test_wrong_mode --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi,async
+cargo +esp build --release --example test_remove_ble --target xtensa-esp32s3-none-elf --features esp32s3,esp32s3-hal/default,esp32s3-hal/embassy-time-timg0,esp32s3-hal/embassy-executor-thread,wifi,ble,coex
 copy ..\target\xtensa-esp32s3-none-elf\release\examples\esp_now_broadcaster ..\tmp\esp32s3
 copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_esp_now ..\tmp\esp32s3
 copy ..\target\xtensa-esp32s3-none-elf\release\examples\open_access_point ..\tmp\esp32s3
 copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_connect ..\tmp\esp32s3
 copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_ble ..\tmp\esp32s3
+copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_reinit ..\tmp\esp32s3
+copy ..\target\xtensa-esp32s3-none-elf\release\examples\non_utf8_access_point ..\tmp\esp32s3
+copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_non_utf8_ssid ..\tmp\esp32s3
+copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_wrong_mode ..\tmp\esp32s3
+copy ..\target\xtensa-esp32s3-none-elf\release\examples\test_remove_ble ..\tmp\esp32s3
 
 cargo +nightly build --release --example esp_now_broadcaster --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi,esp-now
 cargo +nightly build --release --example test_esp_now --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi,esp-now
 cargo +nightly build --release --example open_access_point --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi,esp-now,utils,smoltcp,tcp
 cargo +nightly build --release --example test_connect --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi,esp-now,utils,smoltcp,tcp
 cargo +nightly build --release --example test_ble --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,ble
+cargo +nightly build --release --example test_reinit --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example non_utf8_access_point --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example test_non_utf8_ssid --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example test_wrong_mode --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi,async
+cargo +nightly build --release --example test_remove_ble --target riscv32imc-unknown-none-elf --features esp32c2,esp32c2-hal/default,esp32c2-hal/embassy-time-timg0,wifi,ble,coex
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\esp_now_broadcaster ..\tmp\esp32c2
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_esp_now ..\tmp\esp32c2
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\open_access_point ..\tmp\esp32c2
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_connect ..\tmp\esp32c2
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_ble ..\tmp\esp32c2
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_reinit ..\tmp\esp32c2
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\non_utf8_access_point ..\tmp\esp32c2
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_non_utf8_ssid ..\tmp\esp32c2
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_wrong_mode ..\tmp\esp32c2
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_remove_ble ..\tmp\esp32c2
 
 cargo +nightly build --release --example esp_now_broadcaster --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi,esp-now
 cargo +nightly build --release --example test_esp_now --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi,esp-now
 cargo +nightly build --release --example open_access_point --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi,esp-now,utils,smoltcp,tcp
 cargo +nightly build --release --example test_connect --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi,esp-now,utils,smoltcp,tcp
 cargo +nightly build --release --example test_ble --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,ble
+cargo +nightly build --release --example test_reinit --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example non_utf8_access_point --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example test_non_utf8_ssid --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example test_wrong_mode --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi,async
+cargo +nightly build --release --example test_remove_ble --target riscv32imc-unknown-none-elf --features esp32c3,esp32c3-hal/default,esp32c3-hal/embassy-time-timg0,wifi,ble,coex
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\esp_now_broadcaster ..\tmp\esp32c3
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_esp_now ..\tmp\esp32c3
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\open_access_point ..\tmp\esp32c3
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_connect ..\tmp\esp32c3
 copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_ble ..\tmp\esp32c3
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_reinit ..\tmp\esp32c3
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\non_utf8_access_point ..\tmp\esp32c3
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_non_utf8_ssid ..\tmp\esp32c3
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_wrong_mode ..\tmp\esp32c3
+copy ..\target\riscv32imc-unknown-none-elf\release\examples\test_remove_ble ..\tmp\esp32c3
 
 cargo +nightly build --release --example esp_now_broadcaster --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi,esp-now
 cargo +nightly build --release --example test_esp_now --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi,esp-now
 cargo +nightly build --release --example open_access_point --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi,esp-now,utils,smoltcp,tcp
 cargo +nightly build --release --example test_connect --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi,esp-now,utils,smoltcp,tcp
 cargo +nightly build --release --example test_ble --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,ble
+cargo +nightly build --release --example test_reinit --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example non_utf8_access_point --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example test_non_utf8_ssid --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi
+cargo +nightly build --release --example test_wrong_mode --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi,async
+cargo +nightly build --release --example test_remove_ble --target riscv32imac-unknown-none-elf --features esp32c6,esp32c6-hal/default,esp32c6-hal/embassy-time-timg0,wifi,ble,coex
 copy ..\target\riscv32imac-unknown-none-elf\release\examples\esp_now_broadcaster ..\tmp\esp32c6
 copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_esp_now ..\tmp\esp32c6
 copy ..\target\riscv32imac-unknown-none-elf\release\examples\open_access_point ..\tmp\esp32c6
 copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_connect ..\tmp\esp32c6
 copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_ble ..\tmp\esp32c6
+copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_reinit ..\tmp\esp32c6
+copy ..\target\riscv32imac-unknown-none-elf\release\examples\non_utf8_access_point ..\tmp\esp32c6
+copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_non_utf8_ssid ..\tmp\esp32c6
+copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_wrong_mode ..\tmp\esp32c6
+copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_remove_ble ..\tmp\esp32c6
 
 cargo +nightly build --release --example test_ble --target riscv32imac-unknown-none-elf --no-default-features --features esp32h2,esp32h2-hal/default,esp32h2-hal/embassy-time-timg0,ble
 copy ..\target\riscv32imac-unknown-none-elf\release\examples\test_ble ..\tmp\esp32h2